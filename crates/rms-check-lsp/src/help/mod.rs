@@ -1,82 +1,61 @@
-mod en;
-
+use lazy_static::lazy_static;
 use lsp_types::{
     Documentation, ParameterInformation, ParameterLabel, SignatureHelp, SignatureInformation,
 };
-use rms_check::{ArgType, AtomKind, ByteIndex, Parser, RMSFile, TOKENS};
+use rms_check::{ArgType, AtomKind, ByteIndex, Parser, RMSFile, TokenType, TOKENS};
+use std::collections::HashMap;
 
-/// Helper struct to create SignatureInformation structures.
-#[derive(Debug, Clone)]
-struct SignatureBuilder {
-    name: &'static str,
-    description: Option<String>,
-    args: Vec<ParameterInformation>,
+lazy_static! {
+    /// Signatures for all known tokens.
+    static ref SIGNATURES: HashMap<&'static str, SignatureInformation> = TOKENS
+        .values()
+        .map(|token_type| (token_type.name, build_signature(token_type)))
+        .collect();
 }
 
-impl SignatureBuilder {
-    /// Create a new signature builder for the command with the given name.
-    fn new(name: &'static str) -> Self {
-        Self {
-            name,
-            description: None,
-            args: vec![],
-        }
+/// Get the name of an argument type, for use in signature labels.
+const fn arg_type_name(arg_type: ArgType) -> &'static str {
+    match arg_type {
+        ArgType::Word => "Word",
+        ArgType::Number => "Number",
+        ArgType::Token => "Token",
+        ArgType::OptionalToken => "OptionalToken",
+        ArgType::Filename => "Filename",
     }
+}
 
-    /// Set the description of this command.
-    fn description(&mut self, description: &str) -> &mut Self {
-        self.description = Some(description.to_string());
-        self
-    }
-
-    /// Add an argument to the command.
-    fn arg(&mut self, name: &str, documentation: &str) -> &mut Self {
-        let mut label = name.to_string();
-        if let Some(ty) = TOKENS.get(self.name) {
-            if let Some(arg) = ty.arg_type(self.args.len() as u8) {
-                label += &format!(
-                    ":{}",
-                    match arg {
-                        ArgType::Word => "Word",
-                        ArgType::Number => "Number",
-                        ArgType::Token => "Token",
-                        ArgType::OptionalToken => "OptionalToken",
-                        ArgType::Filename => "Filename",
-                    }
-                );
-            }
-        }
-
-        self.args.push(ParameterInformation {
-            label: ParameterLabel::Simple(label),
-            documentation: Some(Documentation::String(documentation.to_string())),
+/// Create the SignatureInformation for a token type.
+fn build_signature(token_type: &TokenType) -> SignatureInformation {
+    let mut label = token_type.name.to_string();
+    let mut parameters = vec![];
+    for arg in token_type.args() {
+        let type_name = arg_type_name(arg.arg_type());
+        let arg_label = match arg.name() {
+            Some(name) => format!("{}:{}", name, type_name),
+            None => type_name.to_string(),
+        };
+        label += &format!(" {}", arg_label);
+        parameters.push(ParameterInformation {
+            label: ParameterLabel::Simple(arg_label),
+            documentation: arg
+                .documentation()
+                .map(|doc| Documentation::String(doc.to_string())),
         });
-        self
     }
 
-    /// Consume the builder and create a SignatureInformation instance.
-    #[must_use]
-    fn build(self) -> SignatureInformation {
-        let mut label = self.name.to_string();
-        for arg in &self.args {
-            if let ParameterLabel::Simple(arg_name) = &arg.label {
-                label += &format!(" {}", arg_name);
-            } else {
-                unreachable!("Expected ParameterLabel::Simple(), got {:?}", arg.label);
-            }
-        }
-        SignatureInformation {
-            label,
-            documentation: self.description.map(Documentation::String),
-            active_parameter: None,
-            parameters: Some(self.args),
-        }
+    SignatureInformation {
+        label,
+        documentation: token_type
+            .description()
+            .map(|description| Documentation::String(description.to_string())),
+        active_parameter: None,
+        parameters: Some(parameters),
     }
 }
 
 /// Get the language server SignatureInformation for a given command name.
 fn get_signature(command_name: &str) -> Option<&SignatureInformation> {
-    en::SIGNATURES.get(command_name)
+    SIGNATURES.get(command_name)
 }
 
 pub fn find_signature_help(file: &RMSFile<'_>, position: ByteIndex) -> Option<SignatureHelp> {
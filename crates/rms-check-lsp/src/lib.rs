@@ -12,17 +12,19 @@ use jsonrpc_core::{ErrorCode, IoHandler, Params};
 use lsp_types::{
     CodeAction, CodeActionKind, CodeActionParams, CodeActionProviderCapability, Diagnostic,
     DiagnosticRelatedInformation, DiagnosticSeverity, DidChangeTextDocumentParams,
-    DidCloseTextDocumentParams, DidOpenTextDocumentParams, DocumentFormattingParams, FoldingRange,
-    FoldingRangeParams, FoldingRangeProviderCapability, GotoDefinitionParams, InitializeParams,
-    InitializeResult, InitializedParams, Location, MessageType, NumberOrString, OneOf, Position,
-    PublishDiagnosticsParams, ServerCapabilities, ServerInfo, ShowMessageParams,
-    SignatureHelpOptions, TextDocumentItem, TextDocumentPositionParams, TextDocumentSyncCapability,
-    TextDocumentSyncKind, TextEdit, Url, WorkDoneProgressOptions, WorkspaceEdit,
+    DidChangeWatchedFilesParams, DidChangeWatchedFilesRegistrationOptions,
+    DidCloseTextDocumentParams, DidOpenTextDocumentParams, DocumentFormattingParams,
+    FileSystemWatcher, FoldingRange, FoldingRangeParams, FoldingRangeProviderCapability,
+    GotoDefinitionParams, InitializeParams, InitializeResult, InitializedParams, Location,
+    MessageType, NumberOrString, OneOf, Position, PublishDiagnosticsParams, Registration,
+    RegistrationParams, ServerCapabilities, ServerInfo, ShowMessageParams, SignatureHelpOptions,
+    TextDocumentItem, TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind,
+    TextEdit, Url, WorkDoneProgressOptions, WorkspaceEdit,
 };
 use multisplice::Multisplice;
 use rms_check::{
-    AtomKind, ByteIndex, Compatibility, FileId, FormatOptions, RMSCheck, RMSFile, Severity,
-    SourceLocation,
+    AtomKind, ByteIndex, Compatibility, Config, FileId, FormatOptions, IncrementalCheck, RMSCheck,
+    RMSFile, Severity, SourceLocation, CONFIG_FILE_NAME,
 };
 use serde_json::{self, json};
 use std::collections::HashMap;
@@ -50,22 +52,70 @@ fn out_of_range() -> jsonrpc_core::Error {
     internal_error("Range conversion between rms-check and the Language Server Protocol failed. This is a bug.")
 }
 
+/// Find the configuration file for a document on disk.
+#[cfg(not(target_arch = "wasm32"))]
+fn find_config(uri: &Url) -> Result<Option<Config>, rms_check::ConfigError> {
+    match uri.to_file_path() {
+        Ok(path) => Config::find(path),
+        Err(()) => Ok(None),
+    }
+}
+
+/// There is no file system access in WebAssembly, so configuration files are not supported.
+#[cfg(target_arch = "wasm32")]
+fn find_config(_uri: &Url) -> Result<Option<Config>, rms_check::ConfigError> {
+    Ok(None)
+}
+
+/// Get the default compatibility level for documents using a configuration file.
+fn default_compatibility(config: &Config) -> Compatibility {
+    config.compatibility().unwrap_or(Compatibility::Conquerors)
+}
+
+/// Create an `RMSFile` for a document, with the random_map.def files from the configuration.
+fn load_file(uri: &Url, text: String, config: &Config) -> RMSFile<'static> {
+    let mut file = RMSFile::from_string(uri, text);
+    config.add_definitions(&mut file);
+    file
+}
+
 struct Document {
     version: i32,
     // Can be 'static because we'll only pass in owned data.
     file: RMSFile<'static>,
+    /// The configuration file for this document.
+    config: Config,
+    /// Reuses lint results from before the edited part of the document.
+    check: IncrementalCheck,
     diagnostics: Vec<rms_check::Diagnostic>,
 }
 
 impl Document {
-    fn new(file: RMSFile<'static>, version: i32) -> Self {
+    fn new(uri: &Url, text: String, version: i32, config: Config) -> Self {
+        let check_config = config.clone();
         Self {
             version,
-            file,
+            file: load_file(uri, text, &config),
+            config,
+            check: IncrementalCheck::new(move || {
+                RMSCheck::default()
+                    .compatibility(Compatibility::Conquerors)
+                    .with_config(&check_config)
+            }),
             diagnostics: vec![],
         }
     }
 
+    /// Replace the contents of the document.
+    fn set_text(&mut self, uri: &Url, text: String) {
+        self.file = load_file(uri, text, &self.config);
+    }
+
+    /// Get the default compatibility level for this document.
+    fn compatibility(&self) -> Compatibility {
+        default_compatibility(&self.config)
+    }
+
     fn to_lsp_range(&self, location: SourceLocation) -> Option<lsp_types::Range> {
         let start = self.file.get_location(location.file(), location.start())?;
         let end = self.file.get_location(location.file(), location.end())?;
@@ -81,6 +131,11 @@ impl Document {
         })
     }
 
+    /// Get the URI for a file, if it is a file that the editor can open.
+    fn file_uri(&self, file: FileId) -> Option<Url> {
+        self.file.name(file).parse().ok()
+    }
+
     fn to_source_location(&self, file: FileId, range: lsp_types::Range) -> Option<SourceLocation> {
         let start = self
             .file
@@ -99,6 +154,8 @@ where
 {
     emit: Emit,
     documents: HashMap<Url, Document>,
+    /// Whether the client can notify us when configuration files change.
+    can_watch_config: bool,
 }
 
 impl<Emit> Inner<Emit>
@@ -129,20 +186,20 @@ where
             related_information: Some(
                 input
                     .labels()
-                    .map(|label| {
-                        Ok(DiagnosticRelatedInformation {
+                    // Leave out labels in files that the editor can not open, like the builtin
+                    // random_map.def files.
+                    .filter_map(|label| {
+                        let uri = doc.file_uri(label.location().file())?;
+                        Some(Ok(DiagnosticRelatedInformation {
                             location: Location {
-                                uri: doc
-                                    .file
-                                    .name(label.location().file())
-                                    .parse()
-                                    .map_err(internal_error)?,
-                                range: doc
-                                    .to_lsp_range(label.location())
-                                    .ok_or_else(out_of_range)?,
+                                uri,
+                                range: match doc.to_lsp_range(label.location()) {
+                                    Some(range) => range,
+                                    None => return Some(Err(out_of_range())),
+                                },
                             },
                             message: label.message().to_string(),
-                        })
+                        }))
                     })
                     .collect::<Result<Vec<_>, _>>()?,
             ),
@@ -150,8 +207,27 @@ where
         })
     }
 
+    /// Show a message to the user.
+    fn show_message(&self, typ: MessageType, message: impl ToString) {
+        (self.emit)(json!({
+            "jsonrpc": "2.0",
+            "method": "window/showMessage",
+            "params": ShowMessageParams {
+                typ,
+                message: message.to_string(),
+            },
+        }));
+    }
+
     /// Initialize the language server.
-    fn initialize(&mut self, _params: InitializeParams) -> RpcResult {
+    fn initialize(&mut self, params: InitializeParams) -> RpcResult {
+        self.can_watch_config = params
+            .capabilities
+            .workspace
+            .and_then(|workspace| workspace.did_change_watched_files)
+            .and_then(|capability| capability.dynamic_registration)
+            .unwrap_or(false);
+
         let capabilities = ServerCapabilities {
             code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
             document_formatting_provider: Some(OneOf::Left(true)),
@@ -179,14 +255,79 @@ where
         serde_json::to_value(result).map_err(internal_error)
     }
 
+    /// The client is ready, ask it to tell us about changes to configuration files.
+    #[allow(clippy::unnecessary_wraps)]
+    fn initialized(&mut self, _params: InitializedParams) -> Result<(), jsonrpc_core::Error> {
+        if !self.can_watch_config {
+            return Ok(());
+        }
+        let options = DidChangeWatchedFilesRegistrationOptions {
+            watchers: vec![FileSystemWatcher {
+                glob_pattern: format!("**/{}", CONFIG_FILE_NAME),
+                kind: None,
+            }],
+        };
+        (self.emit)(json!({
+            "jsonrpc": "2.0",
+            "id": "watch-config",
+            "method": "client/registerCapability",
+            "params": RegistrationParams {
+                registrations: vec![Registration {
+                    id: "watch-config".to_string(),
+                    method: "workspace/didChangeWatchedFiles".to_string(),
+                    register_options: Some(json!(options)),
+                }],
+            },
+        }));
+        Ok(())
+    }
+
+    /// A configuration file changed, reload it and re-lint all documents.
+    fn watched_files_changed(
+        &mut self,
+        params: DidChangeWatchedFilesParams,
+    ) -> Result<(), jsonrpc_core::Error> {
+        let config_changed = params
+            .changes
+            .iter()
+            .any(|change| change.uri.path().ends_with(CONFIG_FILE_NAME));
+        if !config_changed {
+            return Ok(());
+        }
+
+        let uris: Vec<Url> = self.documents.keys().cloned().collect();
+        for uri in uris {
+            let config = find_config(&uri).unwrap_or_else(|err| {
+                self.show_message(
+                    MessageType::Warning,
+                    format!("could not read configuration file: {}", err),
+                );
+                None
+            });
+            if let Some(doc) = self.documents.get_mut(&uri) {
+                let text = doc.file.main_source().to_string();
+                *doc = Document::new(&uri, text, doc.version, config.unwrap_or_default());
+            }
+            self.run_checks_and_publish(uri)?;
+        }
+        Ok(())
+    }
+
     /// A document was opened, lint.
     fn opened(&mut self, params: DidOpenTextDocumentParams) -> Result<(), jsonrpc_core::Error> {
         let TextDocumentItem {
             uri, version, text, ..
         } = params.text_document;
+        let config = find_config(&uri).unwrap_or_else(|err| {
+            self.show_message(
+                MessageType::Warning,
+                format!("could not read configuration file: {}", err),
+            );
+            None
+        });
         self.documents.insert(
             uri.clone(),
-            Document::new(RMSFile::from_string(uri.clone(), text), version),
+            Document::new(&uri, text, version, config.unwrap_or_default()),
         );
 
         self.run_checks_and_publish(uri)
@@ -220,7 +361,7 @@ where
                 }
             }
             doc.version += 1;
-            doc.file = RMSFile::from_string(uri.as_str(), splicer.to_string());
+            doc.set_text(&uri, splicer.to_string());
             self.run_checks_and_publish(uri)?;
         }
 
@@ -256,7 +397,7 @@ where
                     code_actions.push(CodeAction {
                         title: fix.message().to_string(),
                         kind: Some(CodeActionKind::QUICKFIX),
-                        diagnostics: Some(vec![self.to_lsp_diagnostic(&doc, diagnostic)?]),
+                        diagnostics: Some(vec![self.to_lsp_diagnostic(doc, diagnostic)?]),
                         edit: Some(WorkspaceEdit {
                             change_annotations: None,
                             changes: Some({
@@ -337,7 +478,7 @@ where
 
         let (state, atom) = doc
             .file
-            .parse_to(doc.file.file_id(), index, Compatibility::Conquerors);
+            .parse_to(doc.file.file_id(), index, doc.compatibility());
         let atom = match atom {
             Some(atom) => atom,
             None => return Ok(serde_json::to_value(()).unwrap()),
@@ -374,14 +515,13 @@ where
             _ => None,
         };
 
-        if let Some((_source_location, target_location)) = loc {
+        let target = loc.and_then(|(_source_location, target_location)| {
+            Some((doc.file_uri(target_location.file())?, target_location))
+        });
+        if let Some((uri, target_location)) = target {
             // Maybe use LocationLink here later
             serde_json::to_value(Some(Location {
-                uri: doc
-                    .file
-                    .name(target_location.file())
-                    .parse()
-                    .map_err(internal_error)?,
+                uri,
                 range: doc.to_lsp_range(target_location).ok_or_else(out_of_range)?,
             }))
         } else {
@@ -397,9 +537,12 @@ where
             .get(&params.text_document.uri)
             .ok_or_else(unknown_file)?;
 
-        let options = FormatOptions::default()
-            .tab_size(params.options.tab_size as u32)
-            .use_spaces(params.options.insert_spaces);
+        // Prefer the project's formatting options over the editor's.
+        let options = doc.config.format_options().unwrap_or_else(|| {
+            FormatOptions::default()
+                .tab_size(params.options.tab_size)
+                .use_spaces(params.options.insert_spaces)
+        });
         let result = options.format(doc.file.main_source());
 
         serde_json::to_value(vec![TextEdit {
@@ -421,9 +564,7 @@ where
             _ => return,
         };
 
-        let result = RMSCheck::default()
-            .compatibility(Compatibility::Conquerors)
-            .check(&doc.file);
+        let result = doc.check.check(&doc.file);
 
         doc.diagnostics = result.into_iter().collect();
     }
@@ -439,7 +580,7 @@ where
         let diagnostics: Vec<lsp_types::Diagnostic> = doc
             .diagnostics
             .iter()
-            .map(|diagnostic| self.to_lsp_diagnostic(&doc, diagnostic))
+            .map(|diagnostic| self.to_lsp_diagnostic(doc, diagnostic))
             .collect::<Result<Vec<_>, _>>()?;
 
         let params = PublishDiagnosticsParams::new(uri, diagnostics, Some(doc.version));
@@ -470,6 +611,7 @@ impl RMSCheckLSP {
             inner: Arc::new(Mutex::new(Inner {
                 emit: Box::new(emit),
                 documents: Default::default(),
+                can_watch_config: false,
             })),
             handler: IoHandler::new(),
         };
@@ -483,7 +625,9 @@ impl RMSCheckLSP {
             inner.initialize(params)
         });
 
-        self.add_notification("initialized", |_inner, _params: InitializedParams| Ok(()));
+        self.add_notification("initialized", |inner, params: InitializedParams| {
+            inner.initialized(params)
+        });
 
         self.add_notification(
             "textDocument/didOpen",
@@ -500,6 +644,11 @@ impl RMSCheckLSP {
             |inner, params: DidCloseTextDocumentParams| inner.closed(params),
         );
 
+        self.add_notification(
+            "workspace/didChangeWatchedFiles",
+            |inner, params: DidChangeWatchedFilesParams| inner.watched_files_changed(params),
+        );
+
         self.add_method(
             "textDocument/codeAction",
             |inner, params: CodeActionParams| inner.code_action(params),
@@ -587,6 +736,10 @@ impl RMSCheckLSP {
 
     /// Handle a JSON-RPC message.
     pub fn handle_sync(&mut self, message: serde_json::Value) -> Option<serde_json::Value> {
+        // Responses to our own requests, like `client/registerCapability`, need no handling.
+        if message.get("method").is_none() && message.get("id").is_some() {
+            return None;
+        }
         self.handler
             .handle_request_sync(&message.to_string())
             .map(|string| string.parse().unwrap())
@@ -0,0 +1,65 @@
+//! Compare full checks to incremental checks, simulating typing in a large script.
+//!
+//! Run with `cargo bench -p rms-check --bench incremental`.
+
+use rms_check::{IncrementalCheck, RMSCheck, RMSFile};
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 20;
+
+fn average(total: Duration) -> String {
+    format!("{:>8.2?}", total / ITERATIONS)
+}
+
+fn main() -> anyhow::Result<()> {
+    let source = std::fs::read_to_string("tests/rms/CM_Houseboat_v2.rms")?;
+    println!(
+        "checking {} lines, average of {} runs",
+        source.lines().count(),
+        ITERATIONS
+    );
+
+    for &position in &[0.1, 0.5, 0.9, 1.0] {
+        // Start typing at a line boundary at this position in the file.
+        let index = source[..(source.len() as f64 * position) as usize]
+            .rfind('\n')
+            .map_or(0, |index| index + 1);
+        let edits: Vec<String> = (0..ITERATIONS)
+            .map(|n| {
+                let mut edited = source.clone();
+                edited.insert_str(index, &"/* typing */ "[..n as usize % 12 + 1]);
+                edited
+            })
+            .collect();
+
+        let mut full = Duration::default();
+        for edited in &edits {
+            let rms = RMSFile::from_string("CM_Houseboat_v2.rms", edited.as_str());
+            let start = Instant::now();
+            RMSCheck::default().check(&rms);
+            full += start.elapsed();
+        }
+
+        let mut check = IncrementalCheck::new(RMSCheck::default);
+        check.check(&RMSFile::from_string(
+            "CM_Houseboat_v2.rms",
+            source.as_str(),
+        ));
+        let mut incremental = Duration::default();
+        for edited in &edits {
+            let rms = RMSFile::from_string("CM_Houseboat_v2.rms", edited.as_str());
+            let start = Instant::now();
+            check.check(&rms);
+            incremental += start.elapsed();
+        }
+
+        println!(
+            "edit at {:>3}%: full check {}, incremental check {}",
+            (position * 100.0) as u32,
+            average(full),
+            average(incremental)
+        );
+    }
+
+    Ok(())
+}
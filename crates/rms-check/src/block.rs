@@ -0,0 +1,414 @@
+//! Collect commands like `create_object` together with the attributes in their blocks, so lints
+//! can check a block as a whole.
+
+use crate::diagnostic::SourceLocation;
+use crate::parser::{Atom, AtomKind};
+use crate::persistent::PersistentList;
+use crate::state::{atom_at, nesting_at, Nesting, ParseState};
+use crate::tokenizer::Word;
+use crate::tokens::{TokenContext, TOKENS};
+use crate::RMSFile;
+use cow_utils::CowUtils;
+
+/// An attribute inside a block.
+#[derive(Debug, Clone)]
+pub struct BlockAttribute<'a> {
+    /// The name of the attribute.
+    pub name: Word<'a>,
+    /// The arguments to the attribute.
+    pub arguments: Vec<Word<'a>>,
+    /// The attribute including its arguments.
+    pub location: SourceLocation,
+    /// The `if` statements and `start_random` blocks inside the block that this attribute is
+    /// nested in.
+    pub nesting: Vec<Nesting<'a>>,
+}
+
+impl BlockAttribute<'_> {
+    /// Is this attribute only used in some games, because it is inside an `if` statement or a
+    /// `start_random` block?
+    pub fn is_conditional(&self) -> bool {
+        !self.nesting.is_empty()
+    }
+
+    /// Is this attribute used in every game where `other` is used, because it is in the same
+    /// branch as `other` or in a branch that contains it?
+    pub fn is_used_with(&self, other: &BlockAttribute<'_>) -> bool {
+        self.nesting.len() <= other.nesting.len()
+            && self
+                .nesting
+                .iter()
+                .zip(&other.nesting)
+                .all(|(a, b)| a.location() == b.location())
+    }
+}
+
+/// A command like `create_object`, together with the attributes in its block.
+#[derive(Debug, Clone)]
+pub struct Block<'a> {
+    /// The name of the command.
+    pub name: Word<'a>,
+    /// The arguments to the command.
+    pub arguments: Vec<Word<'a>>,
+    /// The attributes inside the block, in source order.
+    pub attributes: Vec<BlockAttribute<'a>>,
+    /// The `if` statements and `start_random` blocks that the command is nested in.
+    pub nesting: Vec<Nesting<'a>>,
+    /// Every branch of the `if` statements and `start_random` blocks inside the block, including
+    /// branches without attributes, in source order. Each branch is described by its nesting
+    /// inside the block, ending with the `if`, `elseif`, `else`, `start_random` or
+    /// `percent_chance` that starts it.
+    pub branches: Vec<Vec<Nesting<'a>>>,
+    /// The command up to and including the closing brace.
+    pub location: SourceLocation,
+}
+
+impl<'a> Block<'a> {
+    /// Get the attributes with the given name, ignoring case.
+    pub fn attributes_named<'b>(
+        &'b self,
+        name: &'b str,
+    ) -> impl Iterator<Item = &'b BlockAttribute<'a>> + 'b {
+        self.attributes
+            .iter()
+            .filter(move |attribute| attribute.name.value.eq_ignore_ascii_case(name))
+    }
+
+    /// Get the first attribute with the given name, ignoring case.
+    pub fn attribute(&self, name: &str) -> Option<&BlockAttribute<'a>> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.name.value.eq_ignore_ascii_case(name))
+    }
+
+    /// Does the block contain an attribute with the given name?
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.attribute(name).is_some()
+    }
+}
+
+/// A block that we are reading the attributes of.
+#[derive(Debug, Clone)]
+struct PendingBlock<'a> {
+    /// The command that starts the block.
+    command: Atom<'a>,
+    /// The `if` statements and `start_random` blocks that the command is nested in.
+    nesting: Vec<Nesting<'a>>,
+    /// The attributes read so far.
+    attributes: Vec<(Atom<'a>, Vec<Nesting<'a>>)>,
+    /// The locations of the attributes and their nesting, shared with snapshots. Blocks that are
+    /// never closed can contain the rest of the script, so they should not be copied for every
+    /// snapshot.
+    attribute_locations: PersistentList<(SourceLocation, Vec<SourceLocation>)>,
+    /// The branches read so far.
+    branches: Vec<Vec<Nesting<'a>>>,
+    /// The locations of the branches, shared with snapshots.
+    branch_locations: PersistentList<Vec<SourceLocation>>,
+    /// Whether the opening brace has been read.
+    is_open: bool,
+}
+
+/// Keeps track of the block that is being read, and builds a `Block` when it is closed.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BlockBuilder<'a> {
+    current: Option<PendingBlock<'a>>,
+}
+
+/// An owned copy of a `BlockBuilder`, which does not borrow from the source code.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BlockBuilderSnapshot {
+    current: Option<PendingBlockSnapshot>,
+}
+
+#[derive(Debug, Clone)]
+struct PendingBlockSnapshot {
+    command: SourceLocation,
+    nesting: Vec<SourceLocation>,
+    attributes: PersistentList<(SourceLocation, Vec<SourceLocation>)>,
+    branches: PersistentList<Vec<SourceLocation>>,
+    is_open: bool,
+}
+
+/// Does this command start a block?
+fn is_block_command(name: &str) -> bool {
+    TOKENS
+        .get(name.cow_to_ascii_lowercase().as_ref())
+        .is_some_and(|token| matches!(token.context(), TokenContext::Command(_)))
+}
+
+impl<'a> BlockBuilder<'a> {
+    /// Update the current block upon reading a new atom, returning the block if this atom
+    /// closed it. This must be called before `state` is updated for this atom.
+    pub fn update(&mut self, state: &ParseState<'a>, atom: &Atom<'a>) -> Option<Block<'a>> {
+        let brace = state
+            .nesting
+            .iter()
+            .position(|nesting| matches!(nesting, Nesting::Brace(_)));
+        match (&atom.kind, brace) {
+            (AtomKind::Command { name, .. }, None) => {
+                self.current = if is_block_command(name.value) {
+                    Some(PendingBlock {
+                        command: atom.clone(),
+                        nesting: state.nesting.clone(),
+                        attributes: vec![],
+                        attribute_locations: Default::default(),
+                        branches: vec![],
+                        branch_locations: Default::default(),
+                        is_open: false,
+                    })
+                } else {
+                    None
+                };
+            }
+            (AtomKind::Command { .. }, Some(brace)) => {
+                if let Some(pending) = self.current.as_mut().filter(|pending| pending.is_open) {
+                    let nesting = state.nesting[brace + 1..].to_vec();
+                    pending.attribute_locations.push((
+                        atom.location,
+                        nesting.iter().map(Nesting::location).collect(),
+                    ));
+                    pending.attributes.push((atom.clone(), nesting));
+                }
+            }
+            (
+                AtomKind::If { .. }
+                | AtomKind::ElseIf { .. }
+                | AtomKind::Else { .. }
+                | AtomKind::StartRandom { .. }
+                | AtomKind::PercentChance { .. },
+                Some(brace),
+            ) => {
+                if let Some(pending) = self.current.as_mut().filter(|pending| pending.is_open) {
+                    let mut nesting = state.nesting[brace + 1..].to_vec();
+                    // Do what `ParseState::update_nesting` will do for this atom.
+                    let branch = match atom.kind {
+                        AtomKind::If { .. } => Nesting::If(atom.clone()),
+                        AtomKind::ElseIf { .. } => Nesting::ElseIf(atom.clone()),
+                        AtomKind::Else { .. } => Nesting::Else(atom.clone()),
+                        AtomKind::StartRandom { .. } => Nesting::StartRandom(atom.clone()),
+                        _ => Nesting::PercentChance(atom.clone()),
+                    };
+                    let replaces_last = matches!(
+                        (&branch, nesting.last()),
+                        (
+                            Nesting::ElseIf(_) | Nesting::Else(_),
+                            Some(Nesting::If(_) | Nesting::ElseIf(_))
+                        ) | (Nesting::PercentChance(_), Some(Nesting::PercentChance(_)))
+                    );
+                    if replaces_last {
+                        nesting.pop();
+                    }
+                    nesting.push(branch);
+                    pending
+                        .branch_locations
+                        .push(nesting.iter().map(Nesting::location).collect());
+                    pending.branches.push(nesting);
+                }
+            }
+            (AtomKind::OpenBlock { .. }, None) => match self.current.as_mut() {
+                Some(pending) if !pending.is_open => pending.is_open = true,
+                _ => self.current = None,
+            },
+            (AtomKind::CloseBlock { .. }, Some(brace)) if brace == state.nesting.len() - 1 => {
+                let pending = self.current.take().filter(|pending| pending.is_open)?;
+                return Some(pending.finish(atom));
+            }
+            _ => (),
+        }
+        None
+    }
+
+    /// Take an owned snapshot of the current state.
+    pub fn snapshot(&self) -> BlockBuilderSnapshot {
+        BlockBuilderSnapshot {
+            current: self.current.as_ref().map(|pending| PendingBlockSnapshot {
+                command: pending.command.location,
+                nesting: pending.nesting.iter().map(Nesting::location).collect(),
+                attributes: pending.attribute_locations.clone(),
+                branches: pending.branch_locations.clone(),
+                is_open: pending.is_open,
+            }),
+        }
+    }
+
+    /// Restore a block builder from a snapshot, parsing the atoms it refers to from `rms`.
+    pub fn restore(rms: &'a RMSFile<'a>, snapshot: BlockBuilderSnapshot) -> Self {
+        let nesting = |locations: &[SourceLocation]| {
+            locations
+                .iter()
+                .map(|&location| nesting_at(rms, location))
+                .collect::<Vec<_>>()
+        };
+        Self {
+            current: snapshot.current.map(|pending| PendingBlock {
+                command: atom_at(rms, pending.command),
+                nesting: nesting(&pending.nesting),
+                attributes: pending
+                    .attributes
+                    .iter()
+                    .map(|(location, locations)| (atom_at(rms, *location), nesting(locations)))
+                    .collect(),
+                attribute_locations: pending.attributes,
+                branches: pending
+                    .branches
+                    .iter()
+                    .map(|locations| nesting(locations))
+                    .collect(),
+                branch_locations: pending.branches,
+                is_open: pending.is_open,
+            }),
+        }
+    }
+}
+
+impl<'a> PendingBlock<'a> {
+    /// Create the block, now that it is closed by the `close` atom.
+    fn finish(self, close: &Atom<'a>) -> Block<'a> {
+        let (name, arguments) = match self.command.kind {
+            AtomKind::Command { name, arguments } => (name, arguments),
+            kind => unreachable!("Expected AtomKind::Command, got {:?}", kind),
+        };
+        let attributes = self
+            .attributes
+            .into_iter()
+            .map(|(atom, nesting)| match atom.kind {
+                AtomKind::Command { name, arguments } => BlockAttribute {
+                    name,
+                    arguments,
+                    location: atom.location,
+                    nesting,
+                },
+                kind => unreachable!("Expected AtomKind::Command, got {:?}", kind),
+            })
+            .collect();
+        Block {
+            name,
+            arguments,
+            attributes,
+            nesting: self.nesting,
+            branches: self.branches,
+            location: SourceLocation::new(
+                self.command.location.file(),
+                self.command.location.start()..close.location.end(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Compatibility, Parser};
+
+    /// Describe the blocks in `source` like "create_object GOLD: number_of_objects 5, if:base_terrain".
+    fn blocks(source: &str, snapshot_at: Option<usize>) -> Vec<String> {
+        let rms = RMSFile::from_string("blocks.rms", source);
+        let mut state = ParseState::new(&rms, Compatibility::Conquerors);
+        let mut builder = BlockBuilder::default();
+        let mut blocks = vec![];
+        for (index, (atom, _)) in Parser::new(rms.file_id(), source).enumerate() {
+            if Some(index) == snapshot_at {
+                builder = BlockBuilder::restore(&rms, builder.snapshot());
+            }
+            blocks.extend(builder.update(&state, &atom));
+            state.update(&atom);
+            state.update_nesting(&atom);
+        }
+        blocks
+            .iter()
+            .map(|block| {
+                let describe = |name: &Word<'_>, arguments: &[Word<'_>]| {
+                    std::iter::once(name.value)
+                        .chain(arguments.iter().map(|arg| arg.value))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                };
+                let attributes: Vec<_> = block
+                    .attributes
+                    .iter()
+                    .map(|attribute| {
+                        let prefix = if attribute.is_conditional() {
+                            "if:"
+                        } else {
+                            ""
+                        };
+                        format!(
+                            "{}{}",
+                            prefix,
+                            describe(&attribute.name, &attribute.arguments)
+                        )
+                    })
+                    .collect();
+                let branches: Vec<_> = block
+                    .branches
+                    .iter()
+                    .map(|branch| {
+                        branch
+                            .iter()
+                            .map(|nesting| match &nesting.atom().kind {
+                                AtomKind::If { .. } => "if",
+                                AtomKind::ElseIf { .. } => "elseif",
+                                AtomKind::Else { .. } => "else",
+                                AtomKind::StartRandom { .. } => "start_random",
+                                _ => "percent_chance",
+                            })
+                            .collect::<Vec<_>>()
+                            .join(">")
+                    })
+                    .collect();
+                format!(
+                    "{}: {} [{}]",
+                    describe(&block.name, &block.arguments),
+                    attributes.join(", "),
+                    branches.join(", ")
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn collect_blocks() {
+        let source = "<OBJECTS_GENERATION>\r\ncreate_object GOLD {\r\n  number_of_objects 5\r\n  if TINY_MAP set_gaia_object_only\r\n  elseif SMALL_MAP\r\n  else start_random percent_chance 50 set_loose_grouping end_random\r\n  endif\r\n}\r\nif LARGE_MAP\r\n  create_object STONE { }\r\nendif\r\n";
+        let expected = vec![
+            "create_object GOLD: number_of_objects 5, if:set_gaia_object_only, if:set_loose_grouping [if, elseif, else, else>start_random, else>start_random>percent_chance]",
+            "create_object STONE:  []",
+        ];
+        assert_eq!(blocks(source, None), expected);
+        // Blocks can be restored from a snapshot taken halfway through.
+        for index in 0..20 {
+            assert_eq!(
+                blocks(source, Some(index)),
+                expected,
+                "snapshot at {}",
+                index
+            );
+        }
+    }
+
+    #[test]
+    fn block_model() {
+        let source = "<LAND_GENERATION>\r\nif TINY_MAP\r\ncreate_land {\r\n  terrain_type DESERT\r\n  LAND_PERCENT 10\r\n  land_percent 20\r\n}\r\nendif\r\n";
+        let rms = RMSFile::from_string("blocks.rms", source);
+        let mut state = ParseState::new(&rms, Compatibility::Conquerors);
+        let mut builder = BlockBuilder::default();
+        let mut blocks = vec![];
+        for (atom, _) in Parser::new(rms.file_id(), source) {
+            blocks.extend(builder.update(&state, &atom));
+            state.update(&atom);
+            state.update_nesting(&atom);
+        }
+
+        assert_eq!(blocks.len(), 1);
+        let block = &blocks[0];
+        assert_eq!(block.name.value, "create_land");
+        assert!(matches!(block.nesting.as_slice(), [Nesting::If(_)]));
+        assert_eq!(block.location.range(), 32.into()..109.into());
+        assert!(block.has_attribute("terrain_type"));
+        assert!(!block.has_attribute("base_size"));
+        assert_eq!(block.attributes_named("land_percent").count(), 2);
+        assert_eq!(
+            block.attribute("land_percent").unwrap().arguments[0].value,
+            "10"
+        );
+    }
+}
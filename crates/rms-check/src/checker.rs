@@ -1,12 +1,15 @@
 //! The checker that runs lints and keeps track of warnings.
 
-use crate::diagnostic::Diagnostic;
-use crate::parser::Atom;
-use crate::state::{Compatibility, ParseState};
+use crate::block::{Block, BlockBuilder, BlockBuilderSnapshot};
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::parser::{Atom, AtomKind, ParseError, ParseErrorKind};
+use crate::state::{Compatibility, ParseState, ParseStateSnapshot};
+use crate::suppressions::{is_directive, Suppressions};
 use crate::RMSFile;
-use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::Arc;
 
-pub trait Lint {
+pub trait Lint: Send {
     fn name(&self) -> &'static str;
     fn run_inside_comments(&self) -> bool {
         false
@@ -14,78 +17,56 @@ pub trait Lint {
     fn lint_atom(&mut self, _state: &mut ParseState<'_>, _atom: &Atom<'_>) -> Vec<Diagnostic> {
         Default::default()
     }
-}
-
-/// Builtin #define or #const names for AoE2: The Age of Conquerors.
-#[allow(dead_code)] // need to use this at some point?
-const AOC_OPTION_DEFINES: [&str; 8] = [
-    "TINY_MAP",
-    "SMALL_MAP",
-    "MEDIUM_MAP",
-    "LARGE_MAP",
-    "HUGE_MAP",
-    "GIGANTIC_MAP",
-    "UP_AVAILABLE",
-    "UP_EXTENSION",
-];
-
-lazy_static! {
-    /// Builtin #define or #const names for UserPatch.
-    #[allow(dead_code)] // need to use this at some point?
-    static ref UP_OPTION_DEFINES: Vec<String> = {
-        let mut list = vec![
-            "FIXED_POSITIONS".to_string(),
-            "AI_PLAYERS".to_string(),
-            "CAPTURE_RELIC".to_string(),
-            "DEATH_MATCH".to_string(),
-            "DEFEND_WONDER".to_string(),
-            "KING_OT_HILL".to_string(),
-            "RANDOM_MAP".to_string(),
-            "REGICIDE".to_string(),
-            "TURBO_RANDOM_MAP".to_string(),
-            "WONDER_RACE".to_string(),
-        ];
-
-        for i in 1..=8 {
-            list.push(format!("{}_PLAYER_GAME", i));
-        }
-        for i in 0..=4 {
-            list.push(format!("{}_TEAM_GAME", i));
-        }
-        for team in 0..=4 {
-            for player in 1..=8 {
-                list.push(format!("PLAYER{}_TEAM{}", player, team));
-            }
-        }
-        for team in 0..=4 {
-            for size in 0..=8 {
-                list.push(format!("TEAM{}_SIZE{}", team, size));
-            }
-        }
-
-        list
-    };
+    /// Check a command like `create_object` together with all the attributes in its block. This
+    /// is called when the closing brace of the block is read, after `lint_atom`.
+    fn lint_block(&mut self, _state: &mut ParseState<'_>, _block: &Block<'_>) -> Vec<Diagnostic> {
+        Default::default()
+    }
+    /// Report diagnostics after all atoms in the script have been checked, for lints that look at
+    /// the script as a whole.
+    fn lint_end(&mut self, _state: &mut ParseState<'_>) -> Vec<Diagnostic> {
+        Default::default()
+    }
+    /// Copy the current state of this lint, so checking can resume from this point after the
+    /// source code changes further down.
+    ///
+    /// Lints that return `None` (the default) are always rerun on the entire file.
+    fn snapshot(&self) -> Option<Box<dyn Lint>> {
+        None
+    }
 }
 
 #[derive(Default)]
 pub struct CheckerBuilder {
     lints: Vec<Box<dyn Lint>>,
+    /// The names of the lints that were removed.
+    disabled_lints: Vec<&'static str>,
     compatibility: Compatibility,
+    severities: HashMap<String, Severity>,
 }
 
 impl CheckerBuilder {
-    pub fn build<'source>(self, rms: &'source RMSFile<'source>) -> Checker<'source> {
+    /// Get the default compatibility for a map. Scripts can still override this using
+    /// `/* Compatibility: */` comments.
+    pub(crate) fn compatibility_for(&self, rms: &RMSFile<'_>) -> Compatibility {
         // Default to UP 1.5 if it's a ZR@ map
-        let compatibility = if rms.is_zip_rms() && self.compatibility < Compatibility::UserPatch15 {
+        if rms.is_zip_rms() && self.compatibility < Compatibility::UserPatch15 {
             Compatibility::UserPatch15
         } else {
             self.compatibility
-        };
+        }
+    }
 
+    pub fn build<'source>(self, rms: &'source RMSFile<'source>) -> Checker<'source> {
+        let compatibility = self.compatibility_for(rms);
         let state = ParseState::new(rms, compatibility);
         Checker {
             lints: self.lints,
+            disabled_lints: self.disabled_lints,
+            severities: Arc::new(self.severities),
+            suppressions: Default::default(),
             state,
+            blocks: Default::default(),
         }
     }
 
@@ -94,6 +75,30 @@ impl CheckerBuilder {
         self
     }
 
+    /// Remove the lint with the given name.
+    pub fn without_lint(mut self, name: &str) -> Self {
+        let disabled_lints = &mut self.disabled_lints;
+        self.lints.retain(|lint| {
+            let keep = lint.name() != name;
+            if !keep {
+                disabled_lints.push(lint.name());
+            }
+            keep
+        });
+        self
+    }
+
+    /// Report diagnostics with the given code at a different severity.
+    pub fn severity(mut self, code: impl ToString, severity: Severity) -> Self {
+        self.severities.insert(code.to_string(), severity);
+        self
+    }
+
+    /// Iterate over the names of the lints that will be run.
+    pub fn lint_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.lints.iter().map(|lint| lint.name())
+    }
+
     pub const fn compatibility(mut self, compatibility: Compatibility) -> Self {
         self.compatibility = compatibility;
         self
@@ -102,7 +107,17 @@ impl CheckerBuilder {
 
 pub struct Checker<'a> {
     lints: Vec<Box<dyn Lint>>,
+    /// The names of the lints that were removed. Suppression comments for these lints are not
+    /// reported as unused.
+    disabled_lints: Vec<&'static str>,
+    /// The configured severities. These and the suppressions are shared with snapshots, and only
+    /// copied when they change.
+    severities: Arc<HashMap<String, Severity>>,
+    /// The suppression comments found so far.
+    suppressions: Arc<Suppressions>,
     state: ParseState<'a>,
+    /// The block that is currently being read.
+    blocks: BlockBuilder<'a>,
 }
 
 impl<'a> Checker<'a> {
@@ -110,17 +125,116 @@ impl<'a> Checker<'a> {
         CheckerBuilder::default()
     }
 
+    /// Take a snapshot of the checker state, if all lints support it.
+    pub fn snapshot(&self) -> Option<CheckerSnapshot> {
+        let lints = self
+            .lints
+            .iter()
+            .map(|lint| lint.snapshot())
+            .collect::<Option<Vec<_>>>()?;
+        Some(CheckerSnapshot {
+            lints,
+            disabled_lints: self.disabled_lints.clone(),
+            severities: self.severities.clone(),
+            suppressions: self.suppressions.clone(),
+            state: self.state.snapshot(),
+            blocks: self.blocks.snapshot(),
+        })
+    }
+
+    /// Run the lints for an atom, and report the parse errors that no lint already reported on.
+    pub fn check_atom(
+        &mut self,
+        atom: &Atom<'a>,
+        parse_errors: Vec<ParseError>,
+    ) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        if matches!(&atom.kind, AtomKind::Comment { content, .. } if is_directive(content)) {
+            Arc::make_mut(&mut self.suppressions).add_atom(self.state.rms, atom);
+        }
+        let warnings = self.write_atom(atom);
+        for w in parse_errors {
+            if w.kind == ParseErrorKind::MissingCommandArgs {
+                // Handled by arg-types lint
+                continue;
+            }
+
+            let overlaps_parse_warning = |lint_warning: &Diagnostic| {
+                let lint_range = lint_warning.location().range();
+                let parse_range = w.location.range();
+                lint_range.contains(&parse_range.start) && lint_range.contains(&parse_range.end)
+            };
+            if warnings.iter().all(overlaps_parse_warning) {
+                diagnostics.push(
+                    Diagnostic::parse_error(w.location, format!("{:?}", w.kind)).with_code("parse"),
+                );
+            }
+        }
+        diagnostics.extend(warnings);
+
+        self.apply_severities(diagnostics)
+    }
+
+    /// Run the lints that report on the script as a whole. This must be called after all atoms
+    /// have been checked.
+    pub fn finish(&mut self) -> Vec<Diagnostic> {
+        let state = &mut self.state;
+        let mut warnings = vec![];
+        for lint in self.lints.iter_mut() {
+            let new_warnings = lint
+                .lint_end(state)
+                .into_iter()
+                .map(move |warning| warning.with_code(lint.name()));
+            warnings.extend(new_warnings);
+        }
+        self.apply_severities(warnings)
+    }
+
+    /// Apply the configured severities to diagnostics.
+    fn apply_severities(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        let severities = &self.severities;
+        diagnostics
+            .into_iter()
+            .map(|diagnostic| {
+                let severity = diagnostic
+                    .code()
+                    .and_then(|code| severities.get(code).copied());
+                match severity {
+                    Some(severity) => diagnostic.with_severity(severity),
+                    None => diagnostic,
+                }
+            })
+            .collect()
+    }
+
+    /// Remove the diagnostics that are suppressed by comments in the script, and warn about
+    /// suppression comments that did not do anything.
+    pub fn apply_suppressions(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        self.suppressions
+            .apply(self.state.rms, diagnostics, &self.disabled_lints)
+    }
+
     pub fn write_atom(&mut self, atom: &Atom<'a>) -> Vec<Diagnostic> {
-        let mut state = &mut self.state;
+        let state = &mut self.state;
         let mut warnings = vec![];
         for lint in self.lints.iter_mut() {
             let new_warnings = lint
-                .lint_atom(&mut state, atom)
+                .lint_atom(state, atom)
                 .into_iter()
                 .map(move |warning| warning.with_code(lint.name()));
             warnings.extend(new_warnings);
         }
 
+        if let Some(block) = self.blocks.update(state, atom) {
+            for lint in self.lints.iter_mut() {
+                let new_warnings = lint
+                    .lint_block(state, &block)
+                    .into_iter()
+                    .map(move |warning| warning.with_code(lint.name()));
+                warnings.extend(new_warnings);
+            }
+        }
+
         self.state.update(atom);
         if let Some(nest_warning) = self.state.update_nesting(atom) {
             warnings.push(nest_warning);
@@ -129,3 +243,32 @@ impl<'a> Checker<'a> {
         warnings
     }
 }
+
+/// An owned copy of the checker state, that can be restored for a different version of the same
+/// source code.
+pub struct CheckerSnapshot {
+    lints: Vec<Box<dyn Lint>>,
+    disabled_lints: Vec<&'static str>,
+    severities: Arc<HashMap<String, Severity>>,
+    suppressions: Arc<Suppressions>,
+    state: ParseStateSnapshot,
+    blocks: BlockBuilderSnapshot,
+}
+
+impl CheckerSnapshot {
+    /// Restore a checker from this snapshot. The snapshot is consumed, so take a new one if
+    /// checking needs to resume from this point again.
+    ///
+    /// This is only valid if the source code did not change up to the point where the snapshot
+    /// was taken.
+    pub fn restore<'a>(self, rms: &'a RMSFile<'a>) -> Checker<'a> {
+        Checker {
+            lints: self.lints,
+            disabled_lints: self.disabled_lints,
+            severities: self.severities,
+            suppressions: self.suppressions,
+            state: ParseState::restore(rms, &self.state),
+            blocks: BlockBuilder::restore(rms, self.blocks),
+        }
+    }
+}
@@ -0,0 +1,388 @@
+//! Project configuration, read from `rms-check.toml` files.
+//!
+//! ## Example
+//! ```toml
+//! # The default compatibility, if the script does not have a `/* Compatibility: */` comment.
+//! compatibility = "up 1.5"
+//! # Extra random_map.def files, relative to this file.
+//! definitions = ["mod.def"]
+//!
+//! [lints]
+//! # Turn lints on or off, or change the severity of their diagnostics.
+//! comment-contents = "off"
+//! unknown-attribute = "error"
+//!
+//! [format]
+//! tab_size = 4
+//! use_spaces = true
+//! align_arguments = false
+//! ```
+
+use crate::diagnostic::Severity;
+use crate::formatter::FormatOptions;
+use crate::state::Compatibility;
+use crate::{DefinitionsMode, RMSCheck, RMSFile, LINT_NAMES};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The name of configuration files.
+pub const CONFIG_FILE_NAME: &str = "rms-check.toml";
+
+/// How a lint is configured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    /// Do not run the lint.
+    Off,
+    /// Run the lint, using the severities it picks for its diagnostics.
+    On,
+    /// Run the lint, and report all its diagnostics as errors.
+    Error,
+    /// Run the lint, and report all its diagnostics as warnings.
+    Warning,
+    /// Run the lint, and report all its diagnostics as hints.
+    Hint,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FormatConfig {
+    tab_size: Option<u32>,
+    use_spaces: Option<bool>,
+    align_arguments: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    compatibility: Option<String>,
+    #[serde(default)]
+    definitions: Vec<PathBuf>,
+    #[serde(default)]
+    lints: HashMap<String, LintLevel>,
+    format: Option<FormatConfig>,
+}
+
+/// An error that occurred while reading a configuration file.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file could not be read.
+    Io(PathBuf, io::Error),
+    /// The file is not valid TOML, or has unexpected keys.
+    Toml(PathBuf, toml::de::Error),
+    /// The compatibility setting is not a known compatibility level.
+    UnknownCompatibility(PathBuf, String),
+    /// The file configures a lint that does not exist.
+    UnknownLint(PathBuf, String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            Self::Toml(path, err) => write!(f, "{}: {}", path.display(), err),
+            Self::UnknownCompatibility(path, name) => {
+                write!(f, "{}: unknown compatibility `{}`", path.display(), name)
+            }
+            Self::UnknownLint(path, name) => {
+                write!(f, "{}: unknown lint `{}`", path.display(), name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Project configuration for rms-check.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    path: Option<PathBuf>,
+    compatibility: Option<Compatibility>,
+    lints: HashMap<String, LintLevel>,
+    format: Option<FormatOptions>,
+    /// The extra random_map.def files, and their contents.
+    definitions: Vec<(PathBuf, String)>,
+}
+
+impl Config {
+    /// Parse a configuration file. Relative definition file paths are resolved relative to the
+    /// directory containing `path`.
+    pub fn from_toml(path: impl AsRef<Path>, source: &str) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let file: ConfigFile =
+            toml::from_str(source).map_err(|err| ConfigError::Toml(path.to_owned(), err))?;
+
+        let compatibility = match file.compatibility {
+            Some(name) => Some(
+                name.parse()
+                    .map_err(|_| ConfigError::UnknownCompatibility(path.to_owned(), name))?,
+            ),
+            None => None,
+        };
+
+        if let Some(name) = file
+            .lints
+            .keys()
+            .find(|name| !LINT_NAMES.contains(&name.as_str()))
+        {
+            return Err(ConfigError::UnknownLint(path.to_owned(), name.clone()));
+        }
+
+        let format = file.format.map(|format| {
+            let mut options = FormatOptions::default();
+            if let Some(tab_size) = format.tab_size {
+                options = options.tab_size(tab_size);
+            }
+            if let Some(use_spaces) = format.use_spaces {
+                options = options.use_spaces(use_spaces);
+            }
+            if let Some(align_arguments) = format.align_arguments {
+                options = options.align_arguments(align_arguments);
+            }
+            options
+        });
+
+        let root = path.parent().unwrap_or_else(|| Path::new(""));
+        let definitions = file
+            .definitions
+            .into_iter()
+            .map(|definitions_path| {
+                let definitions_path = root.join(definitions_path);
+                let source = std::fs::read(&definitions_path)
+                    .map_err(|err| ConfigError::Io(definitions_path.clone(), err))?;
+                Ok((definitions_path, crate::to_chardet_string(source)))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            path: Some(path.to_owned()),
+            compatibility,
+            lints: file.lints,
+            format,
+            definitions,
+        })
+    }
+
+    /// Read a configuration file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let source =
+            std::fs::read_to_string(path).map_err(|err| ConfigError::Io(path.to_owned(), err))?;
+        Self::from_toml(path, &source)
+    }
+
+    /// Find the configuration file for a script, by walking up the directory tree starting at
+    /// `path`. Returns `None` if there is no configuration file.
+    pub fn find(path: impl AsRef<Path>) -> Result<Option<Self>, ConfigError> {
+        let path = path.as_ref();
+        let path = if path.is_absolute() {
+            path.to_owned()
+        } else {
+            std::env::current_dir()
+                .map_err(|err| ConfigError::Io(path.to_owned(), err))?
+                .join(path)
+        };
+
+        for dir in path.ancestors() {
+            let config_path = dir.join(CONFIG_FILE_NAME);
+            if config_path.is_file() {
+                return Self::load(config_path).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    /// The path to the configuration file, if it was read from disk.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The default compatibility level for scripts, if configured.
+    pub const fn compatibility(&self) -> Option<Compatibility> {
+        self.compatibility
+    }
+
+    /// How a lint is configured, if it is mentioned in the configuration file.
+    pub fn lint_level(&self, name: &str) -> Option<LintLevel> {
+        self.lints.get(name).copied()
+    }
+
+    /// The formatter options, if configured.
+    pub fn format_options(&self) -> Option<FormatOptions> {
+        self.format.clone()
+    }
+
+    /// Add the configured random_map.def files to a map, for every compatibility level.
+    pub fn add_definitions(&self, rms: &mut RMSFile<'_>) {
+        for (path, source) in &self.definitions {
+            rms.add_definitions_for_all(
+                path.to_string_lossy(),
+                source.clone(),
+                DefinitionsMode::Layer,
+            );
+        }
+    }
+}
+
+impl RMSCheck {
+    /// Configure the checker using a configuration file: set the default compatibility, and
+    /// disable lints or change their severities.
+    pub fn with_config(self, config: &Config) -> Self {
+        let mut checker = self.checker;
+        if let Some(compatibility) = config.compatibility {
+            checker = checker.compatibility(compatibility);
+        }
+        for (name, level) in &config.lints {
+            checker = match level {
+                LintLevel::Off => checker.without_lint(name),
+                LintLevel::On => checker,
+                LintLevel::Error => checker.severity(name, Severity::Error),
+                LintLevel::Warning => checker.severity(name, Severity::Warning),
+                LintLevel::Hint => checker.severity(name, Severity::Hint),
+            };
+        }
+        Self { checker }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(config: &Config, source: &str) -> Vec<(Option<String>, Severity)> {
+        let rms = RMSFile::from_string("test.rms", source);
+        RMSCheck::default()
+            .with_config(config)
+            .check(&rms)
+            .iter()
+            .map(|diagnostic| (diagnostic.code().map(str::to_string), diagnostic.severity()))
+            .collect()
+    }
+
+    #[test]
+    fn lint_names() {
+        let default_lints: Vec<_> = RMSCheck::default().checker.lint_names().collect();
+        assert_eq!(default_lints, LINT_NAMES);
+    }
+
+    #[test]
+    fn parse_config() -> anyhow::Result<()> {
+        let config = Config::from_toml(
+            "rms-check.toml",
+            r#"
+                compatibility = "up 1.5"
+                [lints]
+                comment-contents = "off"
+                arg-types = "error"
+                [format]
+                tab_size = 4
+            "#,
+        )?;
+        assert_eq!(config.compatibility(), Some(Compatibility::UserPatch15));
+        assert_eq!(config.lint_level("comment-contents"), Some(LintLevel::Off));
+        assert_eq!(config.lint_level("arg-types"), Some(LintLevel::Error));
+        assert_eq!(config.lint_level("include"), None);
+        assert_eq!(
+            crate::format(
+                "<PLAYER_SETUP> random_placement",
+                config.format_options().unwrap()
+            ),
+            "<PLAYER_SETUP>\r\nrandom_placement\r\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_config() {
+        let unknown_lint = Config::from_toml("rms-check.toml", "[lints]\nno-such-lint = \"off\"");
+        assert!(
+            matches!(unknown_lint, Err(ConfigError::UnknownLint(_, name)) if name == "no-such-lint")
+        );
+        let unknown_compat = Config::from_toml("rms-check.toml", "compatibility = \"aok\"");
+        assert!(matches!(
+            unknown_compat,
+            Err(ConfigError::UnknownCompatibility(_, _))
+        ));
+        let unknown_level = Config::from_toml("rms-check.toml", "[lints]\ninclude = \"loud\"");
+        assert!(matches!(unknown_level, Err(ConfigError::Toml(_, _))));
+        let unknown_key = Config::from_toml("rms-check.toml", "compat = \"de\"");
+        assert!(matches!(unknown_key, Err(ConfigError::Toml(_, _))));
+    }
+
+    #[test]
+    fn lint_levels() -> anyhow::Result<()> {
+        let source = "#define A\r\nif A /* A */ endif\r\n<LAND_GENERATION>\r\nbase_terrain A\r\n";
+        assert_eq!(
+            check(&Config::default(), source),
+            vec![
+                (Some("comment-contents".to_string()), Severity::Warning),
+                (Some("arg-types".to_string()), Severity::Warning),
+            ]
+        );
+
+        let config = Config::from_toml(
+            "rms-check.toml",
+            "[lints]\ncomment-contents = \"off\"\narg-types = \"hint\"",
+        )?;
+        assert_eq!(
+            check(&config, source),
+            vec![(Some("arg-types".to_string()), Severity::Hint)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn disabled_lint_suppressions() -> anyhow::Result<()> {
+        // Suppressions for disabled lints are not reported as unused.
+        let config = Config::from_toml("rms-check.toml", "[lints]\narg-types = \"off\"")?;
+        let source = "#define A\r\n<LAND_GENERATION>\r\n/* rms-check-disable-next-line arg-types */\r\nbase_terrain A\r\n";
+        assert!(check(&config, source).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn find_config() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(format!("rms-check-config-{}", std::process::id()));
+        let nested = root.join("maps").join("nested");
+        std::fs::create_dir_all(&nested)?;
+        std::fs::write(root.join("mod.def"), "#const MOD_UNIT 1500\r\n")?;
+        std::fs::write(
+            root.join(CONFIG_FILE_NAME),
+            "compatibility = \"de\"\ndefinitions = [\"mod.def\"]\n",
+        )?;
+
+        let config = Config::find(nested.join("map.rms"))?;
+        std::fs::remove_dir_all(&root)?;
+
+        let config = config.expect("should find the config file");
+        assert_eq!(config.path(), Some(root.join(CONFIG_FILE_NAME).as_path()));
+        assert_eq!(
+            config.compatibility(),
+            Some(Compatibility::DefinitiveEdition)
+        );
+
+        let source = "<OBJECTS_GENERATION>\r\ncreate_object MOD_UNIT { number_of_objects 1 }\r\n";
+        let mut rms = RMSFile::from_string("map.rms", source);
+        assert!(RMSCheck::default()
+            .with_config(&config)
+            .check(&rms)
+            .has_warnings());
+        config.add_definitions(&mut rms);
+        assert!(!RMSCheck::default()
+            .with_config(&config)
+            .check(&rms)
+            .has_warnings());
+        // The definitions are also used if the script selects a different compatibility level.
+        let source = format!("/* Compatibility: UserPatch 1.5 */\r\n{}", source);
+        let mut rms = RMSFile::from_string("map.rms", source);
+        config.add_definitions(&mut rms);
+        assert!(!RMSCheck::default()
+            .with_config(&config)
+            .check(&rms)
+            .has_warnings());
+        Ok(())
+    }
+}
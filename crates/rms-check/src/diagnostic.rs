@@ -2,7 +2,7 @@
 use std::fmt::Display;
 use std::ops::Range;
 
-///
+/// Identifies a file inside an `RMSFile`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct FileId(u32);
 
@@ -206,6 +206,10 @@ impl Diagnostic {
         }
     }
 
+    pub fn with_severity(self, severity: Severity) -> Self {
+        Self { severity, ..self }
+    }
+
     pub fn add_labels(mut self, labels: impl IntoIterator<Item = Label>) -> Self {
         self.labels.extend(labels);
         self
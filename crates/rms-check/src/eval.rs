@@ -0,0 +1,118 @@
+//! Evaluate numeric arguments and `#const` values.
+
+use std::fmt::{self, Display};
+use std::ops::RangeInclusive;
+
+/// The possible values of a numeric argument or `#const`: a single number, or the range of numbers
+/// an `rnd(min,max)` call can produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Value {
+    min: i32,
+    max: i32,
+}
+
+impl Value {
+    /// Create a value that is a single number.
+    pub const fn number(number: i32) -> Self {
+        Self {
+            min: number,
+            max: number,
+        }
+    }
+
+    /// Create a value that can be any number in a range, like `rnd(min,max)`. The bounds may be
+    /// given in any order.
+    pub fn range(a: i32, b: i32) -> Self {
+        Self {
+            min: a.min(b),
+            max: a.max(b),
+        }
+    }
+
+    /// Parse a number literal or an `rnd(min,max)` call.
+    pub fn parse(value: &str) -> Option<Self> {
+        if let Ok(number) = value.parse() {
+            return Some(Self::number(number));
+        }
+        let args = value.strip_prefix("rnd(")?.strip_suffix(')')?;
+        let mut parts = args.split(',');
+        let min = parts.next()?.parse().ok()?;
+        let max = parts.next()?.parse().ok()?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self::range(min, max))
+    }
+
+    /// The smallest possible value.
+    pub const fn min(self) -> i32 {
+        self.min
+    }
+
+    /// The largest possible value.
+    pub const fn max(self) -> i32 {
+        self.max
+    }
+
+    /// Is this a single number, rather than a range?
+    pub const fn is_number(self) -> bool {
+        self.min == self.max
+    }
+
+    /// Are all possible values inside `range`?
+    pub fn is_within(self, range: RangeInclusive<i32>) -> bool {
+        range.contains(&self.min) && range.contains(&self.max)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_number() {
+            write!(f, "{}", self.min)
+        } else {
+            write!(f, "rnd({},{})", self.min, self.max)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Compatibility, ParseState, RMSFile};
+
+    #[test]
+    fn parse() {
+        assert_eq!(Value::parse("10"), Some(Value::number(10)));
+        assert_eq!(Value::parse("-3"), Some(Value::number(-3)));
+        assert_eq!(Value::parse("rnd(1,5)"), Some(Value::range(1, 5)));
+        assert_eq!(Value::parse("rnd(5,1)"), Some(Value::range(1, 5)));
+        assert_eq!(Value::parse("rnd(1,5,7)"), None);
+        assert_eq!(Value::parse("rnd(1)"), None);
+        assert_eq!(Value::parse("rnd(A,5)"), None);
+        assert_eq!(Value::parse("SOMEVAL"), None);
+        assert!(Value::range(1, 5).is_within(0..=7));
+        assert!(!Value::range(1, 8).is_within(0..=7));
+        assert_eq!(Value::range(8, 1).to_string(), "rnd(1,8)");
+    }
+
+    #[test]
+    fn evaluate_consts() {
+        let source = "#const A 3\r\n#const B A\r\n#const C rnd(10,20)\r\n#const D C\r\n#const LOOP1 LOOP2\r\n#const LOOP2 LOOP1\r\n#define E\r\n";
+        let rms = RMSFile::from_string("test.rms", source);
+        let mut state = ParseState::new(&rms, Compatibility::Conquerors);
+        for (atom, _) in crate::Parser::new(rms.file_id(), source) {
+            state.update(&atom);
+        }
+
+        assert_eq!(state.evaluate("5"), Some(Value::number(5)));
+        assert_eq!(state.evaluate("A"), Some(Value::number(3)));
+        assert_eq!(state.evaluate("B"), Some(Value::number(3)));
+        assert_eq!(state.evaluate("C"), Some(Value::range(10, 20)));
+        assert_eq!(state.evaluate("D"), Some(Value::range(10, 20)));
+        assert_eq!(state.evaluate("LOOP1"), None);
+        assert_eq!(state.evaluate("E"), None);
+        assert_eq!(state.evaluate("F"), None);
+        // Builtin constants.
+        assert_eq!(state.evaluate("GRASS"), Some(Value::number(0)));
+    }
+}
@@ -0,0 +1,361 @@
+//! Expand conditionals and random blocks, to see the script the game would interpret for a
+//! particular set of game settings.
+
+use crate::formatter::FormatOptions;
+use crate::parser::{Atom, AtomKind, Parser};
+use crate::state::{Compatibility, ParseState};
+use crate::RMSFile;
+use std::collections::{HashMap, HashSet};
+
+/// Options for expanding a script.
+///
+/// ## Example
+/// ```rust
+/// use rms_check::{expand, ExpandOptions, RMSFile};
+/// let rms = RMSFile::from_string("example.rms", "if TINY_MAP base_terrain DESERT else base_terrain GRASS endif");
+/// let options = ExpandOptions::default().define("TINY_MAP");
+/// assert_eq!(expand(&rms, options), "base_terrain DESERT\r\n");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ExpandOptions {
+    compatibility: Compatibility,
+    defines: HashSet<String>,
+    random_choices: Vec<usize>,
+    default_random_choice: usize,
+    keep_random_blocks: bool,
+    format_options: FormatOptions,
+}
+
+impl ExpandOptions {
+    /// Set the default compatibility level (default `Compatibility::Conquerors`). This determines
+    /// the `#define`s that are set by the game itself, like `UP_EXTENSION`. Scripts can override
+    /// this using `/* Compatibility: */` comments.
+    pub fn compatibility(self, compatibility: Compatibility) -> Self {
+        Self {
+            compatibility,
+            ..self
+        }
+    }
+
+    /// Treat a `#define` as set, like a map size (`LARGE_MAP`) or a player count
+    /// (`4_PLAYER_GAME`).
+    pub fn define(mut self, name: impl ToString) -> Self {
+        self.defines.insert(name.to_string());
+        self
+    }
+
+    /// Set the `percent_chance` branch to take in each `start_random` block, in source order.
+    /// Branches are 0-indexed. Blocks without a choice use the `default_random_choice()`.
+    pub fn random_choices(self, random_choices: Vec<usize>) -> Self {
+        Self {
+            random_choices,
+            ..self
+        }
+    }
+
+    /// Set the `percent_chance` branch to take in `start_random` blocks that do not have a
+    /// specific choice (default 0, the first branch). If a block has fewer branches, none of them
+    /// are taken.
+    pub fn default_random_choice(self, default_random_choice: usize) -> Self {
+        Self {
+            default_random_choice,
+            ..self
+        }
+    }
+
+    /// Whether to keep `start_random` blocks as they are, instead of taking a single branch
+    /// (default false).
+    pub fn keep_random_blocks(self, keep_random_blocks: bool) -> Self {
+        Self {
+            keep_random_blocks,
+            ..self
+        }
+    }
+
+    /// Set the options for formatting the expanded script.
+    pub fn format_options(self, format_options: FormatOptions) -> Self {
+        Self {
+            format_options,
+            ..self
+        }
+    }
+}
+
+/// A conditional or random block that we are inside of.
+#[derive(Debug, Clone, Copy)]
+enum Frame {
+    /// An `if` statement, with its `elseif` and `else` branches.
+    Condition {
+        /// Is the code around this statement included?
+        parent_active: bool,
+        /// Is the current branch included?
+        active: bool,
+        /// Was any branch included yet?
+        taken: bool,
+    },
+    /// A `start_random` block.
+    Random {
+        /// Is the code around this block included?
+        parent_active: bool,
+        /// Is the current `percent_chance` branch included?
+        active: bool,
+        /// The number of `percent_chance` branches seen so far.
+        branch: usize,
+        /// The branch to include.
+        choice: usize,
+    },
+}
+
+impl Frame {
+    const fn is_active(self) -> bool {
+        match self {
+            Self::Condition { active, .. } | Self::Random { active, .. } => active,
+        }
+    }
+}
+
+/// Walk through the atoms in the main script, and call `visit` with each atom and whether it is
+/// included for the given settings. Conditional statements are never included.
+fn walk<'a>(rms: &'a RMSFile<'a>, options: &ExpandOptions, mut visit: impl FnMut(&Atom<'a>, bool)) {
+    let mut state = ParseState::new(rms, options.compatibility);
+    // `#define`s and `#undefine`s from the script. These take precedence over the options.
+    let mut overrides = HashMap::new();
+    let mut frames: Vec<Frame> = vec![];
+    let mut num_random_blocks = 0;
+
+    for (atom, _) in Parser::new(rms.file_id(), rms.main_source()) {
+        let active = frames.last().is_none_or(|frame| frame.is_active());
+        let is_defined = |name: &str| {
+            overrides
+                .get(name)
+                .copied()
+                .unwrap_or_else(|| options.defines.contains(name) || state.has_define(name))
+        };
+
+        match atom.kind {
+            AtomKind::If { condition, .. } => {
+                let matches = is_defined(condition.value);
+                frames.push(Frame::Condition {
+                    parent_active: active,
+                    active: active && matches,
+                    taken: matches,
+                });
+            }
+            AtomKind::ElseIf { condition, .. } => {
+                let matches = is_defined(condition.value);
+                if let Some(Frame::Condition {
+                    parent_active,
+                    active,
+                    taken,
+                }) = frames.last_mut()
+                {
+                    *active = *parent_active && !*taken && matches;
+                    *taken = *taken || matches;
+                }
+            }
+            AtomKind::Else { .. } => {
+                if let Some(Frame::Condition {
+                    parent_active,
+                    active,
+                    taken,
+                }) = frames.last_mut()
+                {
+                    *active = *parent_active && !*taken;
+                    *taken = true;
+                }
+            }
+            AtomKind::EndIf { .. } => {
+                if let Some(Frame::Condition { .. }) = frames.last() {
+                    frames.pop();
+                }
+            }
+            AtomKind::StartRandom { .. } if !options.keep_random_blocks => {
+                let choice = options
+                    .random_choices
+                    .get(num_random_blocks)
+                    .copied()
+                    .unwrap_or(options.default_random_choice);
+                num_random_blocks += 1;
+                frames.push(Frame::Random {
+                    parent_active: active,
+                    active: false,
+                    branch: 0,
+                    choice,
+                });
+            }
+            AtomKind::PercentChance { .. } if !options.keep_random_blocks => {
+                if let Some(Frame::Random {
+                    parent_active,
+                    active,
+                    branch,
+                    choice,
+                }) = frames.last_mut()
+                {
+                    *active = *parent_active && *branch == *choice;
+                    *branch += 1;
+                }
+            }
+            AtomKind::EndRandom { .. } if !options.keep_random_blocks => {
+                if let Some(Frame::Random { .. }) = frames.last() {
+                    frames.pop();
+                }
+            }
+            _ if active => {
+                match atom.kind {
+                    AtomKind::Define { name, .. } => {
+                        overrides.insert(name.value, true);
+                    }
+                    AtomKind::Undefine { name, .. } => {
+                        overrides.insert(name.value, false);
+                    }
+                    _ => (),
+                }
+                state.update(&atom);
+                visit(&atom, true);
+                continue;
+            }
+            _ => (),
+        }
+        visit(&atom, false);
+    }
+}
+
+/// Expand a script for a particular set of game settings: only the `if` branches whose conditions
+/// are met and the chosen `percent_chance` branches are kept, and the conditional statements
+/// themselves are removed.
+///
+/// `#define` and `#undefine` statements in included branches are taken into account for later
+/// conditions. The result is formatted using the `format_options()`.
+pub fn expand(rms: &RMSFile<'_>, options: ExpandOptions) -> String {
+    let source = rms.main_source();
+    let mut output = vec![];
+    walk(rms, &options, |atom, included| {
+        if included {
+            let range = atom.range();
+            output.push(&source[usize::from(range.start)..usize::from(range.end)]);
+        }
+    });
+
+    options.format_options.format(&output.join("\r\n"))
+}
+
+/// Expand a script like `expand()`, but keep the layout of the source code intact: everything
+/// that is not included is replaced by spaces. Byte offsets and line numbers in the result are the
+/// same as in the original script.
+pub(crate) fn mask(rms: &RMSFile<'_>, options: &ExpandOptions) -> String {
+    let mut bytes = rms.main_source().as_bytes().to_vec();
+    walk(rms, options, |atom, included| {
+        if !included {
+            let range = atom.range();
+            for byte in &mut bytes[usize::from(range.start)..usize::from(range.end)] {
+                if *byte != b'\r' && *byte != b'\n' {
+                    *byte = b' ';
+                }
+            }
+        }
+    });
+    // Atoms start and end at character boundaries, so this only replaces entire characters.
+    String::from_utf8(bytes).expect("masking must only replace entire characters")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_str(source: &str, options: ExpandOptions) -> String {
+        expand(&RMSFile::from_string("test.rms", source), options)
+    }
+
+    #[test]
+    fn conditions() {
+        let source = "if TINY_MAP\r\n  base_terrain DESERT\r\nelseif SMALL_MAP\r\n  base_terrain SNOW\r\nelse\r\n  base_terrain GRASS\r\nendif\r\n";
+        assert_eq!(
+            expand_str(source, ExpandOptions::default()),
+            "base_terrain GRASS\r\n"
+        );
+        assert_eq!(
+            expand_str(source, ExpandOptions::default().define("SMALL_MAP")),
+            "base_terrain SNOW\r\n"
+        );
+        // Only the first matching branch is taken.
+        assert_eq!(
+            expand_str(
+                source,
+                ExpandOptions::default()
+                    .define("TINY_MAP")
+                    .define("SMALL_MAP")
+            ),
+            "base_terrain DESERT\r\n"
+        );
+    }
+
+    #[test]
+    fn defines_in_script() {
+        let source = "if LARGE_MAP #define BIG endif\r\n#define WATER\r\nif BIG\r\n  base_terrain WATER\r\nendif\r\n#undefine WATER\r\nif WATER\r\n  base_terrain DESERT\r\nendif\r\n";
+        assert_eq!(
+            expand_str(source, ExpandOptions::default()),
+            "#define WATER\r\n#undefine WATER\r\n"
+        );
+        assert_eq!(
+            expand_str(source, ExpandOptions::default().define("LARGE_MAP")),
+            "#define BIG\r\n#define WATER\r\nbase_terrain WATER\r\n#undefine WATER\r\n"
+        );
+
+        // Builtin defines depend on the compatibility.
+        let source = "if UP_EXTENSION\r\n  base_terrain DESERT\r\nendif\r\n";
+        assert_eq!(expand_str(source, ExpandOptions::default()), "");
+        assert_eq!(
+            expand_str(
+                source,
+                ExpandOptions::default().compatibility(Compatibility::UserPatch15)
+            ),
+            "base_terrain DESERT\r\n"
+        );
+    }
+
+    #[test]
+    fn random_blocks() {
+        let source = "start_random\r\n  percent_chance 50 #const A 1\r\n  percent_chance 50 #const A 2\r\nend_random\r\nstart_random\r\n  percent_chance 30 #const B 1\r\n  percent_chance 30\r\n    start_random\r\n      percent_chance 50 #const B 2\r\n      percent_chance 50 #const B 3\r\n    end_random\r\n  percent_chance 40 #const B 4\r\nend_random\r\n";
+        assert_eq!(
+            expand_str(source, ExpandOptions::default()),
+            "#const A 1\r\n#const B 1\r\n"
+        );
+        assert_eq!(
+            expand_str(source, ExpandOptions::default().default_random_choice(1)),
+            "#const A 2\r\n#const B 3\r\n"
+        );
+        assert_eq!(
+            expand_str(
+                source,
+                ExpandOptions::default().random_choices(vec![0, 1, 0])
+            ),
+            "#const A 1\r\n#const B 2\r\n"
+        );
+        // No branch is taken if the choice is out of range.
+        assert_eq!(
+            expand_str(source, ExpandOptions::default().default_random_choice(2)),
+            "#const B 4\r\n"
+        );
+        assert_eq!(
+            expand_str(
+                "if A start_random percent_chance 50 #const B 1 end_random endif",
+                ExpandOptions::default()
+                    .define("A")
+                    .keep_random_blocks(true)
+            ),
+            "start_random\r\n  percent_chance 50 #const B 1\r\nend_random\r\n"
+        );
+    }
+
+    #[test]
+    fn mask_source() {
+        let source = "if TINY_MAP\r\n  base_terrain DESERT /* é */\r\nelse\r\n  base_terrain GRASS\r\nendif\r\n";
+        let rms = RMSFile::from_string("test.rms", source);
+        let masked = mask(&rms, &ExpandOptions::default().define("TINY_MAP"));
+        assert_eq!(masked.len(), source.len());
+        assert_eq!(
+            masked,
+            "           \r\n  base_terrain DESERT /* é */\r\n    \r\n                    \r\n     \r\n"
+        );
+    }
+}
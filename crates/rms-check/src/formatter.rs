@@ -227,7 +227,7 @@ impl<'file> Formatter<'file> {
                         .max(name.value.len() + indent * self.options.tab_size as usize),
                     arg_width: width
                         .arg_width
-                        .max(arguments.get(0).map(|word| word.value.len()).unwrap_or(0)),
+                        .max(arguments.first().map(|word| word.value.len()).unwrap_or(0)),
                 },
                 AtomKind::If { .. } => {
                     indent += 1;
@@ -539,13 +539,13 @@ impl<'file> Formatter<'file> {
             // - Maintain padding lines.
             // - Do not add linebreak before comments at the end of a line
 
-            if self.has_padding_line(&prev, &atom) {
+            if self.has_padding_line(prev, &atom) {
                 // A padding line may already have been added by the formatter for another reason,
                 // like after top-level `endif`s. Don't add another in that case.
                 if !self.result.ends_with("\r\n\r\n") {
                     self.newline();
                 }
-            } else if self.should_comment_be_on_same_line(&prev, &atom) {
+            } else if self.should_comment_be_on_same_line(prev, &atom) {
                 if self.result.ends_with("\r\n") {
                     self.result.pop();
                     self.result.pop();
@@ -0,0 +1,213 @@
+//! Incremental checking for scripts that are being edited.
+
+use crate::checker::CheckerSnapshot;
+use crate::diagnostic::{ByteIndex, Diagnostic};
+use crate::parser::Parser;
+use crate::{RMSCheck, RMSCheckResult, RMSFile};
+
+/// The number of atoms to check in between checkpoints.
+const CHECKPOINT_INTERVAL: usize = 256;
+
+/// The checker state right before an atom.
+struct Checkpoint {
+    /// The location of the atom that comes after this checkpoint.
+    next_atom: std::ops::Range<ByteIndex>,
+    /// The lint and parser state.
+    checker: CheckerSnapshot,
+    /// The number of diagnostics that were reported before this checkpoint.
+    diagnostics: usize,
+}
+
+/// Check a script repeatedly while it is being edited.
+///
+/// The first check runs on the entire script. Later checks compare the new source code to the
+/// previously checked version, and resume from the last checkpoint before the first change,
+/// reusing the diagnostics that were reported up to that point.
+///
+/// ## Example
+/// ```rust
+/// use rms_check::{IncrementalCheck, RMSCheck, RMSFile};
+/// let mut check = IncrementalCheck::new(RMSCheck::default);
+/// let source = "<OBJECTS_GENERATION> create_object SCOUT { number_of_objects 1 }";
+/// let rms = RMSFile::from_string("example.rms", source);
+/// assert!(!check.check(&rms).has_warnings());
+/// let rms = RMSFile::from_string("example.rms", source.replace("objects", "object"));
+/// assert!(check.check(&rms).has_warnings());
+/// ```
+pub struct IncrementalCheck {
+    make_check: Box<dyn Fn() -> RMSCheck + Send>,
+    /// The previously checked source code, or None if nothing was checked yet.
+    source: Option<String>,
+    checkpoints: Vec<Checkpoint>,
+    /// The diagnostics reported by the lints.
+    diagnostics: Vec<Diagnostic>,
+    /// The diagnostics after applying suppression comments.
+    result: Vec<Diagnostic>,
+}
+
+impl IncrementalCheck {
+    /// Create an incremental checker. `make_check` is called to create a fresh checker when a
+    /// script has to be checked from the start.
+    pub fn new(make_check: impl Fn() -> RMSCheck + Send + 'static) -> Self {
+        Self {
+            make_check: Box::new(make_check),
+            source: None,
+            checkpoints: vec![],
+            diagnostics: vec![],
+            result: vec![],
+        }
+    }
+
+    /// Check a new version of the script.
+    pub fn check(&mut self, rms: &RMSFile<'_>) -> RMSCheckResult {
+        let source = rms.main_source();
+        let changed_at = match &self.source {
+            Some(previous) if previous == source => {
+                return RMSCheckResult {
+                    diagnostics: self.result.clone(),
+                };
+            }
+            Some(previous) => ByteIndex::from(
+                previous
+                    .bytes()
+                    .zip(source.bytes())
+                    .take_while(|(a, b)| a == b)
+                    .count(),
+            ),
+            None => ByteIndex::from(0),
+        };
+
+        // The parser looks ahead one word to find the end of an atom, so the atom after a
+        // checkpoint and the whitespace following it must be unchanged as well.
+        let valid = self
+            .checkpoints
+            .iter()
+            .position(|checkpoint| checkpoint.next_atom.end >= changed_at)
+            .unwrap_or(self.checkpoints.len());
+        self.checkpoints.truncate(valid);
+
+        // Restoring consumes the checkpoint, so a new one is taken at the first atom.
+        let (mut checker, start, resumed) = match self.checkpoints.pop() {
+            Some(checkpoint) => {
+                self.diagnostics.truncate(checkpoint.diagnostics);
+                let start = checkpoint.next_atom.start;
+                (checkpoint.checker.restore(rms), start, true)
+            }
+            None => {
+                self.diagnostics.clear();
+                let checker = (self.make_check)().checker.build(rms);
+                (checker, ByteIndex::from(0), false)
+            }
+        };
+
+        let parser = Parser::new_at(rms.file_id(), source, start);
+        for (index, (atom, parse_errors)) in parser.enumerate() {
+            if (index > 0 || resumed) && index % CHECKPOINT_INTERVAL == 0 {
+                if let Some(snapshot) = checker.snapshot() {
+                    self.checkpoints.push(Checkpoint {
+                        next_atom: atom.range(),
+                        checker: snapshot,
+                        diagnostics: self.diagnostics.len(),
+                    });
+                }
+            }
+            self.diagnostics
+                .extend(checker.check_atom(&atom, parse_errors));
+        }
+
+        self.source = Some(source.to_string());
+        // Diagnostics about the script as a whole are recomputed every time, so they are not
+        // stored with the checkpoints.
+        let mut diagnostics = self.diagnostics.clone();
+        diagnostics.extend(checker.finish());
+        self.result = checker.apply_suppressions(diagnostics);
+        RMSCheckResult {
+            diagnostics: self.result.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full_check(source: &str) -> Vec<String> {
+        let rms = RMSFile::from_string("test.rms", source);
+        RMSCheck::default()
+            .check(&rms)
+            .iter()
+            .map(|diagnostic| format!("{:?}", diagnostic))
+            .collect()
+    }
+
+    fn incremental_check(check: &mut IncrementalCheck, source: &str) -> Vec<String> {
+        let rms = RMSFile::from_string("test.rms", source);
+        check
+            .check(&rms)
+            .iter()
+            .map(|diagnostic| format!("{:?}", diagnostic))
+            .collect()
+    }
+
+    #[test]
+    fn matches_full_check() -> anyhow::Result<()> {
+        let original = std::fs::read_to_string("tests/rms/Dry Arabia.rms")?;
+        let mut check = IncrementalCheck::new(RMSCheck::default);
+        assert_eq!(
+            incremental_check(&mut check, &original),
+            full_check(&original)
+        );
+
+        let edits = [
+            // Typo in an attribute near the end.
+            ("number_of_objects", "number_of_object"),
+            // Unbalance the nesting in the middle.
+            ("start_random", "if"),
+            // Break a command at the start.
+            ("random_placement", "random_placemen"),
+            // Introduce an unknown #const.
+            ("create_object", "create_object UNKNOWN_CONST"),
+        ];
+        let mut source = original.clone();
+        for (from, to) in edits.iter() {
+            let index = source.rfind(from).unwrap();
+            source.replace_range(index..index + from.len(), to);
+            assert_eq!(incremental_check(&mut check, &source), full_check(&source));
+        }
+
+        // Append some text.
+        source.push_str("\r\nend_random }\r\n");
+        assert_eq!(incremental_check(&mut check, &source), full_check(&source));
+        // And undo everything.
+        assert_eq!(
+            incremental_check(&mut check, &original),
+            full_check(&original)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn resume_in_nested_context() {
+        let mut source = String::new();
+        source.push_str("#const MY_TERRAIN 10\r\n#define MY_DEFINE\r\n<LAND_GENERATION>\r\n");
+        for _ in 0..100 {
+            source
+                .push_str("if MY_DEFINE\r\n  create_land { terrain_type MY_TERRAIN }\r\nendif\r\n");
+        }
+        source.push_str("start_random\r\npercent_chance 50\r\ncreate_land {\r\n");
+        let prefix_len = source.len();
+        source.push_str("  terrain_type MY_TERRAIN\r\n}\r\nend_random\r\n");
+
+        let mut check = IncrementalCheck::new(RMSCheck::default);
+        assert_eq!(incremental_check(&mut check, &source), full_check(&source));
+        assert!(!check.checkpoints.is_empty());
+
+        source.insert_str(
+            prefix_len,
+            "  land_percent MY_TERRAIN\r\n  unknown_attr\r\n",
+        );
+        assert_eq!(incremental_check(&mut check, &source), full_check(&source));
+        source.push_str("}\r\nendif");
+        assert_eq!(incremental_check(&mut check, &source), full_check(&source));
+    }
+}
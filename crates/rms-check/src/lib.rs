@@ -7,25 +7,46 @@
 // #![warn(missing_docs)]
 #![warn(unused)]
 
+mod block;
 mod checker;
+mod config;
 mod diagnostic;
+mod eval;
+mod expand;
 mod formatter;
+mod incremental;
 mod lints;
 mod parser;
+mod persistent;
 mod state;
+mod suppressions;
+mod sweep;
+mod syntax;
 mod tokenizer;
 mod tokens;
 
+pub use crate::block::{Block, BlockAttribute};
 use crate::checker::Checker;
 pub use crate::checker::{CheckerBuilder, Lint};
-pub use crate::diagnostic::{ByteIndex, Diagnostic, FileId, Fix, Severity, SourceLocation};
+pub use crate::config::{Config, ConfigError, LintLevel, CONFIG_FILE_NAME};
+pub use crate::diagnostic::{ByteIndex, Diagnostic, FileId, Fix, Label, Severity, SourceLocation};
+pub use crate::eval::Value;
+pub use crate::expand::{expand, ExpandOptions};
 pub use crate::formatter::{format, FormatOptions};
+pub use crate::incremental::IncrementalCheck;
 pub use crate::parser::{Atom, AtomKind, ParseErrorKind, Parser};
-pub use crate::state::{Compatibility, Nesting, ParseState};
+pub use crate::state::{Compatibility, ConstDefinition, Nesting, ParseState};
+pub use crate::sweep::{
+    Configuration, ConfigurationSweep, SweepDiagnostic, SweepResult, DEFAULT_MAX_CONFIGURATIONS,
+};
+pub use crate::syntax::{SyntaxElement, SyntaxKind, SyntaxNode, SyntaxTree, Trivia};
 pub use crate::tokenizer::Word;
-pub use crate::tokens::{ArgType, TokenContext, TokenType, TOKENS};
+pub use crate::tokens::{
+    extend_tokens, ArgRange, ArgType, TokenArg, TokenCategory, TokenContext, TokenDatabaseError,
+    TokenType, TOKENS,
+};
 use encoding_rs::Encoding;
-use std::{borrow::Cow, fs::File, io, path::Path};
+use std::{borrow::Cow, collections::HashMap, fs::File, io, path::Path};
 use zip::ZipArchive;
 
 fn to_chardet_string(bytes: Vec<u8>) -> String {
@@ -98,49 +119,91 @@ impl<'source> FileData<'source> {
     }
 }
 
+/// A random_map.def file that provides builtin `#const` and `#define` names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DefinitionFile {
+    /// The file containing the definitions.
+    pub file: FileId,
+    /// A `#define` name that must be set for these definitions to be available. For example,
+    /// UserPatch constants are only available inside `if UP_EXTENSION` when targeting AoC.
+    pub condition: Option<&'static str>,
+}
+
+impl DefinitionFile {
+    const fn new(file: FileId) -> Self {
+        Self {
+            file,
+            condition: None,
+        }
+    }
+
+    const fn when(file: FileId, condition: &'static str) -> Self {
+        Self {
+            file,
+            condition: Some(condition),
+        }
+    }
+}
+
+/// How a custom random_map.def file is combined with the builtin definitions.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionsMode {
+    /// Use the custom definitions in addition to the builtin ones.
+    #[default]
+    Layer,
+    /// Use the custom definitions instead of the builtin ones.
+    Override,
+}
+
 /// Container for a random map script, generalising various formats.
 #[derive(Debug)]
 pub struct RMSFile<'source> {
     files: Vec<FileData<'source>>,
-    /// File ID of the AoC random_map.def file.
-    def_aoc: FileId,
-    /// File ID of the HD Edition random_map.def file.
-    def_hd: FileId,
-    /// File ID of the Definitive Edition random_map.def file.
-    def_de: FileId,
-    /// File ID of the WololoKingdoms random_map.def file.
-    def_wk: FileId,
+    /// The random_map.def files for each compatibility level, in the order they are loaded.
+    definitions: HashMap<Compatibility, Vec<DefinitionFile>>,
+    /// Whether this is a ZR@ map, either zipped or unpacked.
+    is_zip_rms: bool,
 }
 
 impl<'source> RMSFile<'source> {
     fn new(mut files: Vec<FileData<'source>>) -> Self {
-        let def_aoc = FileId::new(files.len() as u32);
-        files.push(FileData::new(
-            "random_map.def".into(),
-            include_str!("def_aoc.rms").into(),
-        ));
-        let def_hd = FileId::new(files.len() as u32);
-        files.push(FileData::new(
-            "random_map.def".into(),
-            include_str!("def_hd.rms").into(),
-        ));
-        let def_de = FileId::new(files.len() as u32);
-        files.push(FileData::new(
-            "random_map.def".into(),
-            include_str!("def_de.rms").into(),
-        ));
-        let def_wk = FileId::new(files.len() as u32);
-        files.push(FileData::new(
-            "random_map.def".into(),
-            include_str!("def_wk.rms").into(),
-        ));
+        let mut add_definitions = |source: &'static str| {
+            let id = FileId::new(files.len() as u32);
+            files.push(FileData::new("random_map.def".into(), source.into()));
+            id
+        };
+        let def_aoc = add_definitions(include_str!("def_aoc.rms"));
+        let def_up15 = add_definitions(include_str!("def_up15.rms"));
+        let def_hd = add_definitions(include_str!("def_hd.rms"));
+        let def_de = add_definitions(include_str!("def_de.rms"));
+        let def_wk = add_definitions(include_str!("def_wk.rms"));
+
+        let aoc = vec![
+            DefinitionFile::new(def_aoc),
+            DefinitionFile::when(def_up15, "UP_EXTENSION"),
+        ];
+        let mut definitions = HashMap::new();
+        definitions.insert(Compatibility::All, aoc.clone());
+        definitions.insert(Compatibility::Conquerors, aoc.clone());
+        definitions.insert(Compatibility::UserPatch14, aoc);
+        definitions.insert(
+            Compatibility::UserPatch15,
+            vec![DefinitionFile::new(def_aoc), DefinitionFile::new(def_up15)],
+        );
+        definitions.insert(Compatibility::HDEdition, vec![DefinitionFile::new(def_hd)]);
+        definitions.insert(
+            Compatibility::DefinitiveEdition,
+            vec![DefinitionFile::new(def_de)],
+        );
+        definitions.insert(
+            Compatibility::WololoKingdoms,
+            vec![DefinitionFile::new(def_wk)],
+        );
 
         Self {
             files,
-            def_aoc,
-            def_hd,
-            def_de,
-            def_wk,
+            definitions,
+            is_zip_rms: false,
         }
     }
 
@@ -185,7 +248,10 @@ impl<'source> RMSFile<'source> {
             }
         }
 
-        Ok(Self::new(files))
+        Ok(Self {
+            is_zip_rms: true,
+            ..Self::new(files)
+        })
     }
 
     /// Create an RMSFile from a string of bytes containing a ZR@ map.
@@ -208,7 +274,10 @@ impl<'source> RMSFile<'source> {
             }
         }
 
-        Ok(Self::new(files))
+        Ok(Self {
+            is_zip_rms: true,
+            ..Self::new(files)
+        })
     }
 
     /// Create an RMSFile from a file path containing a ZR@ map.
@@ -218,14 +287,90 @@ impl<'source> RMSFile<'source> {
 
     // pub fn from_bytes(name: impl AsRef<str>, source: &[u8]) -> io::Result<Self> {}
 
-    /// Get the definitions file for this map.
-    pub(crate) fn definitions(&self, compatibility: Compatibility) -> (FileId, &str) {
-        match compatibility {
-            Compatibility::WololoKingdoms => (self.def_wk, self.source(self.def_wk)),
-            Compatibility::HDEdition => (self.def_hd, self.source(self.def_hd)),
-            Compatibility::DefinitiveEdition => (self.def_de, self.source(self.def_de)),
-            _ => (self.def_aoc, self.source(self.def_aoc)),
+    /// Add a custom random_map.def file for a compatibility level, for example to support
+    /// constants added by a mod.
+    ///
+    /// ## Example
+    /// ```rust
+    /// use rms_check::{Compatibility, DefinitionsMode, RMSCheck, RMSFile};
+    /// let source = "<OBJECTS_GENERATION> create_object MOD_UNIT { number_of_objects 1 }";
+    /// let mut rms = RMSFile::from_string("example.rms", source);
+    /// rms.add_definitions(
+    ///     Compatibility::DefinitiveEdition,
+    ///     "mod.def",
+    ///     "#const MOD_UNIT 1500",
+    ///     DefinitionsMode::Layer,
+    /// );
+    /// let result = RMSCheck::default()
+    ///     .compatibility(Compatibility::DefinitiveEdition)
+    ///     .check(&rms);
+    /// assert!(!result.has_warnings());
+    /// ```
+    pub fn add_definitions(
+        &mut self,
+        compatibility: Compatibility,
+        name: impl ToString,
+        source: impl Into<Cow<'source, str>>,
+        mode: DefinitionsMode,
+    ) -> FileId {
+        let id = FileId::new(self.files.len() as u32);
+        self.files
+            .push(FileData::new(name.to_string(), source.into()));
+        let definitions = self.definitions.entry(compatibility).or_default();
+        if mode == DefinitionsMode::Override {
+            definitions.clear();
         }
+        definitions.push(DefinitionFile::new(id));
+        id
+    }
+
+    /// Add a custom random_map.def file for every compatibility level, so it is also used when a
+    /// script selects a level with a `/* Compatibility: */` comment.
+    pub fn add_definitions_for_all(
+        &mut self,
+        name: impl ToString,
+        source: impl Into<Cow<'source, str>>,
+        mode: DefinitionsMode,
+    ) -> FileId {
+        let id = FileId::new(self.files.len() as u32);
+        self.files
+            .push(FileData::new(name.to_string(), source.into()));
+        // Every level has builtin definitions, so they are all in the map.
+        for definitions in self.definitions.values_mut() {
+            if mode == DefinitionsMode::Override {
+                definitions.clear();
+            }
+            definitions.push(DefinitionFile::new(id));
+        }
+        id
+    }
+
+    /// Load a custom random_map.def file from disk, for a compatibility level.
+    pub fn load_definitions(
+        &mut self,
+        compatibility: Compatibility,
+        path: impl AsRef<Path>,
+        mode: DefinitionsMode,
+    ) -> io::Result<FileId> {
+        let source = to_chardet_string(std::fs::read(path.as_ref())?);
+        Ok(self.add_definitions(compatibility, path.as_ref().to_string_lossy(), source, mode))
+    }
+
+    /// Load a custom random_map.def file from disk, for every compatibility level.
+    pub fn load_definitions_for_all(
+        &mut self,
+        path: impl AsRef<Path>,
+        mode: DefinitionsMode,
+    ) -> io::Result<FileId> {
+        let source = to_chardet_string(std::fs::read(path.as_ref())?);
+        Ok(self.add_definitions_for_all(path.as_ref().to_string_lossy(), source, mode))
+    }
+
+    /// Get the definitions files for this map.
+    pub(crate) fn definitions(&self, compatibility: Compatibility) -> &[DefinitionFile] {
+        self.definitions
+            .get(&compatibility)
+            .map_or(&[], |files| files.as_slice())
     }
 
     /// Get the [`FileId`] of the main script in this map.
@@ -268,8 +413,20 @@ impl<'source> RMSFile<'source> {
         })
     }
 
-    fn is_zip_rms(&self) -> bool {
-        self.files[0].name.starts_with("ZR@")
+    const fn is_zip_rms(&self) -> bool {
+        self.is_zip_rms
+    }
+
+    /// Create a copy of this map with different source code for the main script.
+    pub(crate) fn with_main_source(&self, source: String) -> RMSFile<'source> {
+        let mut files = self.files.clone();
+        let main = &mut files[self.file_id().to_usize()];
+        *main = FileData::new(main.name.clone(), Cow::Owned(source));
+        RMSFile {
+            files,
+            definitions: self.definitions.clone(),
+            is_zip_rms: self.is_zip_rms,
+        }
     }
 
     /// Get the ByteIndex for a line/column pair. Returns None if the line or column is out of
@@ -287,6 +444,11 @@ impl<'source> RMSFile<'source> {
             .and_then(|file| file.get_location(index))
     }
 
+    /// Parse a file into a lossless syntax tree.
+    pub fn syntax_tree(&self, file: FileId) -> SyntaxTree<'_> {
+        SyntaxTree::parse(file, self.source(file))
+    }
+
     /// Get the parsing state at a particular offset.
     pub fn parse_to(
         &self,
@@ -336,22 +498,52 @@ impl IntoIterator for RMSCheckResult {
     }
 }
 
-///
+/// A configurable lint runner for random map scripts.
 pub struct RMSCheck {
     checker: CheckerBuilder,
 }
 
+/// The names of the lints that are run by [`RMSCheck::default`].
+pub const LINT_NAMES: &[&str] = &[
+    "arg-types",
+    "actor-areas-match",
+    "attribute-case",
+    "attribute-context",
+    "comment-contents",
+    "compatibility",
+    "conflicting-attributes",
+    "dead-branch",
+    "include",
+    "incorrect-section",
+    "land-capacity",
+    "land-ids-match",
+    "percent-chance",
+    "required-attributes",
+    "token-category",
+    "unknown-attribute",
+    "unused-definition",
+];
+
 impl Default for RMSCheck {
     fn default() -> RMSCheck {
         RMSCheck::new()
             .with_lint(Box::new(lints::ArgTypesLint::new()))
             .with_lint(Box::new(lints::ActorAreasMatchLint::new()))
             .with_lint(Box::new(lints::AttributeCaseLint {}))
+            .with_lint(Box::new(lints::AttributeContextLint::new()))
             .with_lint(Box::new(lints::CommentContentsLint::new()))
             .with_lint(Box::new(lints::CompatibilityLint::new()))
+            .with_lint(Box::new(lints::ConflictingAttributesLint::new()))
+            .with_lint(Box::new(lints::DeadBranchLint::new()))
             .with_lint(Box::new(lints::IncludeLint::new()))
             .with_lint(Box::new(lints::IncorrectSectionLint::new()))
+            .with_lint(Box::new(lints::LandCapacityLint::new()))
+            .with_lint(Box::new(lints::LandIdsMatchLint::new()))
+            .with_lint(Box::new(lints::PercentChanceLint::new()))
+            .with_lint(Box::new(lints::RequiredAttributesLint::new()))
+            .with_lint(Box::new(lints::TokenCategoryLint::new()))
             .with_lint(Box::new(lints::UnknownAttributeLint {}))
+            .with_lint(Box::new(lints::UnusedDefinitionLint::new()))
     }
 }
 
@@ -387,29 +579,13 @@ impl RMSCheck {
         let mut diagnostics = vec![];
 
         let parser = Parser::new(rms.file_id(), rms.main_source());
-        for (atom, parse_warning) in parser {
-            let warnings = checker.write_atom(&atom);
-            for w in parse_warning {
-                if w.kind == ParseErrorKind::MissingCommandArgs {
-                    // Handled by arg-types lint
-                    continue;
-                }
-
-                let overlaps_parse_warning = |lint_warning: &Diagnostic| {
-                    let lint_range = lint_warning.location().range();
-                    let parse_range = w.location.range();
-                    lint_range.contains(&parse_range.start) && lint_range.contains(&parse_range.end)
-                };
-                if warnings.iter().all(overlaps_parse_warning) {
-                    diagnostics.push(
-                        Diagnostic::parse_error(w.location, format!("{:?}", w.kind))
-                            .with_code("parse"),
-                    );
-                }
-            }
-            diagnostics.extend(warnings);
+        for (atom, parse_errors) in parser {
+            diagnostics.extend(checker.check_atom(&atom, parse_errors));
         }
+        diagnostics.extend(checker.finish());
 
-        RMSCheckResult { diagnostics }
+        RMSCheckResult {
+            diagnostics: checker.apply_suppressions(diagnostics),
+        }
     }
 }
@@ -1,55 +1,190 @@
-use crate::diagnostic::{Diagnostic, SourceLocation};
-use crate::{Atom, AtomKind, Lint, ParseState};
+use super::branches::{excludes, BranchId, Branches};
+use crate::diagnostic::{Diagnostic, Label, SourceLocation};
+use crate::{Atom, AtomKind, Block, Lint, ParseState, Word};
+use cow_utils::CowUtils;
+use std::collections::{HashMap, HashSet};
 
-#[derive(Default)]
+/// An `actor_area` id in the script.
+#[derive(Clone)]
+struct ActorArea {
+    /// The id.
+    id: i32,
+    /// The argument that holds the id.
+    location: SourceLocation,
+    /// The conditional and random blocks that the id is inside of.
+    branches: Vec<BranchId>,
+}
+
+/// Check that actor areas are defined when they are used, and used when they are defined.
+///
+/// All definitions and references are collected first, so references may come before the
+/// definition. Definitions in branches that can not be used together with a reference do not count.
+#[derive(Default, Clone)]
 pub struct ActorAreasMatchLint {
-    actor_areas: Vec<(i32, SourceLocation)>,
+    /// The actor areas created by `actor_area` and `create_actor_area`.
+    definitions: Vec<ActorArea>,
+    /// The actor areas used by `actor_area_to_place_in` and `avoid_actor_area`.
+    references: Vec<ActorArea>,
+    /// Whether the script uses `avoid_all_actor_areas`, which uses every actor area.
+    avoids_all: bool,
+    /// The branches that we are currently inside of.
+    branches: Branches,
 }
 
 impl ActorAreasMatchLint {
     pub fn new() -> Self {
         Default::default()
     }
+
+    /// Read an actor area id, if it is a single known number.
+    fn actor_area(&self, state: &ParseState<'_>, arg: &Word<'_>) -> Option<ActorArea> {
+        let id = match state.evaluate(arg.value) {
+            Some(value) if value.is_number() => value.min(),
+            _ => return None,
+        };
+        Some(ActorArea {
+            id,
+            location: arg.location,
+            branches: self.branches.current(),
+        })
+    }
+
+    fn check_reference(&self, reference: &ActorArea) -> Option<Diagnostic> {
+        let definitions: Vec<_> = self
+            .definitions
+            .iter()
+            .filter(|definition| definition.id == reference.id)
+            .collect();
+        if definitions.is_empty() {
+            return Some(Diagnostic::warning(
+                reference.location,
+                format_args!("Actor area {} is never defined", reference.id),
+            ));
+        }
+        if definitions
+            .iter()
+            .all(|definition| excludes(&definition.branches, &reference.branches))
+        {
+            return Some(
+                Diagnostic::warning(
+                    reference.location,
+                    format_args!(
+                        "Actor area {} is only defined in branches that can not be used together with this one",
+                        reference.id
+                    ),
+                )
+                .add_labels(
+                    definitions
+                        .iter()
+                        .map(|definition| Label::new(definition.location, "Defined here")),
+                ),
+            );
+        }
+        None
+    }
+
+    fn check_unused(&self) -> Vec<Diagnostic> {
+        if self.avoids_all {
+            return vec![];
+        }
+        let used: HashSet<i32> = self
+            .references
+            .iter()
+            .map(|reference| reference.id)
+            .collect();
+        let mut unused: HashMap<i32, Vec<&ActorArea>> = HashMap::new();
+        for definition in &self.definitions {
+            if !used.contains(&definition.id) {
+                unused.entry(definition.id).or_default().push(definition);
+            }
+        }
+        let mut unused: Vec<_> = unused.into_iter().collect();
+        unused.sort_by_key(|(_, definitions)| definitions[0].location.start());
+
+        unused
+            .into_iter()
+            .map(|(id, definitions)| {
+                Diagnostic::warning(
+                    definitions[0].location,
+                    format_args!("Actor area {} is never used", id),
+                )
+                .add_labels(
+                    definitions[1..]
+                        .iter()
+                        .map(|other| Label::new(other.location, "Also defined here")),
+                )
+            })
+            .collect()
+    }
 }
 
 impl Lint for ActorAreasMatchLint {
     fn name(&self) -> &'static str {
         "actor-areas-match"
     }
-    fn lint_atom(&mut self, _state: &mut ParseState<'_>, atom: &Atom<'_>) -> Vec<Diagnostic> {
-        if let AtomKind::Command { name, arguments } = &atom.kind {
-            let mut warnings = vec![];
-
-            match name.value {
-                "actor_area" if !arguments.is_empty() => {
-                    if let Ok(n) = arguments[0].value.parse::<i32>() {
-                        self.actor_areas.push((n, arguments[0].location));
-                    }
-                }
-                "actor_area_to_place_in" | "avoid_actor_area" if !arguments.is_empty() => {
-                    if let Ok(to_place_in) = arguments[0].value.parse::<i32>() {
-                        if self.actor_areas.iter().all(|(n, _)| *n != to_place_in) {
-                            warnings.push(Diagnostic::warning(
-                                arguments[0].location,
-                                format_args!("Actor area {} is never defined", to_place_in),
-                            ));
-                        }
-                    }
-                }
-                _ => (),
-            }
+    fn snapshot(&self) -> Option<Box<dyn Lint>> {
+        Some(Box::new(self.clone()))
+    }
+    fn lint_atom(&mut self, state: &mut ParseState<'_>, atom: &Atom<'_>) -> Vec<Diagnostic> {
+        self.branches.update(atom);
+        let (name, arguments) = match &atom.kind {
+            AtomKind::Command { name, arguments } => (name, arguments),
+            _ => return vec![],
+        };
 
-            warnings
-        } else {
-            Default::default()
+        match name.value.cow_to_ascii_lowercase().as_ref() {
+            "actor_area" => {
+                let definition = arguments
+                    .first()
+                    .and_then(|arg| self.actor_area(state, arg));
+                self.definitions.extend(definition);
+            }
+            "create_actor_area" => {
+                // `create_actor_area X Y ActorAreaId Radius`
+                let definition = arguments.get(2).and_then(|arg| self.actor_area(state, arg));
+                self.definitions.extend(definition);
+            }
+            "actor_area_to_place_in" | "avoid_actor_area" => {
+                let reference = arguments
+                    .first()
+                    .and_then(|arg| self.actor_area(state, arg));
+                self.references.extend(reference);
+            }
+            "avoid_all_actor_areas" => self.avoids_all = true,
+            _ => (),
+        }
+        vec![]
+    }
+    fn lint_block(&mut self, _state: &mut ParseState<'_>, block: &Block<'_>) -> Vec<Diagnostic> {
+        if block.has_attribute("actor_area") {
+            return vec![];
         }
+        block
+            .attributes_named("actor_area_radius")
+            .map(|radius| {
+                Diagnostic::warning(
+                    radius.name.location,
+                    "`actor_area_radius` has no effect without an `actor_area` in the same block",
+                )
+            })
+            .collect()
+    }
+    fn lint_end(&mut self, _state: &mut ParseState<'_>) -> Vec<Diagnostic> {
+        let mut warnings: Vec<_> = self
+            .references
+            .iter()
+            .filter_map(|reference| self.check_reference(reference))
+            .collect();
+        warnings.extend(self.check_unused());
+        warnings
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{RMSCheck, RMSFile, Severity};
+    use crate::lints::lint_messages;
+    use crate::{Compatibility, RMSCheck, RMSFile, Severity};
 
     #[test]
     fn actor_areas_match() {
@@ -89,4 +224,80 @@ mod tests {
         assert_eq!(second.message(), "Actor area 18 is never defined");
         assert!(warnings.next().is_none());
     }
+
+    #[test]
+    fn order_independent() {
+        assert_eq!(
+            lint_messages(
+                ActorAreasMatchLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<OBJECTS_GENERATION>\r\ncreate_object GOLD { actor_area_to_place_in 1 }\r\ncreate_object TOWN_CENTER { actor_area 1 }\r\n"
+            ),
+            Vec::<String>::new()
+        );
+        // `create_actor_area` also defines actor areas, and constants are evaluated.
+        assert_eq!(
+            lint_messages(
+                ActorAreasMatchLint::new(),
+                Compatibility::DefinitiveEdition,
+                "#const NEAR_TC 5\r\n<OBJECTS_GENERATION>\r\ncreate_actor_area 50 50 NEAR_TC 10\r\ncreate_object GOLD { avoid_actor_area 5 }\r\n"
+            ),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn branches() {
+        assert_eq!(
+            lint_messages(
+                ActorAreasMatchLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<OBJECTS_GENERATION>\r\nif TINY_MAP\r\ncreate_object TOWN_CENTER { actor_area 1 }\r\nelse\r\ncreate_object GOLD { avoid_actor_area 1 }\r\nendif\r\n"
+            ),
+            vec![
+                "Actor area 1 is only defined in branches that can not be used together with this one",
+            ]
+        );
+        assert_eq!(
+            lint_messages(
+                ActorAreasMatchLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<OBJECTS_GENERATION>\r\nif TINY_MAP\r\ncreate_object TOWN_CENTER { actor_area 1 }\r\nendif\r\ncreate_object GOLD { avoid_actor_area 1 }\r\n"
+            ),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn unused() {
+        assert_eq!(
+            lint_messages(
+                ActorAreasMatchLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<OBJECTS_GENERATION>\r\ncreate_object TOWN_CENTER { actor_area 1 }\r\ncreate_object TOWN_CENTER { actor_area 1 }\r\ncreate_object GOLD { actor_area 2 }\r\ncreate_object GOLD { avoid_actor_area 2 }\r\n"
+            ),
+            vec!["Actor area 1 is never used"]
+        );
+        // `avoid_all_actor_areas` uses every actor area.
+        assert_eq!(
+            lint_messages(
+                ActorAreasMatchLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<OBJECTS_GENERATION>\r\ncreate_object TOWN_CENTER { actor_area 1 }\r\ncreate_object GOLD { avoid_all_actor_areas }\r\n"
+            ),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn radius_without_actor_area() {
+        assert_eq!(
+            lint_messages(
+                ActorAreasMatchLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<OBJECTS_GENERATION>\r\ncreate_object TOWN_CENTER {\r\n  actor_area 1\r\n  actor_area_radius 10\r\n}\r\ncreate_object GOLD {\r\n  actor_area_radius 10\r\n  actor_area_to_place_in 1\r\n}\r\n"
+            ),
+            vec!["`actor_area_radius` has no effect without an `actor_area` in the same block"]
+        );
+    }
 }
@@ -1,9 +1,11 @@
-use crate::diagnostic::{Diagnostic, Fix};
-use crate::{ArgType, Atom, AtomKind, Lint, ParseState, Word, TOKENS};
+use crate::diagnostic::{Diagnostic, Fix, Label};
+use crate::{
+    ArgType, Atom, AtomKind, Compatibility, Lint, ParseState, TokenArg, Value, Word, TOKENS,
+};
 use cow_utils::CowUtils;
 use strsim::jaro_winkler;
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct ArgTypesLint {}
 
 impl ArgTypesLint {
@@ -41,9 +43,32 @@ impl ArgTypesLint {
     ) -> Option<Diagnostic> {
         // 1. Check if this may or may not be defined—else warn
         if !state.has_const(token.value) {
-            if state.has_define(token.value) {
+            if let Some(condition) = state.const_condition(token.value) {
+                Some(
+                    Diagnostic::warning(
+                        token.location,
+                        format_args!(
+                            "Token `{}` is only defined when `{}` is set",
+                            token.value, condition
+                        ),
+                    )
+                    .suggest(Fix::new(
+                        token.location,
+                        format_args!(
+                            "Wrap this command in an `if {}` statement or change the compatibility setting",
+                            condition
+                        ),
+                    )),
+                )
+            } else if state.has_define(token.value) {
                 // 2. Check if this has a value (is defined using #const)—else warn
-                Some(Diagnostic::warning(token.location, format_args!("Expected a valued token (defined using #const), got a valueless token `{}` (defined using #define)", token.value)))
+                let warn = Diagnostic::warning(token.location, format_args!("Expected a valued token (defined using #const), got a valueless token `{}` (defined using #define)", token.value));
+                Some(match state.get_define(token.value) {
+                    Some(definition) => {
+                        warn.add_label(Label::new(definition.location(), "Defined here"))
+                    }
+                    None => warn,
+                })
             } else {
                 let warn = Diagnostic::warning(
                     token.location,
@@ -65,12 +90,15 @@ impl ArgTypesLint {
 
     fn check_number(
         &self,
-        _state: &ParseState<'_>,
+        state: &ParseState<'_>,
         name: &Word<'_>,
         arg: &Word<'_>,
     ) -> Option<Diagnostic> {
         // This may be a valued (#const) constant,
         // or a number (12, -35),
+        if accepts_const_numbers(state) && state.has_const(arg.value) {
+            return None;
+        }
         arg.value
             .parse::<i32>()
             .err()
@@ -107,6 +135,40 @@ impl ArgTypesLint {
             })
     }
 
+    /// Check that an argument has one of the values that the token database allows. Constants
+    /// and `rnd()` calls are evaluated, and all of their possible values must be valid.
+    fn check_range(
+        &self,
+        state: &ParseState<'_>,
+        name: &Word<'_>,
+        index: usize,
+        token_arg: &TokenArg,
+        arg: &Word<'_>,
+    ) -> Option<Diagnostic> {
+        let range = token_arg.range()?;
+        let value = if token_arg.arg_type() != ArgType::Number || accepts_const_numbers(state) {
+            state.evaluate(arg.value)?
+        } else {
+            Value::parse(arg.value)?
+        };
+        if range.contains(value) {
+            return None;
+        }
+        let message = match (token_arg.invalid_message(), token_arg.name()) {
+            (Some(message), _) => message.to_string(),
+            (None, Some(arg_name)) => format!("`{}` {} must be {}", name.value, arg_name, range),
+            (None, None) => format!("`{}` argument {} must be {}", name.value, index + 1, range),
+        };
+        let warn = Diagnostic::warning(arg.location, message);
+        Some(match state.get_const(arg.value) {
+            Some(definition) => warn.add_label(Label::new(
+                definition.location(),
+                format_args!("Defined here, evaluates to {}", value),
+            )),
+            None => warn,
+        })
+    }
+
     fn check_arg(
         &self,
         state: &ParseState<'_>,
@@ -166,7 +228,7 @@ impl ArgTypesLint {
             Player,
             Team,
         }
-        let target = if let Some(arg) = args.get(0) {
+        let target = if let Some(arg) = args.first() {
             match arg.value {
                 "AT_COLOR" => Some(AssignTarget::Color),
                 "AT_PLAYER" => Some(AssignTarget::Player),
@@ -185,21 +247,19 @@ impl ArgTypesLint {
 
         if let Some(Ok(number)) = args.get(1).map(|f| f.value.parse::<i32>()) {
             match target {
-                Some(AssignTarget::Color) | Some(AssignTarget::Player) => {
-                    if !(0..=8).contains(&number) {
-                        warnings.push(Diagnostic::warning(
-                            args[1].location,
-                            "`assign_to` Number must be 1-8 when targeting AT_COLOR or AT_PLAYER",
-                        ));
-                    }
+                Some(AssignTarget::Color) | Some(AssignTarget::Player)
+                    if !(0..=8).contains(&number) =>
+                {
+                    warnings.push(Diagnostic::warning(
+                        args[1].location,
+                        "`assign_to` Number must be 1-8 when targeting AT_COLOR or AT_PLAYER",
+                    ));
                 }
-                Some(AssignTarget::Team) => {
-                    if (number < -4 || number > 4) && number != -10 {
-                        warnings.push(Diagnostic::warning(
-                            args[1].location,
-                            "`assign_to` Number must be 1-4 when targeting AT_TEAM",
-                        ));
-                    }
+                Some(AssignTarget::Team) if !(-4..=4).contains(&number) && number != -10 => {
+                    warnings.push(Diagnostic::warning(
+                        args[1].location,
+                        "`assign_to` Number must be 1-4 when targeting AT_TEAM",
+                    ));
                 }
                 _ => (),
             }
@@ -207,18 +267,14 @@ impl ArgTypesLint {
 
         if let Some(Ok(mode)) = args.get(2).map(|f| f.value.parse::<i32>()) {
             match target {
-                Some(AssignTarget::Team) => {
-                    if mode != -1 && mode != 0 {
-                        warnings.push(Diagnostic::warning(args[2].location,"`assign_to` Mode must be 0 (random selection) or -1 (ordered selection) when targeting AT_TEAM"));
-                    }
+                Some(AssignTarget::Team) if mode != -1 && mode != 0 => {
+                    warnings.push(Diagnostic::warning(args[2].location,"`assign_to` Mode must be 0 (random selection) or -1 (ordered selection) when targeting AT_TEAM"));
                 }
-                Some(_) => {
-                    if mode != 0 {
-                        warnings.push(Diagnostic::warning(
-                            args[2].location,
-                            "`assign_to` Mode should be 0 when targeting AT_COLOR or AT_PLAYER",
-                        ));
-                    }
+                Some(_) if mode != 0 => {
+                    warnings.push(Diagnostic::warning(
+                        args[2].location,
+                        "`assign_to` Mode should be 0 when targeting AT_COLOR or AT_PLAYER",
+                    ));
                 }
                 _ => (),
             }
@@ -240,11 +296,17 @@ impl Lint for ArgTypesLint {
     fn name(&self) -> &'static str {
         "arg-types"
     }
+    fn snapshot(&self) -> Option<Box<dyn Lint>> {
+        Some(Box::new(self.clone()))
+    }
     fn lint_atom(&mut self, state: &mut ParseState<'_>, atom: &Atom<'_>) -> Vec<Diagnostic> {
         if let AtomKind::Command { name, arguments } = &atom.kind {
             let token_type = &TOKENS[name.value.cow_to_ascii_lowercase().as_ref()];
             let mut warnings = vec![];
             for i in 0..token_type.arg_len() {
+                if i >= token_type.min_arg_len() && arguments.get(i as usize).is_none() {
+                    break;
+                }
                 if let Some(warning) = self.check_arg(
                     state,
                     atom,
@@ -255,45 +317,18 @@ impl Lint for ArgTypesLint {
                 }
             }
 
+            for (i, (token_arg, arg)) in token_type.args().iter().zip(arguments).enumerate() {
+                warnings.extend(self.check_range(state, name, i, token_arg, arg));
+            }
+
             match name.value {
-                "base_elevation" if !arguments.is_empty() => {
-                    let arg = arguments[0];
-                    if let Ok(n) = arg.value.parse::<i32>() {
-                        if !(0..=7).contains(&n) {
-                            warnings.push(Diagnostic::warning(
-                                arg.location,
-                                "Elevation value out of range (0 or 1-7)",
-                            ));
-                        }
-                    }
-                }
-                "land_position" => {
-                    if let Some(Ok(first)) = arguments.get(0).map(|f| f.value.parse::<i32>()) {
-                        if !(0..=100).contains(&first) {
-                            warnings.push(Diagnostic::warning(
-                                arguments[0].location,
-                                "Land position out of range (0-100)",
-                            ));
-                        }
-                    }
-                    if let Some(Ok(second)) = arguments.get(1).map(|f| f.value.parse::<i32>()) {
-                        if !(0..=99).contains(&second) {
-                            warnings.push(Diagnostic::warning(
-                                arguments[1].location,
-                                "Land position out of range (0-99)",
-                            ));
-                        }
-                    }
-                }
-                "zone" if !arguments.is_empty() => {
-                    if arguments[0].value == "99" {
-                        warnings.push(Diagnostic::warning(
-                            arguments[0].location,
-                            "`zone 99` crashes the game",
-                        ));
-                    }
+                "zone" if arguments.first().is_some_and(|arg| arg.value == "99") => {
+                    warnings.push(Diagnostic::warning(
+                        arguments[0].location,
+                        "`zone 99` crashes the game",
+                    ));
                 }
-                "assign_to" => self.check_assign_to(&arguments, &mut warnings),
+                "assign_to" => self.check_assign_to(arguments, &mut warnings),
                 _ => (),
             }
 
@@ -304,6 +339,12 @@ impl Lint for ArgTypesLint {
     }
 }
 
+/// Can `#const` names be used as numeric arguments? Older versions only accept number literals and
+/// `rnd()` calls.
+fn accepts_const_numbers(state: &ParseState<'_>) -> bool {
+    state.compatibility() == Compatibility::DefinitiveEdition
+}
+
 fn meant<'a>(actual: &str, possible: impl Iterator<Item = &'a str>) -> Option<&'a str> {
     possible
         .map(|expected| (expected, jaro_winkler(actual, expected)))
@@ -342,7 +383,7 @@ fn is_valid_rnd(s: &str) -> (bool, Option<String>) {
 mod tests {
     use super::*;
     use crate::diagnostic::{ByteIndex, SourceLocation};
-    use crate::{Compatibility, RMSCheck, RMSFile, Severity};
+    use crate::{Compatibility, DefinitionsMode, RMSCheck, RMSFile, Severity};
 
     #[test]
     fn is_numeric_test() {
@@ -418,6 +459,120 @@ mod tests {
         );
     }
 
+    #[test]
+    fn up_constants() -> anyhow::Result<()> {
+        let file = RMSFile::from_path("./tests/rms/up-constants.rms")?;
+        let checks = [
+            (Compatibility::All, 3, 0),
+            (Compatibility::Conquerors, 3, 0),
+            (Compatibility::UserPatch14, 3, 0),
+            (Compatibility::UserPatch15, 0, 0),
+            (Compatibility::WololoKingdoms, 0, 0),
+            (Compatibility::HDEdition, 0, 6),
+            (Compatibility::DefinitiveEdition, 0, 6),
+        ];
+
+        for (compatibility, conditional, undefined) in &checks {
+            let result = RMSCheck::new()
+                .compatibility(*compatibility)
+                .with_lint(Box::new(ArgTypesLint::new()))
+                .check(&file);
+
+            let count = |message: &str| {
+                result
+                    .iter()
+                    .filter(|warning| warning.message().ends_with(message))
+                    .count()
+            };
+            assert_eq!(
+                count("is only defined when `UP_EXTENSION` is set"),
+                *conditional,
+                "{:?}",
+                compatibility
+            );
+            assert_eq!(count("is never defined"), *undefined, "{:?}", compatibility);
+            assert_eq!(
+                result.iter().count(),
+                conditional + undefined,
+                "{:?}",
+                compatibility
+            );
+        }
+
+        // Conditional constants are only reported outside of `if UP_EXTENSION`.
+        let result = RMSCheck::new()
+            .with_lint(Box::new(ArgTypesLint::new()))
+            .check(&file);
+        let first = result.iter().next().unwrap();
+        assert_eq!(
+            first.location(),
+            SourceLocation::new(file.file_id(), ByteIndex::from(30)..ByteIndex::from(43))
+        );
+        assert_eq!(
+            first.message(),
+            "Token `SET_ATTRIBUTE` is only defined when `UP_EXTENSION` is set"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn custom_definitions() {
+        let source = "<OBJECTS_GENERATION>\r\ncreate_object MOD_UNIT\r\ncreate_object MOD_FLAG\r\ncreate_object SCOUT\r\n";
+        let definitions = "#const MOD_UNIT 1500\r\n#define MOD_FLAG\r\n";
+
+        let mut file = RMSFile::from_string("custom.rms", source);
+        let def_file = file.add_definitions(
+            Compatibility::Conquerors,
+            "mod.def",
+            definitions,
+            DefinitionsMode::Layer,
+        );
+        let result = RMSCheck::new()
+            .compatibility(Compatibility::Conquerors)
+            .with_lint(Box::new(ArgTypesLint::new()))
+            .check(&file);
+        let mut warnings = result.iter();
+        let first = warnings.next().unwrap();
+        assert!(warnings.next().is_none());
+        assert_eq!(first.message(), "Expected a valued token (defined using #const), got a valueless token `MOD_FLAG` (defined using #define)");
+        let label = first.labels().next().unwrap();
+        assert_eq!(label.message(), "Defined here");
+        assert_eq!(
+            label.location(),
+            SourceLocation::new(def_file, ByteIndex::from(22)..ByteIndex::from(38))
+        );
+
+        let mut file = RMSFile::from_string("custom.rms", source);
+        file.add_definitions(
+            Compatibility::Conquerors,
+            "mod.def",
+            definitions,
+            DefinitionsMode::Override,
+        );
+        let result = RMSCheck::new()
+            .compatibility(Compatibility::Conquerors)
+            .with_lint(Box::new(ArgTypesLint::new()))
+            .check(&file);
+        let messages: Vec<_> = result.iter().map(|warning| warning.message()).collect();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1], "Token `SCOUT` is never defined");
+
+        // Definitions for other compatibility levels are not used.
+        let result = RMSCheck::new()
+            .compatibility(Compatibility::DefinitiveEdition)
+            .with_lint(Box::new(ArgTypesLint::new()))
+            .check(&file);
+        assert_eq!(result.iter().count(), 2);
+
+        let mut file = RMSFile::from_string("custom.rms", source);
+        file.add_definitions_for_all("mod.def", definitions, DefinitionsMode::Layer);
+        let result = RMSCheck::new()
+            .compatibility(Compatibility::DefinitiveEdition)
+            .with_lint(Box::new(ArgTypesLint::new()))
+            .check(&file);
+        assert_eq!(result.iter().count(), 1);
+    }
+
     #[test]
     fn base_elevation() {
         let filename = "base_elevation.rms";
@@ -439,6 +594,53 @@ mod tests {
         );
     }
 
+    #[test]
+    fn evaluated_ranges() {
+        let source = "#const HIGH 9\r\n#const ALSO_HIGH HIGH\r\n#const RANDOM rnd(50,120)\r\n<LAND_GENERATION>\r\ncreate_land {\r\n  base_elevation ALSO_HIGH\r\n  land_position RANDOM rnd(0,99)\r\n}\r\ncreate_land {\r\n  base_elevation rnd(1,8)\r\n  land_position 50 rnd(90,100)\r\n}\r\n";
+        let file = RMSFile::from_string("evaluated_ranges.rms", source);
+        let result = RMSCheck::new()
+            .compatibility(Compatibility::DefinitiveEdition)
+            .with_lint(Box::new(ArgTypesLint::new()))
+            .check(&file);
+        let warnings: Vec<_> = result.iter().collect();
+        let messages: Vec<_> = warnings.iter().map(|warning| warning.message()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Elevation value out of range (0 or 1-7)",
+                "Land position out of range (0-100)",
+                "Elevation value out of range (0 or 1-7)",
+                "Land position out of range (0-99)",
+            ]
+        );
+
+        let label = warnings[0].labels().next().unwrap();
+        assert_eq!(label.message(), "Defined here, evaluates to 9");
+        assert_eq!(
+            label.location(),
+            SourceLocation::new(file.file_id(), ByteIndex::from(15)..ByteIndex::from(36))
+        );
+        let label = warnings[1].labels().next().unwrap();
+        assert_eq!(label.message(), "Defined here, evaluates to rnd(50,120)");
+        assert!(warnings[2].labels().next().is_none());
+
+        // Constants can not be used as numbers in older versions.
+        let result = RMSCheck::new()
+            .compatibility(Compatibility::UserPatch15)
+            .with_lint(Box::new(ArgTypesLint::new()))
+            .check(&file);
+        let messages: Vec<_> = result.iter().map(|warning| warning.message()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Expected a number argument to base_elevation, but got ALSO_HIGH",
+                "Expected a number argument to land_position, but got RANDOM",
+                "Elevation value out of range (0 or 1-7)",
+                "Land position out of range (0-99)",
+            ]
+        );
+    }
+
     #[test]
     fn floats() {
         let filename = "floats.rms";
@@ -524,5 +726,35 @@ mod tests {
             "`assign_to` Flags must only combine flags 1 and 2"
         );
         assert!(warnings.next().is_none());
+
+        let file = RMSFile::from_string(filename, "create_land { assign_to AT_PLAYER 1 -1 0 }");
+        let result = RMSCheck::new()
+            .compatibility(Compatibility::DefinitiveEdition)
+            .with_lint(Box::new(ArgTypesLint::new()))
+            .check(&file);
+        let messages: Vec<_> = result.iter().map(|warning| warning.message()).collect();
+        assert_eq!(
+            messages,
+            vec!["`assign_to` Mode should be 0 when targeting AT_COLOR or AT_PLAYER"]
+        );
+    }
+
+    #[test]
+    fn generic_ranges() {
+        let source = "#const UNIT 83\r\n#const RESOURCE 0\r\n#const VICTORY 1\r\n#const NOT_A_FLAG 8\r\n<PLAYER_SETUP>\r\nguard_state UNIT RESOURCE 0 VICTORY\r\nguard_state UNIT RESOURCE 0 NOT_A_FLAG\r\n<LAND_GENERATION>\r\ncreate_land { land_percent rnd(50,150) }\r\n<OBJECTS_GENERATION>\r\ncreate_object GOLD { number_of_objects -5 }\r\n";
+        let file = RMSFile::from_string("generic_ranges.rms", source);
+        let result = RMSCheck::new()
+            .compatibility(Compatibility::DefinitiveEdition)
+            .with_lint(Box::new(ArgTypesLint::new()))
+            .check(&file);
+        let messages: Vec<_> = result.iter().map(|warning| warning.message()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "`guard_state` GuardFlags must be a combination of the flags 1, 2 and 4",
+                "`land_percent` Percent must be 0-100",
+                "`number_of_objects` Number must be at least 0",
+            ]
+        );
     }
 }
@@ -0,0 +1,280 @@
+use crate::diagnostic::{Diagnostic, Label, SourceLocation};
+use crate::{Atom, AtomKind, Lint, Nesting, ParseState, TokenContext, TOKENS};
+use cow_utils::CowUtils;
+
+/// The `create_*` command that started a block.
+#[derive(Clone)]
+struct BlockCommand {
+    /// The lowercased name of the command.
+    name: String,
+    /// The location of the command name.
+    location: SourceLocation,
+}
+
+/// Where an attribute was found.
+enum Place<'a> {
+    /// Inside the block of a command.
+    Block(&'a BlockCommand),
+    /// At the top level of a section. Commands before the first section header are read as part
+    /// of `<PLAYER_SETUP>`.
+    TopLevel(&'a str),
+}
+
+/// Does `context` allow an attribute to appear in `place`?
+fn allows(context: &TokenContext, place: &Place<'_>) -> bool {
+    match (context, place) {
+        (TokenContext::Attribute(None), Place::Block(_)) => true,
+        (TokenContext::Attribute(Some(expected)), Place::Block(command)) => {
+            command.name == *expected
+        }
+        (TokenContext::TopLevelAttribute(None), Place::TopLevel(_)) => true,
+        (TokenContext::TopLevelAttribute(Some(expected)), Place::TopLevel(section)) => {
+            section == expected
+        }
+        (TokenContext::AnyOf(contexts), place) => {
+            contexts.iter().any(|context| allows(context, place))
+        }
+        _ => false,
+    }
+}
+
+/// Is this the context of an attribute, rather than a command or a flow control token?
+fn is_attribute(context: &TokenContext) -> bool {
+    match context {
+        TokenContext::Attribute(_) | TokenContext::TopLevelAttribute(_) => true,
+        TokenContext::AnyOf(contexts) => contexts.iter().all(is_attribute),
+        _ => false,
+    }
+}
+
+/// Join a list of names like "a, b or c".
+fn join_or(names: &[String]) -> String {
+    match names.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
+
+/// Describe the places where an attribute with the given context can appear.
+fn describe_allowed(context: &TokenContext) -> String {
+    fn collect(context: &TokenContext, top_levels: &mut Vec<String>, blocks: &mut Vec<String>) {
+        match context {
+            TokenContext::Attribute(Some(block)) => blocks.push(format!("`{}`", block)),
+            TokenContext::Attribute(None) => blocks.push("a block".to_string()),
+            TokenContext::TopLevelAttribute(Some(section)) => {
+                top_levels.push(format!("at the top level of {}", section))
+            }
+            TokenContext::TopLevelAttribute(None) => {
+                top_levels.push("at the top level".to_string())
+            }
+            TokenContext::AnyOf(contexts) => {
+                for context in contexts.iter() {
+                    collect(context, top_levels, blocks);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let mut top_levels = vec![];
+    let mut blocks = vec![];
+    collect(context, &mut top_levels, &mut blocks);
+    if !blocks.is_empty() {
+        top_levels.push(format!("inside {}", join_or(&blocks)));
+    }
+    join_or(&top_levels)
+}
+
+/// Check that attributes are used inside the block or section that they belong to.
+#[derive(Default, Clone)]
+pub struct AttributeContextLint {
+    /// The last command that can start a block.
+    command: Option<BlockCommand>,
+}
+
+impl AttributeContextLint {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Lint for AttributeContextLint {
+    fn name(&self) -> &'static str {
+        "attribute-context"
+    }
+    fn snapshot(&self) -> Option<Box<dyn Lint>> {
+        Some(Box::new(self.clone()))
+    }
+    fn lint_atom(&mut self, state: &mut ParseState<'_>, atom: &Atom<'_>) -> Vec<Diagnostic> {
+        let name = match &atom.kind {
+            AtomKind::Command { name, .. } => name,
+            AtomKind::CloseBlock { .. } => {
+                self.command = None;
+                return vec![];
+            }
+            _ => return vec![],
+        };
+        let lower_name = name.value.cow_to_ascii_lowercase();
+        let token_type = match TOKENS.get(lower_name.as_ref()) {
+            Some(token_type) => token_type,
+            None => return vec![],
+        };
+        let context = token_type.context();
+        if let TokenContext::Command(_) = context {
+            self.command = Some(BlockCommand {
+                name: lower_name.into_owned(),
+                location: name.location,
+            });
+            return vec![];
+        }
+        if !is_attribute(context) {
+            return vec![];
+        }
+
+        let in_block = state
+            .nesting
+            .iter()
+            .any(|nesting| matches!(nesting, Nesting::Brace(_)));
+        let section = state
+            .current_section
+            .as_ref()
+            .map(|section| match &section.kind {
+                AtomKind::Section { name } => name.value,
+                kind => unreachable!("Expected AtomKind::Section, got {:?}", kind),
+            })
+            .unwrap_or("<PLAYER_SETUP>");
+        let place = match (&self.command, in_block) {
+            (Some(command), true) => Place::Block(command),
+            // We don't know what kind of block this is.
+            (None, true) => return vec![],
+            (_, false) => Place::TopLevel(section),
+        };
+        if allows(context, &place) {
+            return vec![];
+        }
+
+        let here = match &place {
+            Place::Block(command) => format!("inside `{}`", command.name),
+            Place::TopLevel(section) => format!("at the top level of {}", section),
+        };
+        let warning = Diagnostic::error(
+            name.location,
+            format_args!(
+                "`{}` can not be used {}, it can only appear {}",
+                name.value,
+                here,
+                describe_allowed(context)
+            ),
+        );
+        let label = match &place {
+            Place::Block(command) => Some(Label::new(command.location, "Block started here")),
+            Place::TopLevel(_) => state
+                .current_section
+                .as_ref()
+                .map(|section| Label::new(section.location, "Section started here")),
+        };
+        vec![match label {
+            Some(label) => warning.add_label(label),
+            None => warning,
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lints::{check_with, lint_messages};
+    use crate::{Compatibility, RMSCheck, RMSFile};
+
+    #[test]
+    fn blocks() {
+        assert_eq!(
+            lint_messages(
+                AttributeContextLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<TERRAIN_GENERATION>\r\ncreate_terrain DESERT {\r\n  number_of_tiles 100\r\n  number_of_objects 5\r\n}\r\n"
+            ),
+            vec!["`number_of_objects` can not be used inside `create_terrain`, it can only appear inside `create_object`"]
+        );
+        // Attributes inside conditionals belong to the enclosing block.
+        assert_eq!(
+            lint_messages(
+                AttributeContextLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<LAND_GENERATION>\r\ncreate_player_lands {\r\n  land_percent 50\r\n  if TINY_MAP number_of_groups 2 endif\r\n}\r\n"
+            ),
+            vec!["`number_of_groups` can not be used inside `create_player_lands`, it can only appear inside `create_object`"]
+        );
+    }
+
+    #[test]
+    fn sections() {
+        assert_eq!(
+            lint_messages(
+                AttributeContextLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<OBJECTS_GENERATION>\r\ncliff_curliness 10\r\n<CLIFF_GENERATION>\r\ncliff_curliness 10\r\n"
+            ),
+            vec!["`cliff_curliness` can not be used at the top level of <OBJECTS_GENERATION>, it can only appear at the top level of <CLIFF_GENERATION>"]
+        );
+        assert_eq!(
+            lint_messages(
+                AttributeContextLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<OBJECTS_GENERATION>\r\nnumber_of_objects 5\r\n"
+            ),
+            vec!["`number_of_objects` can not be used at the top level of <OBJECTS_GENERATION>, it can only appear inside `create_object`"]
+        );
+        assert_eq!(
+            lint_messages(
+                AttributeContextLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<LAND_GENERATION>\r\nland_percent 10\r\n<OBJECTS_GENERATION>\r\nbase_terrain GRASS\r\n"
+            ),
+            vec![
+                "`land_percent` can not be used at the top level of <LAND_GENERATION>, it can only appear inside `create_land`, `create_player_lands` or `create_terrain`",
+                "`base_terrain` can not be used at the top level of <OBJECTS_GENERATION>, it can only appear at the top level of <LAND_GENERATION> or inside `create_land`, `create_player_lands`, `create_elevation`, `create_terrain` or `create_object`",
+            ]
+        );
+    }
+
+    #[test]
+    fn before_first_section() {
+        assert!(lint_messages(
+            AttributeContextLint::new(),
+            Compatibility::DefinitiveEdition,
+            "ai_info_map_type ARABIA\r\n<PLAYER_SETUP>\r\nrandom_placement\r\n"
+        )
+        .is_empty());
+        assert_eq!(
+            lint_messages(
+                AttributeContextLint::new(),
+                Compatibility::DefinitiveEdition,
+                "cliff_curliness 10\r\n<CLIFF_GENERATION>\r\n"
+            ),
+            vec!["`cliff_curliness` can not be used at the top level of <PLAYER_SETUP>, it can only appear at the top level of <CLIFF_GENERATION>"]
+        );
+        let file = RMSFile::from_path("./tests/rms/Dry Arabia.rms").unwrap();
+        let result = RMSCheck::new()
+            .with_lint(Box::new(AttributeContextLint::new()))
+            .check(&file);
+        assert_eq!(result.iter().count(), 0);
+    }
+
+    #[test]
+    fn label() {
+        let source = "<TERRAIN_GENERATION>\r\ncreate_terrain DESERT { number_of_objects 5 }\r\n";
+        let result = check_with(
+            AttributeContextLint::new(),
+            Compatibility::default(),
+            source,
+        );
+        let warning = result.iter().next().unwrap();
+        assert_eq!(warning.code(), Some("attribute-context"));
+        assert_eq!(warning.location().range(), 46.into()..63.into());
+        let label = warning.labels().next().unwrap();
+        assert_eq!(label.message(), "Block started here");
+        assert_eq!(label.location().range(), 22.into()..36.into());
+    }
+}
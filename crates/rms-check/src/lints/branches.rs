@@ -0,0 +1,82 @@
+use crate::state::MAP_SIZE_DEFINES;
+use crate::{Atom, AtomKind};
+
+/// A branch of an `if` statement or `start_random` block.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct BranchId {
+    /// Identifies the block.
+    block: usize,
+    /// The index of the branch in the block.
+    branch: usize,
+    /// The map size that this branch checks for, if it is an `if` or `elseif` branch with a map
+    /// size condition.
+    map_size: Option<&'static str>,
+}
+
+/// Get the map size `#define` that a condition checks for.
+fn map_size(condition: &str) -> Option<&'static str> {
+    MAP_SIZE_DEFINES
+        .iter()
+        .copied()
+        .find(|&define| define == condition)
+}
+
+/// Keep track of the branches of `if` statements and `start_random` blocks that we are inside of,
+/// to find out if two pieces of code can both be used in the same game.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Branches {
+    /// The branches that we are currently inside of.
+    current: Vec<BranchId>,
+    /// The number of `if` statements and `start_random` blocks seen so far.
+    num_blocks: usize,
+}
+
+impl Branches {
+    /// Update the current branches upon reading a new atom.
+    pub fn update(&mut self, atom: &Atom<'_>) {
+        match atom.kind {
+            AtomKind::If { .. } | AtomKind::StartRandom { .. } => {
+                let map_size = match atom.kind {
+                    AtomKind::If { condition, .. } => map_size(condition.value),
+                    _ => None,
+                };
+                self.current.push(BranchId {
+                    block: self.num_blocks,
+                    branch: 0,
+                    map_size,
+                });
+                self.num_blocks += 1;
+            }
+            AtomKind::ElseIf { .. } | AtomKind::Else { .. } | AtomKind::PercentChance { .. } => {
+                if let Some(current) = self.current.last_mut() {
+                    current.branch += 1;
+                    current.map_size = match atom.kind {
+                        AtomKind::ElseIf { condition, .. } => map_size(condition.value),
+                        _ => None,
+                    };
+                }
+            }
+            AtomKind::EndIf { .. } | AtomKind::EndRandom { .. } => {
+                self.current.pop();
+            }
+            _ => (),
+        }
+    }
+
+    /// Get the branches that we are currently inside of.
+    pub fn current(&self) -> Vec<BranchId> {
+        self.current.clone()
+    }
+}
+
+/// Can code inside the branches `a` and code inside the branches `b` never both be used in the
+/// same game, because they are in different branches of the same block, or only apply to
+/// different map sizes?
+pub(super) fn excludes(a: &[BranchId], b: &[BranchId]) -> bool {
+    a.iter().any(|a| {
+        b.iter().any(|b| {
+            (a.block == b.block && a.branch != b.branch)
+                || matches!((a.map_size, b.map_size), (Some(x), Some(y)) if x != y)
+        })
+    })
+}
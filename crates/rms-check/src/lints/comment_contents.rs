@@ -1,4 +1,5 @@
 use crate::diagnostic::{ByteIndex, Diagnostic, Fix, Label, SourceLocation};
+use crate::suppressions::is_directive;
 use crate::{Atom, AtomKind, Compatibility, Lint, Nesting, ParseErrorKind, ParseState, Parser};
 use std::ops::Range;
 
@@ -9,7 +10,7 @@ fn offset_range(span: Range<ByteIndex>, offset: isize) -> Range<ByteIndex> {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct CommentContentsLint {}
 
 impl CommentContentsLint {
@@ -22,6 +23,9 @@ impl Lint for CommentContentsLint {
     fn name(&self) -> &'static str {
         "comment-contents"
     }
+    fn snapshot(&self) -> Option<Box<dyn Lint>> {
+        Some(Box::new(self.clone()))
+    }
 
     fn run_inside_comments(&self) -> bool {
         true
@@ -34,7 +38,20 @@ impl Lint for CommentContentsLint {
             close,
         } = &atom.kind
         {
-            let offset = usize::from(open.end()) as isize;
+            let mut offset = usize::from(open.end()) as isize;
+            // The directive and codes in suppression comments are harmless, but the game still
+            // parses the reason after ` -- `.
+            let content = if is_directive(content) {
+                match content.find(" -- ") {
+                    Some(index) => {
+                        offset += (index + 4) as isize;
+                        &content[index + 4..]
+                    }
+                    None => return vec![],
+                }
+            } else {
+                content
+            };
 
             let (has_start_random, has_if) =
                 state
@@ -50,7 +67,7 @@ impl Lint for CommentContentsLint {
                 && state.compatibility <= Compatibility::UserPatch14
                 || has_start_random && state.compatibility <= Compatibility::UserPatch15;
 
-            let parser = Parser::new(state.rms.file_id(), &content);
+            let parser = Parser::new(state.rms.file_id(), content);
             let mut warnings = vec![];
 
             let mut expecting_more_arguments = None;
@@ -93,7 +110,7 @@ impl Lint for CommentContentsLint {
             return warnings;
         }
 
-        return vec![];
+        vec![]
     }
 }
 
@@ -1,7 +1,35 @@
-use crate::diagnostic::{Diagnostic, Fix};
-use crate::{Atom, AtomKind, Compatibility, Lint, ParseState};
+use crate::diagnostic::{Diagnostic, Fix, SourceLocation};
+use crate::{Atom, AtomKind, Compatibility, Lint, ParseState, Severity, Word, TOKENS};
+use cow_utils::CowUtils;
 
-#[derive(Default)]
+/// Describe what is needed to use a feature introduced by the given compatibility level, and how
+/// to fix a script that uses it.
+const fn requirement(since: Compatibility) -> (&'static str, &'static str) {
+    match since {
+        Compatibility::UserPatch14 => (
+            "requires UserPatch 1.4 or HD Edition",
+            "Wrap this command in an `if UP_AVAILABLE` statement or add a /* Compatibility: UserPatch 1.4 */ comment at the top of the file",
+        ),
+        Compatibility::UserPatch15 => (
+            "requires UserPatch 1.5 or Definitive Edition",
+            "Wrap this command in an `if UP_EXTENSION` statement or add a /* Compatibility: UserPatch 1.5 */ comment at the top of the file",
+        ),
+        Compatibility::WololoKingdoms => (
+            "requires WololoKingdoms",
+            "Add a /* Compatibility: WololoKingdoms */ comment at the top of the file",
+        ),
+        Compatibility::HDEdition => (
+            "requires HD Edition or Definitive Edition",
+            "Add a /* Compatibility: HD Edition */ comment at the top of the file",
+        ),
+        _ => (
+            "is only supported in the Definitive Edition",
+            "Add a /* Compatibility: Definitive Edition */ comment at the top of the file",
+        ),
+    }
+}
+
+#[derive(Default, Clone)]
 pub struct CompatibilityLint {
     conditions: Vec<String>,
 }
@@ -11,107 +39,105 @@ impl CompatibilityLint {
         Self::default()
     }
 
-    fn has_up_extension(&self, state: &ParseState<'_>) -> bool {
-        if state.compatibility() >= Compatibility::UserPatch15 {
-            return true;
-        }
+    fn has_up_extension(&self) -> bool {
         self.conditions.iter().any(|item| item == "UP_EXTENSION")
     }
-    fn has_up_available(&self, state: &ParseState<'_>) -> bool {
-        if state.compatibility() >= Compatibility::UserPatch14 {
-            return true;
+    fn has_up_available(&self) -> bool {
+        self.has_up_extension() || self.conditions.iter().any(|item| item == "UP_AVAILABLE")
+    }
+
+    /// Can a feature introduced by the given compatibility level be used here?
+    fn is_available(&self, state: &ParseState<'_>, since: Compatibility) -> bool {
+        match since {
+            _ if state.compatibility().includes(since) => true,
+            Compatibility::UserPatch14 => self.has_up_available(),
+            Compatibility::UserPatch15 => self.has_up_extension(),
+            _ => false,
         }
-        self.conditions.iter().any(|item| item == "UP_AVAILABLE")
     }
 
     fn add_define_check(&mut self, name: &str) {
         self.conditions.push(name.to_string());
     }
+
+    fn check_command(
+        &self,
+        state: &ParseState<'_>,
+        atom: &Atom<'_>,
+        name: &Word<'_>,
+        arguments: &[Word<'_>],
+    ) -> Vec<Diagnostic> {
+        let token_type = match TOKENS.get(name.value.cow_to_ascii_lowercase().as_ref()) {
+            Some(token_type) => token_type,
+            None => return vec![],
+        };
+        let mut warnings = vec![];
+
+        if !self.is_available(state, token_type.since()) {
+            let (requirement, fix) = requirement(token_type.since());
+            let message = match token_type.since_message() {
+                Some(message) => message.to_string(),
+                None => format!("`{}` {}", name.value, requirement),
+            };
+            warnings.push(
+                Diagnostic::warning(atom.location, message).suggest(Fix::new(atom.location, fix)),
+            );
+        }
+
+        let is_de = state.compatibility() == Compatibility::DefinitiveEdition;
+        match token_type.legacy_arg_len() {
+            Some(len) if !is_de && arguments.len() > len as usize => {
+                let extra = &arguments[len as usize..];
+                let location = SourceLocation::new(
+                    name.location.file(),
+                    extra[0].location.start()..extra[extra.len() - 1].location.end(),
+                );
+                let (_, fix) = requirement(Compatibility::DefinitiveEdition);
+                let message = if len == 0 {
+                    format!(
+                        "Arguments to `{}` are only supported in the Definitive Edition",
+                        name.value
+                    )
+                } else {
+                    format!(
+                        "`{}` only accepts {} arguments outside of the Definitive Edition",
+                        name.value, len
+                    )
+                };
+                warnings
+                    .push(Diagnostic::warning(location, message).suggest(Fix::new(location, fix)));
+            }
+            _ => (),
+        }
+
+        if let Some((deprecated_in, reason)) = token_type.deprecated() {
+            if state.compatibility().includes(deprecated_in) {
+                warnings.push(
+                    Diagnostic::warning(atom.location, reason)
+                        .with_severity(Severity::Hint)
+                        .suggest(Fix::new(atom.location, "Remove this command").replace("")),
+                );
+            }
+        }
+
+        warnings
+    }
 }
 
 impl Lint for CompatibilityLint {
     fn name(&self) -> &'static str {
         "compatibility"
     }
+    fn snapshot(&self) -> Option<Box<dyn Lint>> {
+        Some(Box::new(self.clone()))
+    }
 
     fn lint_atom(&mut self, state: &mut ParseState<'_>, atom: &Atom<'_>) -> Vec<Diagnostic> {
-        let mut warnings = vec![];
-
-        if let AtomKind::Command { name, .. } = &atom.kind {
-            match name.value {
-                "effect_amount" | "effect_percent" => {
-                    if !self.has_up_extension(state) && state.compatibility() != Compatibility::DefinitiveEdition {
-                        warnings.push(Diagnostic::warning(atom.location, "RMS Effects require UserPatch 1.5 or Definitive Edition")
-                                      .suggest(Fix::new(atom.location, "Wrap this command in an `if UP_EXTENSION` statement or add a /* Compatibility: UserPatch 1.5 */ comment at the top of the file")));
-                    }
-                }
-                "direct_placement" => {
-                    if !self.has_up_extension(state) {
-                        warnings.push(Diagnostic::warning(atom.location, "Direct placement requires UserPatch 1.5 or Definitive Edition")
-                                .suggest(Fix::new(atom.location,
-                                    "Wrap this command in an `if UP_EXTENSION` statement or add a /* Compatibility: UserPatch 1.5 */ comment at the top of the file",
-                                ))
-                        )
-                    }
-                }
-                "nomad_resources" => {
-                    if !self.has_up_available(state) && state.compatibility() != Compatibility::HDEdition {
-                        warnings.push(
-                            Diagnostic::warning(atom.location, "Nomad resources requires UserPatch 1.4 or HD Edition")
-                                .suggest(Fix::new(atom.location,
-                                    "Wrap this command in an `if UP_AVAILABLE` statement or add a /* Compatibility: UserPatch 1.4 */ comment at the top of the file",
-                                ))
-                        )
-                    }
-                }
-                "actor_area"
-                | "actor_area_to_place_in"
-                | "avoid_actor_area"
-                | "avoid_all_actor_areas"
-                | "actor_area_radius" if state.compatibility() != Compatibility::DefinitiveEdition => {
-                    warnings.push(
-                        Diagnostic::warning(atom.location, "Actor areas are only supported in the Definitive Edition")
-                        .suggest(Fix::new(atom.location,
-                                          "Add a /* Compatibility: Definitive Edition */ comment at the top of the file",)
-                                )
-                        )
-                }
-                "avoid_forest_zone"
-                | "place_on_forest_zone"
-                | "avoid_cliff_zone" if state.compatibility() != Compatibility::DefinitiveEdition => {
-                    warnings.push(
-                        Diagnostic::warning(atom.location, "Forest and cliff zones are only supported in the Definitive Edition")
-                            .suggest(Fix::new(atom.location,
-                                "Add a /* Compatibility: Definitive Edition */ comment at the top of the file",)
-                            )
-                    )
-                }
-                "second_object" if state.compatibility() != Compatibility::DefinitiveEdition => {
-                    warnings.push(
-                        Diagnostic::warning(atom.location, "second_object is only supported in the Definitive Edition")
-                            .suggest(Fix::new(atom.location,
-                                "Add a /* Compatibility: Definitive Edition */ comment at the top of the file",)
-                            )
-                    )
-                }
-                "enable_balanced_elevation" if state.compatibility() != Compatibility::DefinitiveEdition => {
-                    warnings.push(
-                        Diagnostic::warning(atom.location, "enable_balanced_elevation is only supported in the Definitive Edition")
-                            .suggest(Fix::new(atom.location,
-                                "Add a /* Compatibility: Definitive Edition */ comment at the top of the file",)
-                            )
-                    )
-                }
-                "set_gaia_unconvertible" | "set_gaia_civilization" if state.compatibility() != Compatibility::DefinitiveEdition => {
-                    warnings.push(
-                        Diagnostic::warning(atom.location, format_args!("{} is only supported in the Definitive Edition", name.value))
-                            .suggest(Fix::new(atom.location,
-                                "Add a /* Compatibility: Definitive Edition */ comment at the top of the file",)
-                            )
-                    )
-                }
-                _ => (),
+        let warnings = match &atom.kind {
+            AtomKind::Command { name, arguments } => {
+                self.check_command(state, atom, name, arguments)
             }
+            _ => vec![],
         };
 
         match atom.kind {
@@ -138,6 +164,7 @@ impl Lint for CompatibilityLint {
 #[cfg(test)]
 mod tests {
     use super::CompatibilityLint;
+    use crate::lints::lint_messages;
     use crate::{Compatibility, RMSCheck, RMSFile, Severity};
 
     #[test]
@@ -190,4 +217,71 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn token_versions() {
+        let source = "<PLAYER_SETUP>\r\nguard_state KING AMOUNT_GOLD 0 1\r\n<OBJECTS_GENERATION>\r\ncreate_object GOLD { second_object STONE }\r\n";
+        assert_eq!(
+            lint_messages(CompatibilityLint::new(), Compatibility::Conquerors, source),
+            vec![
+                "`guard_state` requires UserPatch 1.5 or Definitive Edition",
+                "second_object is only supported in the Definitive Edition"
+            ]
+        );
+        assert_eq!(
+            lint_messages(CompatibilityLint::new(), Compatibility::UserPatch15, source),
+            vec!["second_object is only supported in the Definitive Edition"]
+        );
+        assert_eq!(
+            lint_messages(
+                CompatibilityLint::new(),
+                Compatibility::DefinitiveEdition,
+                source
+            ),
+            Vec::<String>::new()
+        );
+        // Guarded by a condition.
+        assert_eq!(
+            lint_messages(
+                CompatibilityLint::new(),
+                Compatibility::Conquerors,
+                "<PLAYER_SETUP>\r\nif UP_EXTENSION guard_state KING AMOUNT_GOLD 0 1 endif\r\n"
+            ),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn de_arguments() {
+        let source = "<TERRAIN_GENERATION>\r\ncreate_terrain DESERT {\r\n  set_avoid_player_start_areas 13\r\n}\r\ncreate_terrain DESERT {\r\n  set_avoid_player_start_areas\r\n}\r\n";
+        assert_eq!(
+            lint_messages(CompatibilityLint::new(), Compatibility::Conquerors, source),
+            vec!["Arguments to `set_avoid_player_start_areas` are only supported in the Definitive Edition"]
+        );
+        assert_eq!(
+            lint_messages(
+                CompatibilityLint::new(),
+                Compatibility::DefinitiveEdition,
+                source
+            ),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn deprecated() {
+        let source = "<PLAYER_SETUP>\r\nrandom_placement\r\n";
+        assert_eq!(
+            lint_messages(CompatibilityLint::new(), Compatibility::Conquerors, source),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            lint_messages(
+                CompatibilityLint::new(),
+                Compatibility::DefinitiveEdition,
+                source
+            ),
+            vec!["Random placement is the default, so `random_placement` has no effect"]
+        );
+    }
 }
@@ -0,0 +1,276 @@
+use crate::diagnostic::{Diagnostic, Fix, Label};
+use crate::{Block, Lint, ParseState};
+
+/// Groups of attributes that can not be used together, because they set the same thing.
+const EXCLUSIVE_GROUPS: &[&[&str]] = &[
+    &["set_loose_grouping", "set_tight_grouping"],
+    &["set_scale_by_groups", "set_scale_by_size"],
+    &["land_percent", "percent_of_land", "number_of_tiles"],
+    &["zone", "set_zone_by_team", "set_zone_randomly"],
+    &["set_place_for_every_player", "place_on_specific_land_id"],
+];
+
+/// Attributes of `create_land` and `create_player_lands` that can only be used once.
+const LAND_ATTRIBUTES: &[&str] = &[
+    "land_percent",
+    "land_position",
+    "land_id",
+    "terrain_type",
+    "base_size",
+    "base_elevation",
+    "left_border",
+    "right_border",
+    "top_border",
+    "bottom_border",
+    "border_fuzziness",
+    "zone",
+    "set_zone_by_team",
+    "set_zone_randomly",
+    "other_zone_avoidance_distance",
+    "assign_to_player",
+    "assign_to",
+    "land_conformity",
+    "base_terrain",
+    "number_of_tiles",
+    "clumping_factor",
+];
+
+/// Attributes that can only be used once, per command. Only the last one would be used.
+const SINGLE_ATTRIBUTES: &[(&str, &[&str])] = &[
+    ("create_land", LAND_ATTRIBUTES),
+    ("create_player_lands", LAND_ATTRIBUTES),
+    (
+        "create_terrain",
+        &[
+            "land_percent",
+            "base_terrain",
+            "percent_of_land",
+            "number_of_tiles",
+            "number_of_clumps",
+            "set_scale_by_groups",
+            "set_scale_by_size",
+            "spacing_to_other_terrain_types",
+            "height_limits",
+            "set_flat_terrain_only",
+            "set_avoid_player_start_areas",
+            "clumping_factor",
+            "base_layer",
+            "beach_terrain",
+        ],
+    ),
+    (
+        "create_elevation",
+        &[
+            "base_terrain",
+            "number_of_tiles",
+            "number_of_clumps",
+            "set_scale_by_groups",
+            "set_scale_by_size",
+            "spacing",
+        ],
+    ),
+    (
+        "create_object",
+        &[
+            "set_scaling_to_map_size",
+            "set_scaling_to_player_number",
+            "number_of_groups",
+            "number_of_objects",
+            "group_variance",
+            "group_placement_radius",
+            "set_loose_grouping",
+            "set_tight_grouping",
+            "terrain_to_place_on",
+            "layer_to_place_on",
+            "set_gaia_object_only",
+            "set_place_for_every_player",
+            "place_on_specific_land_id",
+            "min_distance_to_players",
+            "max_distance_to_players",
+            "max_distance_to_other_zones",
+            "min_distance_group_placement",
+            "temp_min_distance_group_placement",
+            "resource_delta",
+            "actor_area",
+            "actor_area_radius",
+            "second_object",
+            "min_distance_to_map_edge",
+        ],
+    ),
+];
+
+/// Does a later `b` override an earlier `a` in a `command` block?
+fn overrides(command: &str, a: &str, b: &str) -> bool {
+    if a == b {
+        return SINGLE_ATTRIBUTES
+            .iter()
+            .any(|(name, attributes)| *name == command && attributes.contains(&a));
+    }
+    EXCLUSIVE_GROUPS
+        .iter()
+        .any(|group| group.contains(&a) && group.contains(&b))
+}
+
+/// Check for attributes in a block that override each other, so that only the last one is used.
+///
+/// An attribute inside an `if` statement does not override an attribute outside of it, because
+/// setting a default and overriding it in some cases is a common pattern.
+#[derive(Default, Clone)]
+pub struct ConflictingAttributesLint {}
+
+impl ConflictingAttributesLint {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Lint for ConflictingAttributesLint {
+    fn name(&self) -> &'static str {
+        "conflicting-attributes"
+    }
+    fn snapshot(&self) -> Option<Box<dyn Lint>> {
+        Some(Box::new(self.clone()))
+    }
+    fn lint_block(&mut self, _state: &mut ParseState<'_>, block: &Block<'_>) -> Vec<Diagnostic> {
+        let command = block.name.value.to_ascii_lowercase();
+        let names: Vec<_> = block
+            .attributes
+            .iter()
+            .map(|attribute| attribute.name.value.to_ascii_lowercase())
+            .collect();
+
+        let mut warnings = vec![];
+        for (index, (earlier, earlier_name)) in block.attributes.iter().zip(&names).enumerate() {
+            let mut later_attributes = block.attributes[index + 1..]
+                .iter()
+                .zip(&names[index + 1..]);
+            let later = match later_attributes.find(|(later, name)| {
+                overrides(&command, earlier_name, name) && later.is_used_with(earlier)
+            }) {
+                Some((later, _)) => later,
+                None => continue,
+            };
+            warnings.push(
+                Diagnostic::warning(
+                    earlier.location,
+                    format_args!(
+                        "`{}` has no effect, because it is overridden by `{}` later in the same block",
+                        earlier.name.value, later.name.value
+                    ),
+                )
+                .add_label(Label::new(later.location, "Overridden here"))
+                .suggest(Fix::new(earlier.location, "Remove this attribute").replace("")),
+            );
+        }
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lints::{check_with, lint_messages};
+    use crate::{Compatibility, TOKENS};
+
+    #[test]
+    fn known_attributes() {
+        let names = EXCLUSIVE_GROUPS
+            .iter()
+            .flat_map(|group| group.iter())
+            .chain(
+                SINGLE_ATTRIBUTES
+                    .iter()
+                    .flat_map(|(command, attributes)| std::iter::once(command).chain(*attributes)),
+            );
+        for name in names {
+            assert!(TOKENS.contains_key(*name), "{} is not a known token", name);
+        }
+    }
+
+    #[test]
+    fn conflicting() {
+        assert_eq!(
+            lint_messages(
+                ConflictingAttributesLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<OBJECTS_GENERATION>\r\ncreate_object GOLD {\r\n  set_loose_grouping\r\n  set_tight_grouping\r\n}\r\n"
+            ),
+            vec!["`set_loose_grouping` has no effect, because it is overridden by `set_tight_grouping` later in the same block"]
+        );
+        assert_eq!(
+            lint_messages(
+                ConflictingAttributesLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<ELEVATION_GENERATION>\r\ncreate_elevation 7 {\r\n  base_terrain GRASS\r\n  set_scale_by_size\r\n  set_scale_by_groups\r\n}\r\n"
+            ),
+            vec!["`set_scale_by_size` has no effect, because it is overridden by `set_scale_by_groups` later in the same block"]
+        );
+    }
+
+    #[test]
+    fn repeated() {
+        assert_eq!(
+            lint_messages(
+                ConflictingAttributesLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<OBJECTS_GENERATION>\r\ncreate_object GOLD {\r\n  number_of_objects 5\r\n  group_variance 1\r\n  number_of_objects 7\r\n}\r\n"
+            ),
+            vec!["`number_of_objects` has no effect, because it is overridden by `number_of_objects` later in the same block"]
+        );
+        // Some attributes can be used more than once.
+        assert!(lint_messages(
+            ConflictingAttributesLint::new(),
+            Compatibility::DefinitiveEdition,
+            "<OBJECTS_GENERATION>\r\ncreate_object GOLD {\r\n  avoid_actor_area 1\r\n  avoid_actor_area 2\r\n}\r\n"
+        ).is_empty());
+        // The same attribute in different blocks is fine.
+        assert!(lint_messages(
+            ConflictingAttributesLint::new(),
+            Compatibility::DefinitiveEdition,
+            "<OBJECTS_GENERATION>\r\ncreate_object GOLD { number_of_objects 5 }\r\ncreate_object STONE { number_of_objects 5 }\r\n"
+        ).is_empty());
+    }
+
+    #[test]
+    fn branches() {
+        // Different branches, or a default that is overridden in some cases.
+        assert!(lint_messages(
+            ConflictingAttributesLint::new(),
+            Compatibility::DefinitiveEdition,
+            "<OBJECTS_GENERATION>\r\ncreate_object GOLD {\r\n  if TINY_MAP number_of_objects 5\r\n  else number_of_objects 7\r\n  endif\r\n}\r\n"
+        ).is_empty());
+        assert!(lint_messages(
+            ConflictingAttributesLint::new(),
+            Compatibility::DefinitiveEdition,
+            "<OBJECTS_GENERATION>\r\ncreate_object GOLD {\r\n  set_loose_grouping\r\n  if TINY_MAP set_tight_grouping endif\r\n}\r\n"
+        ).is_empty());
+        // An unconditional attribute always overrides a conditional one.
+        assert_eq!(
+            lint_messages(
+                ConflictingAttributesLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<OBJECTS_GENERATION>\r\ncreate_object GOLD {\r\n  if TINY_MAP number_of_objects 5 endif\r\n  number_of_objects 7\r\n}\r\n"
+            ),
+            vec!["`number_of_objects` has no effect, because it is overridden by `number_of_objects` later in the same block"]
+        );
+    }
+
+    #[test]
+    fn labels_and_fix() {
+        let source = "<OBJECTS_GENERATION>\r\ncreate_object GOLD { number_of_objects 5 number_of_objects 7 }\r\n";
+        let result = check_with(
+            ConflictingAttributesLint::new(),
+            Compatibility::default(),
+            source,
+        );
+        let warning = result.iter().next().unwrap();
+        assert_eq!(warning.code(), Some("conflicting-attributes"));
+        assert_eq!(warning.location().range(), 43.into()..62.into());
+        let label = warning.labels().next().unwrap();
+        assert_eq!(label.message(), "Overridden here");
+        assert_eq!(label.location().range(), 63.into()..82.into());
+        let fix = warning.suggestions().next().unwrap();
+        assert_eq!(fix.message(), "Remove this attribute");
+        assert_eq!(fix.replacement(), Some(""));
+    }
+}
@@ -0,0 +1,481 @@
+use crate::diagnostic::{ByteIndex, Diagnostic, Fix, Label, Severity, SourceLocation};
+use crate::state::is_inside_condition;
+use crate::{Atom, AtomKind, Lint, Nesting, ParseState};
+use std::collections::HashSet;
+
+/// Whether the condition of a branch is met.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Truth {
+    /// The condition is never met.
+    Never,
+    /// The condition is always met.
+    Always,
+    /// The condition depends on the game settings or on random choices.
+    Unknown,
+}
+
+/// An `if`, `elseif` or `else` branch.
+#[derive(Clone)]
+struct Branch {
+    /// The `if`, `elseif` or `else` statement, including the condition.
+    location: SourceLocation,
+    /// The `if`, `elseif` or `else` keyword.
+    head: SourceLocation,
+    /// The condition, or `None` for an `else` branch.
+    condition: Option<String>,
+    truth: Truth,
+}
+
+/// An `if` statement that we are inside of.
+#[derive(Clone, Default)]
+struct IfStatement {
+    branches: Vec<Branch>,
+}
+
+impl IfStatement {
+    /// The location of the entire statement, from the `if` to the `endif`.
+    fn location(&self, endif: SourceLocation) -> SourceLocation {
+        SourceLocation::new(endif.file(), self.branches[0].location.start()..endif.end())
+    }
+
+    /// Where the code in the branch at `index` ends.
+    fn branch_end(&self, index: usize, endif: SourceLocation) -> ByteIndex {
+        self.branches
+            .get(index + 1)
+            .map_or(endif.start(), |next| next.location.start())
+    }
+
+    /// The code inside the branch at `index`.
+    fn body<'s>(&self, source: &'s str, index: usize, endif: SourceLocation) -> &'s str {
+        let start = self.branches[index].location.end();
+        &source[usize::from(start)..usize::from(self.branch_end(index, endif))]
+    }
+
+    /// A fix that removes the branch at `index` from the statement.
+    fn remove_branch(
+        &self,
+        source: &str,
+        index: usize,
+        endif: SourceLocation,
+        message: &str,
+    ) -> Fix {
+        let slice =
+            |start: ByteIndex, end: ByteIndex| &source[usize::from(start)..usize::from(end)];
+        let start = self.branches[0].location.start();
+        let replacement = if index > 0 {
+            format!(
+                "{}{}",
+                slice(start, self.branches[index].location.start()),
+                slice(self.branch_end(index, endif), endif.end())
+            )
+        } else {
+            match self.branches.get(1) {
+                // Only one branch, so remove the entire statement.
+                None => String::new(),
+                // The `elseif` becomes the first branch.
+                Some(next) if next.condition.is_some() => {
+                    format!("if{}", slice(next.head.end(), endif.end()))
+                }
+                // Only the `else` branch is left, so its code is always used.
+                Some(_) => self.body(source, 1, endif).trim().to_string(),
+            }
+        };
+        Fix::new(self.location(endif), message).replace(replacement)
+    }
+
+    /// A fix that turns the branch at `index` into an unconditional branch, removing the branches
+    /// after it.
+    fn keep_branch(&self, source: &str, index: usize, endif: SourceLocation) -> Fix {
+        let replacement = if index == 0 {
+            self.body(source, 0, endif).trim().to_string()
+        } else {
+            let start = self.branches[0].location.start();
+            let end = self.branches[index].location.start();
+            format!(
+                "{}else{}{}",
+                &source[usize::from(start)..usize::from(end)],
+                self.body(source, index, endif),
+                &source[usize::from(endif.start())..usize::from(endif.end())]
+            )
+        };
+        Fix::new(self.location(endif), "Remove the condition").replace(replacement)
+    }
+}
+
+/// Find `if` branches that are never taken, or conditions that are always true.
+#[derive(Default, Clone)]
+pub struct DeadBranchLint {
+    /// The `if` statements we are inside of.
+    statements: Vec<IfStatement>,
+    /// `#define` names that are unset by an `#undefine` statement.
+    undefined: HashSet<String>,
+}
+
+impl DeadBranchLint {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Determine whether a condition is met. `nesting` contains the blocks around the `if`
+    /// statement that the condition belongs to.
+    ///
+    /// A `#define` at the top of a script is usually a toggle for the `if` statements that check
+    /// it, so conditions on those are not considered to be always met.
+    fn truth(&self, state: &ParseState<'_>, nesting: &[Nesting<'_>], name: &str) -> Truth {
+        if !state.may_have_define(name) {
+            return Truth::Never;
+        }
+        let is_defined = !self.undefined.contains(name) && state.is_builtin_define(name);
+        if is_defined || is_inside_condition(nesting, name) {
+            Truth::Always
+        } else {
+            Truth::Unknown
+        }
+    }
+
+    fn add_branch(
+        &mut self,
+        atom: &Atom<'_>,
+        head: SourceLocation,
+        condition: Option<&str>,
+        truth: Truth,
+    ) {
+        if let Some(statement) = self.statements.last_mut() {
+            statement.branches.push(Branch {
+                location: atom.location,
+                head,
+                condition: condition.map(ToString::to_string),
+                truth,
+            });
+        }
+    }
+
+    fn check_statement(&mut self, state: &ParseState<'_>, endif: &Atom<'_>) -> Vec<Diagnostic> {
+        let statement = match self.statements.pop() {
+            Some(statement) if !statement.branches.is_empty() => statement,
+            _ => return vec![],
+        };
+        let endif = endif.location;
+        let source = state.rms.source(endif.file());
+        let branches = &statement.branches;
+
+        let mut warnings = vec![];
+        let mut reported = vec![false; branches.len()];
+        let mut always_taken: Option<&Branch> = None;
+        for (index, branch) in branches.iter().enumerate() {
+            let remove =
+                || statement.remove_branch(source, index, endif, "Remove the unreachable branch");
+            if let Some(taken) = always_taken {
+                reported[index] = true;
+                warnings.push(
+                    Diagnostic::warning(
+                        branch.location,
+                        "This branch is never taken, because an earlier branch is always taken",
+                    )
+                    .add_label(Label::new(taken.location, "This branch is always taken"))
+                    .suggest(remove()),
+                );
+                continue;
+            }
+
+            let condition = match &branch.condition {
+                Some(condition) => condition,
+                None => continue,
+            };
+            if branch.truth == Truth::Never {
+                reported[index] = true;
+                warnings.push(
+                    Diagnostic::warning(
+                        branch.location,
+                        format_args!(
+                            "This branch is never taken, because `{}` is never defined",
+                            condition
+                        ),
+                    )
+                    .suggest(remove()),
+                );
+            } else if let Some(original) = branches[..index]
+                .iter()
+                .find(|original| original.condition.as_ref() == Some(condition))
+            {
+                reported[index] = true;
+                warnings.push(
+                    Diagnostic::warning(
+                        branch.location,
+                        "This branch is never taken, because an earlier branch checks the same condition",
+                    )
+                    .add_label(Label::new(
+                        original.location,
+                        format_args!("`{}` is already checked here", condition),
+                    ))
+                    .suggest(remove()),
+                );
+            } else if branch.truth == Truth::Always {
+                reported[index] = true;
+                warnings.push(
+                    Diagnostic::warning(
+                        branch.location,
+                        format_args!(
+                            "`{}` is always defined here, so this condition is always true",
+                            condition
+                        ),
+                    )
+                    .with_severity(Severity::Hint)
+                    .suggest(statement.keep_branch(source, index, endif)),
+                );
+                always_taken = Some(branch);
+            }
+        }
+
+        // Branches right before an `else` that contain the same code as the `else` could be
+        // removed without changing what the script does.
+        let last = branches.len() - 1;
+        if always_taken.is_none() && branches[last].condition.is_none() {
+            let normalize = |index| {
+                statement
+                    .body(source, index, endif)
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+            let else_body = normalize(last);
+            for index in (0..last).rev() {
+                if reported[index] {
+                    continue;
+                }
+                if normalize(index) != else_body {
+                    break;
+                }
+                warnings.push(
+                    Diagnostic::warning(
+                        branches[index].location,
+                        "This branch does the same as the `else` branch",
+                    )
+                    .add_label(Label::new(branches[last].location, "Same as this branch"))
+                    .suggest(statement.remove_branch(
+                        source,
+                        index,
+                        endif,
+                        "Remove the redundant branch",
+                    )),
+                );
+            }
+        }
+        warnings.sort_by_key(|warning| warning.location().start());
+        warnings
+    }
+}
+
+impl Lint for DeadBranchLint {
+    fn name(&self) -> &'static str {
+        "dead-branch"
+    }
+    fn snapshot(&self) -> Option<Box<dyn Lint>> {
+        Some(Box::new(self.clone()))
+    }
+    fn lint_atom(&mut self, state: &mut ParseState<'_>, atom: &Atom<'_>) -> Vec<Diagnostic> {
+        match &atom.kind {
+            AtomKind::If { head, condition } => {
+                let truth = self.truth(state, &state.nesting, condition.value);
+                self.statements.push(IfStatement::default());
+                self.add_branch(atom, head.location, Some(condition.value), truth);
+            }
+            AtomKind::ElseIf { head, condition } => {
+                // The innermost nesting is the `if` statement that this `elseif` belongs to.
+                let outer = &state.nesting[..state.nesting.len().saturating_sub(1)];
+                let truth = self.truth(state, outer, condition.value);
+                self.add_branch(atom, head.location, Some(condition.value), truth);
+            }
+            AtomKind::Else { head } => {
+                self.add_branch(atom, head.location, None, Truth::Always);
+            }
+            AtomKind::EndIf { .. } => return self.check_statement(state, atom),
+            AtomKind::Define { name, .. } => {
+                let is_unconditional = state
+                    .nesting
+                    .iter()
+                    .all(|nesting| matches!(nesting, Nesting::Brace(_)));
+                if is_unconditional {
+                    self.undefined.remove(name.value);
+                }
+            }
+            AtomKind::Undefine { name, .. } => {
+                self.undefined.insert(name.value.to_string());
+            }
+            _ => (),
+        }
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lints::lint_diagnostics;
+    use crate::{Compatibility, RMSCheck, RMSFile};
+
+    fn fixed(source: &str) -> Vec<String> {
+        let file = RMSFile::from_string("dead_branch.rms", source);
+        RMSCheck::new()
+            .with_lint(Box::new(DeadBranchLint::new()))
+            .check(&file)
+            .iter()
+            .flat_map(|diagnostic| diagnostic.suggestions())
+            .map(|fix| {
+                let range = fix.location().range();
+                format!(
+                    "{}{}{}",
+                    &source[..usize::from(range.start)],
+                    fix.replacement().unwrap(),
+                    &source[usize::from(range.end)..]
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn conditions() {
+        assert_eq!(
+            lint_diagnostics(
+                DeadBranchLint::new(),
+                Compatibility::All,
+                "if TINY_MAP #const A 1 elseif SMALL_MAP #const A 2 else #const A 3 endif"
+            ),
+            vec![]
+        );
+        assert_eq!(
+            lint_diagnostics(
+                DeadBranchLint::new(),
+                Compatibility::All,
+                "if SOME_DEFINE #const A 1 endif"
+            ),
+            vec![(
+                Severity::Warning,
+                "This branch is never taken, because `SOME_DEFINE` is never defined".to_string()
+            )]
+        );
+        assert_eq!(
+            lint_diagnostics(
+                DeadBranchLint::new(),
+                Compatibility::All,
+                "if TINY_MAP #const A 1 elseif TINY_MAP #const A 2 endif"
+            ),
+            vec![(
+                Severity::Warning,
+                "This branch is never taken, because an earlier branch checks the same condition"
+                    .to_string()
+            )]
+        );
+        // Builtin defines depend on the compatibility.
+        assert_eq!(
+            lint_diagnostics(
+                DeadBranchLint::new(),
+                Compatibility::All,
+                "if UP_EXTENSION #const A 1 else #const A 2 endif"
+            ),
+            vec![]
+        );
+        assert_eq!(
+            lint_diagnostics(
+                DeadBranchLint::new(),
+                Compatibility::UserPatch15,
+                "if UP_EXTENSION #const A 1 else #const A 2 endif"
+            ),
+            vec![
+                (
+                    Severity::Hint,
+                    "`UP_EXTENSION` is always defined here, so this condition is always true"
+                        .to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "This branch is never taken, because an earlier branch is always taken"
+                        .to_string()
+                ),
+            ]
+        );
+        // Script-level defines are toggles, so conditions on them are not reported.
+        assert_eq!(
+            lint_diagnostics(
+                DeadBranchLint::new(),
+                Compatibility::All,
+                "#define WATER_MAP\r\nif WATER_MAP #const A 1 else #const A 2 endif"
+            ),
+            vec![]
+        );
+        assert_eq!(
+            lint_diagnostics(
+                DeadBranchLint::new(),
+                Compatibility::All,
+                "if TINY_MAP #define A endif\r\nif A #const B 1 endif"
+            ),
+            vec![]
+        );
+        // Builtin defines are always set, unless they are undefined again.
+        assert_eq!(
+            lint_diagnostics(
+                DeadBranchLint::new(),
+                Compatibility::UserPatch15,
+                "#undefine UP_EXTENSION\r\nif UP_EXTENSION #const A 1 else #const A 2 endif"
+            ),
+            vec![]
+        );
+        // Nested conditions.
+        assert_eq!(
+            lint_diagnostics(
+                DeadBranchLint::new(),
+                Compatibility::All,
+                "if TINY_MAP if TINY_MAP #const A 1 endif elseif SMALL_MAP if TINY_MAP #const A 2 endif endif"
+            ),
+            vec![(
+                Severity::Hint,
+                "`TINY_MAP` is always defined here, so this condition is always true".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn same_as_else() {
+        assert_eq!(
+            lint_diagnostics(
+                DeadBranchLint::new(),
+                Compatibility::All,
+                "if TINY_MAP #const A 1 elseif SMALL_MAP #const A 2 elseif MEDIUM_MAP #const  A 2 else #const A 2 endif"
+            ),
+            vec![
+                (
+                    Severity::Warning,
+                    "This branch does the same as the `else` branch".to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "This branch does the same as the `else` branch".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn fixes() {
+        assert_eq!(
+            fixed("if TINY_MAP\r\n  #const A 1\r\nelseif TINY_MAP\r\n  #const A 2\r\nendif\r\n"),
+            vec!["if TINY_MAP\r\n  #const A 1\r\nendif\r\n"]
+        );
+        assert_eq!(
+            fixed("if NOPE\r\n  #const A 1\r\nelseif TINY_MAP\r\n  #const A 2\r\nendif\r\n"),
+            vec!["if TINY_MAP\r\n  #const A 2\r\nendif\r\n"]
+        );
+        assert_eq!(
+            fixed("if NOPE\r\n  #const A 1\r\nelse\r\n  #const A 2\r\nendif\r\n"),
+            vec!["#const A 2\r\n"]
+        );
+        assert_eq!(fixed("if NOPE\r\n  #const A 1\r\nendif\r\n"), vec!["\r\n"]);
+        assert_eq!(
+            fixed("if SMALL_MAP\r\nif TINY_MAP\r\n  #const A 1\r\nelseif SMALL_MAP\r\n  #const A 2\r\nelse\r\n  #const A 3\r\nendif\r\nendif\r\n"),
+            vec![
+                "if SMALL_MAP\r\nif TINY_MAP\r\n  #const A 1\r\nelse\r\n  #const A 2\r\nendif\r\nendif\r\n",
+                "if SMALL_MAP\r\nif TINY_MAP\r\n  #const A 1\r\nelseif SMALL_MAP\r\n  #const A 2\r\nendif\r\nendif\r\n",
+            ]
+        );
+    }
+}
@@ -1,7 +1,7 @@
 use crate::diagnostic::{Diagnostic, Fix};
 use crate::{Atom, AtomKind, Lint, ParseState};
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct IncludeLint {}
 
 impl IncludeLint {
@@ -14,6 +14,9 @@ impl Lint for IncludeLint {
     fn name(&self) -> &'static str {
         "include"
     }
+    fn snapshot(&self) -> Option<Box<dyn Lint>> {
+        Some(Box::new(self.clone()))
+    }
     fn lint_atom(&mut self, _state: &mut ParseState<'_>, atom: &Atom<'_>) -> Vec<Diagnostic> {
         match atom.kind {
             AtomKind::Command { name, .. } if name.value == "#include_drs" => {
@@ -2,7 +2,7 @@ use crate::diagnostic::{Diagnostic, Label};
 use crate::{Atom, AtomKind, Lint, ParseState, TokenContext, TOKENS};
 use cow_utils::CowUtils;
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct IncorrectSectionLint {}
 
 impl IncorrectSectionLint {
@@ -15,6 +15,9 @@ impl Lint for IncorrectSectionLint {
     fn name(&self) -> &'static str {
         "incorrect-section"
     }
+    fn snapshot(&self) -> Option<Box<dyn Lint>> {
+        Some(Box::new(self.clone()))
+    }
 
     fn lint_atom(&mut self, state: &mut ParseState<'_>, atom: &Atom<'_>) -> Vec<Diagnostic> {
         if let AtomKind::Command { name, .. } = atom.kind {
@@ -0,0 +1,548 @@
+use crate::diagnostic::{Diagnostic, SourceLocation};
+use crate::{Atom, AtomKind, Lint, ParseState, TokenContext, Word, TOKENS};
+use cow_utils::CowUtils;
+
+/// A map size that scripts can check for.
+struct MapSize {
+    /// The `#define` that is set for this map size.
+    define: &'static str,
+    /// The width and height of the map in tiles.
+    width: i64,
+    /// The usual number of players for this map size.
+    players: i64,
+}
+
+impl MapSize {
+    const fn tiles(&self) -> i64 {
+        self.width * self.width
+    }
+}
+
+const NUM_MAP_SIZES: usize = 6;
+
+const MAP_SIZES: [MapSize; NUM_MAP_SIZES] = [
+    MapSize {
+        define: "TINY_MAP",
+        width: 120,
+        players: 2,
+    },
+    MapSize {
+        define: "SMALL_MAP",
+        width: 144,
+        players: 3,
+    },
+    MapSize {
+        define: "MEDIUM_MAP",
+        width: 168,
+        players: 4,
+    },
+    MapSize {
+        define: "LARGE_MAP",
+        width: 200,
+        players: 6,
+    },
+    MapSize {
+        define: "HUGE_MAP",
+        width: 220,
+        players: 8,
+    },
+    MapSize {
+        define: "GIGANTIC_MAP",
+        width: 240,
+        players: 8,
+    },
+];
+
+/// The size of the map that `number_of_tiles` is relative to when using `set_scale_by_size` or
+/// `set_scale_by_groups`.
+const SCALE_BASE_TILES: i64 = 100 * 100;
+
+/// The map sizes that some code applies to.
+type SizeMask = [bool; NUM_MAP_SIZES];
+
+const ALL_SIZES: SizeMask = [true; NUM_MAP_SIZES];
+
+fn size_mask(define: &str) -> Option<SizeMask> {
+    let index = MAP_SIZES.iter().position(|size| size.define == define)?;
+    let mut mask = [false; NUM_MAP_SIZES];
+    mask[index] = true;
+    Some(mask)
+}
+
+/// The land requested by some `create_land` and `create_player_lands` commands, for each map size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Allocation {
+    /// The sum of the `land_percent` values.
+    percent: [i64; NUM_MAP_SIZES],
+    /// The minimum number of tiles needed.
+    tiles: [i64; NUM_MAP_SIZES],
+}
+
+impl Allocation {
+    fn add(&mut self, other: &Allocation) {
+        for i in 0..NUM_MAP_SIZES {
+            self.percent[i] += other.percent[i];
+            self.tiles[i] += other.tiles[i];
+        }
+    }
+
+    /// Keep the largest values of `self` and `other`, for alternative branches.
+    fn max(&mut self, other: &Allocation) {
+        for i in 0..NUM_MAP_SIZES {
+            self.percent[i] = self.percent[i].max(other.percent[i]);
+            self.tiles[i] = self.tiles[i].max(other.tiles[i]);
+        }
+    }
+}
+
+/// An `if` statement or `start_random` block that we are inside of.
+#[derive(Clone)]
+struct Frame {
+    /// The map sizes that the code around this block applies to.
+    parent_sizes: SizeMask,
+    /// The map sizes that the current branch applies to.
+    sizes: SizeMask,
+    /// The map sizes that were checked for by previous branches.
+    claimed: SizeMask,
+    /// The largest allocation of the previous branches.
+    previous: Allocation,
+    /// The allocation of the current branch.
+    current: Allocation,
+}
+
+impl Frame {
+    fn new(parent_sizes: SizeMask) -> Self {
+        Self {
+            parent_sizes,
+            sizes: parent_sizes,
+            claimed: [false; NUM_MAP_SIZES],
+            previous: Allocation::default(),
+            current: Allocation::default(),
+        }
+    }
+
+    /// Start a new branch. `condition` is the condition for an `if` or `elseif` branch.
+    fn next_branch(&mut self, condition: Option<&str>) {
+        self.previous.max(&self.current);
+        self.current = Allocation::default();
+        let condition_sizes = condition.and_then(size_mask);
+        for i in 0..NUM_MAP_SIZES {
+            self.sizes[i] = self.parent_sizes[i]
+                && !self.claimed[i]
+                && condition_sizes.is_none_or(|mask| mask[i]);
+        }
+        if let Some(mask) = condition_sizes {
+            for (claimed, checked) in self.claimed.iter_mut().zip(mask.iter()) {
+                *claimed |= checked;
+            }
+        }
+    }
+
+    /// Get the allocation for the whole block.
+    fn finish(mut self) -> Allocation {
+        self.previous.max(&self.current);
+        self.previous
+    }
+}
+
+/// The kind of command whose attributes we are reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CommandKind {
+    Land,
+    PlayerLands,
+    Terrain,
+    Other,
+}
+
+/// A command whose attributes we are reading.
+#[derive(Clone)]
+struct Command {
+    kind: CommandKind,
+    /// Whether we are inside the braces of this command.
+    in_block: bool,
+    land_percent: Option<i64>,
+    number_of_tiles: Option<(i64, SourceLocation)>,
+    base_size: Option<i64>,
+    is_scaled: bool,
+}
+
+/// Check that the lands and terrains requested by a script fit on the map.
+#[derive(Default, Clone)]
+pub struct LandCapacityLint {
+    /// The `<LAND_GENERATION>` section header.
+    land_section: Option<SourceLocation>,
+    command: Option<Command>,
+    frames: Vec<Frame>,
+    /// The allocation of the code outside of any `if` statements and `start_random` blocks.
+    total: Allocation,
+}
+
+impl LandCapacityLint {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn sizes(&self) -> SizeMask {
+        self.frames.last().map_or(ALL_SIZES, |frame| frame.sizes)
+    }
+
+    fn start_command(&mut self, name: &Word<'_>) {
+        let lower_name = name.value.cow_to_ascii_lowercase();
+        let is_command = TOKENS
+            .get(lower_name.as_ref())
+            .is_some_and(|token| matches!(token.context(), TokenContext::Command(_)));
+        if !is_command {
+            return;
+        }
+        let kind = match lower_name.as_ref() {
+            "create_land" => CommandKind::Land,
+            "create_player_lands" => CommandKind::PlayerLands,
+            "create_terrain" => CommandKind::Terrain,
+            _ => CommandKind::Other,
+        };
+        self.command = Some(Command {
+            kind,
+            in_block: false,
+            land_percent: None,
+            number_of_tiles: None,
+            base_size: None,
+            is_scaled: false,
+        });
+    }
+
+    fn read_attribute(&mut self, state: &ParseState<'_>, name: &Word<'_>, arguments: &[Word<'_>]) {
+        let command = match &mut self.command {
+            Some(command) if command.in_block => command,
+            _ => return,
+        };
+        // Use the smallest possible value, so only requests that are always impossible are
+        // reported.
+        let value = arguments
+            .first()
+            .and_then(|arg| state.evaluate(arg.value))
+            .map(|value| i64::from(value.min().max(0)));
+        match name.value.cow_to_ascii_lowercase().as_ref() {
+            "land_percent" => command.land_percent = value,
+            "number_of_tiles" => {
+                command.number_of_tiles = value.map(|value| (value, arguments[0].location));
+            }
+            "base_size" => command.base_size = value,
+            "set_scale_by_size" | "set_scale_by_groups" => command.is_scaled = true,
+            _ => (),
+        }
+    }
+
+    fn finish_command(&mut self) -> Vec<Diagnostic> {
+        let command = match self.command.take() {
+            Some(command) if command.in_block => command,
+            _ => return vec![],
+        };
+        let sizes = self.sizes();
+        match command.kind {
+            CommandKind::Land | CommandKind::PlayerLands => {
+                let mut allocation = Allocation::default();
+                for (i, size) in MAP_SIZES.iter().enumerate() {
+                    if !sizes[i] {
+                        continue;
+                    }
+                    let count = if command.kind == CommandKind::PlayerLands {
+                        size.players
+                    } else {
+                        1
+                    };
+                    // `land_percent 100` is commonly used to let a land fill up the remaining
+                    // space, so it does not claim a share of the map.
+                    let percent = command
+                        .land_percent
+                        .filter(|&percent| percent < 100)
+                        .unwrap_or(0);
+                    let tiles = command.number_of_tiles.map_or(0, |(tiles, _)| tiles) * count;
+                    let base_area = command.base_size.map_or(0, |base_size| {
+                        (2 * base_size + 1) * (2 * base_size + 1) * count
+                    });
+                    allocation.percent[i] = percent;
+                    allocation.tiles[i] = (percent * size.tiles() / 100).max(tiles).max(base_area);
+                }
+                match self.frames.last_mut() {
+                    Some(frame) => frame.current.add(&allocation),
+                    None => self.total.add(&allocation),
+                }
+                vec![]
+            }
+            CommandKind::Terrain => {
+                let (tiles, location) = match command.number_of_tiles {
+                    Some(number_of_tiles) => number_of_tiles,
+                    None => return vec![],
+                };
+                // The largest map size that is too small for this terrain.
+                let too_small = MAP_SIZES
+                    .iter()
+                    .zip(sizes.iter())
+                    .filter(|(_, &applies)| applies)
+                    .map(|(size, _)| size)
+                    .rfind(|size| {
+                        let needed = if command.is_scaled {
+                            tiles * size.tiles() / SCALE_BASE_TILES
+                        } else {
+                            tiles
+                        };
+                        needed > size.tiles()
+                    });
+                match too_small {
+                    Some(size) => vec![Diagnostic::warning(
+                        location,
+                        format_args!(
+                            "This terrain needs more tiles than a {} has ({}x{}, {} tiles)",
+                            size.define,
+                            size.width,
+                            size.width,
+                            size.tiles()
+                        ),
+                    )],
+                    None => vec![],
+                }
+            }
+            CommandKind::Other => vec![],
+        }
+    }
+}
+
+impl Lint for LandCapacityLint {
+    fn name(&self) -> &'static str {
+        "land-capacity"
+    }
+    fn snapshot(&self) -> Option<Box<dyn Lint>> {
+        Some(Box::new(self.clone()))
+    }
+    fn lint_atom(&mut self, state: &mut ParseState<'_>, atom: &Atom<'_>) -> Vec<Diagnostic> {
+        match &atom.kind {
+            AtomKind::Section { name } => {
+                if name.value == "<LAND_GENERATION>" && self.land_section.is_none() {
+                    self.land_section = Some(atom.location);
+                }
+                self.command = None;
+            }
+            AtomKind::Command { name, arguments } => {
+                if self
+                    .command
+                    .as_ref()
+                    .is_some_and(|command| command.in_block)
+                {
+                    self.read_attribute(state, name, arguments);
+                } else {
+                    self.start_command(name);
+                }
+            }
+            AtomKind::OpenBlock { .. } => {
+                if let Some(command) = &mut self.command {
+                    command.in_block = true;
+                }
+            }
+            AtomKind::CloseBlock { .. } => return self.finish_command(),
+            AtomKind::If { condition, .. } => {
+                let mut frame = Frame::new(self.sizes());
+                frame.next_branch(Some(condition.value));
+                self.frames.push(frame);
+            }
+            AtomKind::ElseIf { condition, .. } => {
+                if let Some(frame) = self.frames.last_mut() {
+                    frame.next_branch(Some(condition.value));
+                }
+            }
+            AtomKind::Else { .. } | AtomKind::PercentChance { .. } => {
+                if let Some(frame) = self.frames.last_mut() {
+                    frame.next_branch(None);
+                }
+            }
+            AtomKind::StartRandom { .. } => self.frames.push(Frame::new(self.sizes())),
+            AtomKind::EndIf { .. } | AtomKind::EndRandom { .. } => {
+                if let Some(frame) = self.frames.pop() {
+                    let allocation = frame.finish();
+                    match self.frames.last_mut() {
+                        Some(parent) => parent.current.add(&allocation),
+                        None => self.total.add(&allocation),
+                    }
+                }
+            }
+            _ => (),
+        }
+        vec![]
+    }
+    fn lint_end(&mut self, _state: &mut ParseState<'_>) -> Vec<Diagnostic> {
+        let location = match self.land_section {
+            Some(location) => location,
+            None => return vec![],
+        };
+        // Lands that are inside unclosed blocks are not counted.
+        let total = &self.total;
+
+        let max_percent = total.percent.iter().copied().max().unwrap_or(0);
+        if max_percent > 100 {
+            return vec![Diagnostic::warning(
+                location,
+                format_args!(
+                    "The `land_percent` values of the lands add up to {}%, so some lands can not grow to their full size",
+                    max_percent
+                ),
+            )];
+        }
+
+        let too_small = MAP_SIZES
+            .iter()
+            .enumerate()
+            .rfind(|(i, size)| total.tiles[*i] > size.tiles());
+        match too_small {
+            Some((i, size)) => vec![Diagnostic::warning(
+                location,
+                format_args!(
+                    "The lands need at least {} tiles on a {} with {} players, but the map only has {} tiles ({}x{})",
+                    total.tiles[i],
+                    size.define,
+                    size.players,
+                    size.tiles(),
+                    size.width,
+                    size.width
+                ),
+            )],
+            None => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lints::lint_messages;
+    use crate::Compatibility;
+
+    #[test]
+    fn land_percent() {
+        assert_eq!(
+            lint_messages(
+                LandCapacityLint::new(),
+                Compatibility::default(),
+                "<LAND_GENERATION>\r\ncreate_player_lands { land_percent 60 }\r\ncreate_land { land_percent 40 }\r\n"
+            ),
+            Vec::<String>::new()
+        );
+        // Lands with `land_percent 100` take up the remaining space.
+        assert_eq!(
+            lint_messages(
+                LandCapacityLint::new(),
+                Compatibility::default(),
+                "<LAND_GENERATION>\r\ncreate_land { land_percent 100 }\r\ncreate_land { land_percent 100 }\r\ncreate_land { land_percent 30 }\r\n"
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            lint_messages(
+                LandCapacityLint::new(),
+                Compatibility::default(),
+                "<LAND_GENERATION>\r\ncreate_player_lands { land_percent 60 }\r\ncreate_land { land_percent 50 }\r\n"
+            ),
+            vec!["The `land_percent` values of the lands add up to 110%, so some lands can not grow to their full size"]
+        );
+        // Only one branch is used.
+        assert_eq!(
+            lint_messages(
+                LandCapacityLint::new(),
+                Compatibility::default(),
+                "#const BIG 60\r\n<LAND_GENERATION>\r\ncreate_player_lands { land_percent BIG }\r\nstart_random\r\npercent_chance 50 create_land { land_percent 40 }\r\npercent_chance 50 create_land { land_percent 30 }\r\nend_random\r\nif TINY_MAP create_land { land_percent 10 } endif\r\n"
+            ),
+            vec!["The `land_percent` values of the lands add up to 110%, so some lands can not grow to their full size"]
+        );
+        assert_eq!(
+            lint_messages(
+                LandCapacityLint::new(),
+                Compatibility::default(),
+                "<LAND_GENERATION>\r\ncreate_player_lands { land_percent 60 }\r\nif TINY_MAP create_land { land_percent 40 } else create_land { land_percent 30 } endif\r\n"
+            ),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn tiles() {
+        // 8 players with 1600 tiles each do not fit on a gigantic map.
+        assert_eq!(
+            lint_messages(
+                LandCapacityLint::new(),
+                Compatibility::default(),
+                "<LAND_GENERATION>\r\ncreate_player_lands { number_of_tiles 8000 }\r\n"
+            ),
+            vec!["The lands need at least 64000 tiles on a GIGANTIC_MAP with 8 players, but the map only has 57600 tiles (240x240)"]
+        );
+        // Bases are squares around the center of each land.
+        assert_eq!(
+            lint_messages(
+                LandCapacityLint::new(),
+                Compatibility::default(),
+                "<LAND_GENERATION>\r\ncreate_player_lands { base_size 30 }\r\n"
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            lint_messages(
+                LandCapacityLint::new(),
+                Compatibility::default(),
+                "<LAND_GENERATION>\r\ncreate_player_lands { base_size 60 }\r\n"
+            ),
+            vec!["The lands need at least 117128 tiles on a GIGANTIC_MAP with 8 players, but the map only has 57600 tiles (240x240)"]
+        );
+        assert_eq!(
+            // Only the largest branch counts.
+            lint_messages(
+                LandCapacityLint::new(),
+                Compatibility::default(),
+                "<LAND_GENERATION>\r\nif TINY_MAP\r\ncreate_player_lands { base_size 20 }\r\nelse\r\ncreate_player_lands { base_size 60 }\r\nendif\r\n"
+            ),
+            vec!["The lands need at least 117128 tiles on a GIGANTIC_MAP with 8 players, but the map only has 57600 tiles (240x240)"]
+        );
+    }
+
+    #[test]
+    fn terrain_tiles() {
+        assert_eq!(
+            lint_messages(
+                LandCapacityLint::new(),
+                Compatibility::default(),
+                "<TERRAIN_GENERATION>\r\ncreate_terrain DESERT { number_of_tiles 10000 }\r\n"
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            lint_messages(
+                LandCapacityLint::new(),
+                Compatibility::default(),
+                "<TERRAIN_GENERATION>\r\ncreate_terrain DESERT { number_of_tiles 25000 }\r\n"
+            ),
+            vec!["This terrain needs more tiles than a SMALL_MAP has (144x144, 20736 tiles)"]
+        );
+        assert_eq!(
+            lint_messages(
+                LandCapacityLint::new(),
+                Compatibility::default(),
+                "<TERRAIN_GENERATION>\r\ncreate_terrain DESERT { number_of_tiles 15000 }\r\n"
+            ),
+            vec!["This terrain needs more tiles than a TINY_MAP has (120x120, 14400 tiles)"]
+        );
+        // Scaled tiles are relative to a 100x100 map.
+        assert_eq!(
+            lint_messages(
+                LandCapacityLint::new(),
+                Compatibility::default(),
+                "<TERRAIN_GENERATION>\r\ncreate_terrain DESERT { number_of_tiles 15000 set_scale_by_size }\r\n"
+            ),
+            vec!["This terrain needs more tiles than a GIGANTIC_MAP has (240x240, 57600 tiles)"]
+        );
+        // Only the map sizes that the terrain is created on are checked.
+        assert_eq!(
+            lint_messages(
+                LandCapacityLint::new(),
+                Compatibility::default(),
+                "<TERRAIN_GENERATION>\r\nif LARGE_MAP\r\ncreate_terrain DESERT { number_of_tiles 15000 }\r\nendif\r\n"
+            ),
+            Vec::<String>::new()
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use super::branches::{excludes, BranchId, Branches};
+use crate::diagnostic::{Diagnostic, Label, SourceLocation};
+use crate::{Atom, AtomKind, Lint, ParseState, TokenContext, Word, TOKENS};
+use cow_utils::CowUtils;
+use std::collections::HashMap;
+
+/// A `land_id` or `zone` attribute.
+#[derive(Clone)]
+struct Declaration {
+    /// The argument to the attribute.
+    location: SourceLocation,
+    /// The conditional and random blocks that the attribute is inside of.
+    branches: Vec<BranchId>,
+}
+
+/// Check that lands referred to by `place_on_specific_land_id` exist, that land ids are unique,
+/// and that `create_connect_same_land_zones` has lands in the same zone to connect.
+#[derive(Default, Clone)]
+pub struct LandIdsMatchLint {
+    /// The land ids declared in `<LAND_GENERATION>`.
+    land_ids: HashMap<i32, Vec<Declaration>>,
+    /// The zones declared in `<LAND_GENERATION>`.
+    zones: HashMap<i32, Vec<Declaration>>,
+    /// Whether the script creates player lands, which can be referred to by player number.
+    has_player_lands: bool,
+    /// Whether more than one land can be in the same zone. Lands without a `zone` attribute are
+    /// each in their own zone.
+    has_shared_zones: bool,
+    /// The command whose attributes we are reading.
+    command: Option<String>,
+    /// The branches that we are currently inside of.
+    branches: Branches,
+}
+
+impl LandIdsMatchLint {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn check_land_id(&mut self, state: &ParseState<'_>, arg: &Word<'_>) -> Vec<Diagnostic> {
+        let id = match state.evaluate(arg.value) {
+            Some(value) if value.is_number() => value.min(),
+            _ => return vec![],
+        };
+        let land_id = Declaration {
+            location: arg.location,
+            branches: self.branches.current(),
+        };
+        let previous = self.land_ids.entry(id).or_default();
+        let duplicate = previous
+            .iter()
+            .find(|other| !excludes(&other.branches, &land_id.branches))
+            .map(|other| other.location);
+        previous.push(land_id);
+
+        match duplicate {
+            Some(other) => vec![Diagnostic::warning(
+                arg.location,
+                format_args!("Land id {} is already used by another land, so objects placed on it may end up on either land", id),
+            )
+            .add_label(Label::new(other, "First used here"))],
+            None => vec![],
+        }
+    }
+
+    fn check_zone(&mut self, state: &ParseState<'_>, arg: &Word<'_>) {
+        let id = match state.evaluate(arg.value) {
+            Some(value) if value.is_number() => value.min(),
+            // A random zone may be the same as another land's.
+            _ => {
+                self.has_shared_zones = true;
+                return;
+            }
+        };
+        let zone = Declaration {
+            location: arg.location,
+            branches: self.branches.current(),
+        };
+        let previous = self.zones.entry(id).or_default();
+        // All player lands are put in the zone.
+        if self.command.as_deref() == Some("create_player_lands")
+            || previous
+                .iter()
+                .any(|other| !excludes(&other.branches, &zone.branches))
+        {
+            self.has_shared_zones = true;
+        }
+        previous.push(zone);
+    }
+
+    fn check_zone_connection(&self, name: &Word<'_>) -> Option<Diagnostic> {
+        if self.has_shared_zones {
+            return None;
+        }
+        Some(Diagnostic::warning(
+            name.location,
+            "No two lands are in the same zone, so `create_connect_same_land_zones` does not connect anything",
+        ))
+    }
+
+    fn check_reference(&self, state: &ParseState<'_>, arg: &Word<'_>) -> Option<Diagnostic> {
+        let id = match state.evaluate(arg.value) {
+            Some(value) if value.is_number() => value.min(),
+            _ => return None,
+        };
+        if self.land_ids.contains_key(&id) || (self.has_player_lands && (1..=8).contains(&id)) {
+            return None;
+        }
+        Some(Diagnostic::warning(
+            arg.location,
+            format_args!("Land id {} is never defined", id),
+        ))
+    }
+}
+
+impl Lint for LandIdsMatchLint {
+    fn name(&self) -> &'static str {
+        "land-ids-match"
+    }
+    fn snapshot(&self) -> Option<Box<dyn Lint>> {
+        Some(Box::new(self.clone()))
+    }
+    fn lint_atom(&mut self, state: &mut ParseState<'_>, atom: &Atom<'_>) -> Vec<Diagnostic> {
+        self.branches.update(atom);
+        let (name, arguments) = match &atom.kind {
+            AtomKind::Command { name, arguments } => (name, arguments),
+            AtomKind::CloseBlock { .. } => {
+                self.command = None;
+                return vec![];
+            }
+            _ => return vec![],
+        };
+
+        let lower_name = name.value.cow_to_ascii_lowercase();
+        let is_command = TOKENS
+            .get(lower_name.as_ref())
+            .is_some_and(|token| matches!(token.context(), TokenContext::Command(_)));
+        if is_command {
+            let warnings = match lower_name.as_ref() {
+                "create_player_lands" => {
+                    self.has_player_lands = true;
+                    vec![]
+                }
+                "create_connect_same_land_zones" => {
+                    self.check_zone_connection(name).into_iter().collect()
+                }
+                _ => vec![],
+            };
+            self.command = Some(lower_name.into_owned());
+            return warnings;
+        }
+
+        let in_land = matches!(
+            self.command.as_deref(),
+            Some("create_land") | Some("create_player_lands")
+        );
+        if in_land && (lower_name == "set_zone_by_team" || lower_name == "set_zone_randomly") {
+            self.has_shared_zones = true;
+            return vec![];
+        }
+        let arg = match arguments.first() {
+            Some(arg) => arg,
+            None => return vec![],
+        };
+        match lower_name.as_ref() {
+            "land_id" if in_land => self.check_land_id(state, arg),
+            "zone" if in_land => {
+                self.check_zone(state, arg);
+                vec![]
+            }
+            "place_on_specific_land_id" => self.check_reference(state, arg).into_iter().collect(),
+            _ => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lints::{check_with, lint_messages};
+    use crate::Compatibility;
+
+    #[test]
+    fn references() {
+        assert_eq!(
+            lint_messages(
+                LandIdsMatchLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<LAND_GENERATION>\r\ncreate_land { land_id 10 }\r\n<OBJECTS_GENERATION>\r\ncreate_object GOLD { place_on_specific_land_id 10 }\r\ncreate_object GOLD { place_on_specific_land_id 11 }\r\n"
+            ),
+            vec!["Land id 11 is never defined"]
+        );
+        // Constants are evaluated.
+        assert_eq!(
+            lint_messages(
+                LandIdsMatchLint::new(),
+                Compatibility::DefinitiveEdition,
+                "#const ISLAND 10\r\n<LAND_GENERATION>\r\ncreate_land { land_id ISLAND }\r\n<OBJECTS_GENERATION>\r\ncreate_object GOLD { place_on_specific_land_id 10 }\r\n"
+            ),
+            Vec::<String>::new()
+        );
+        // Player lands can be referred to by player number.
+        assert_eq!(
+            lint_messages(
+                LandIdsMatchLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<LAND_GENERATION>\r\ncreate_player_lands { land_percent 50 }\r\n<OBJECTS_GENERATION>\r\ncreate_object GOLD { place_on_specific_land_id 3 }\r\ncreate_object GOLD { place_on_specific_land_id 9 }\r\n"
+            ),
+            vec!["Land id 9 is never defined"]
+        );
+    }
+
+    #[test]
+    fn duplicate_land_ids() {
+        let source =
+            "<LAND_GENERATION>\r\ncreate_land { land_id 10 }\r\ncreate_land { land_id 10 }\r\n";
+        let result = check_with(LandIdsMatchLint::new(), Compatibility::default(), source);
+        let warning = result.iter().next().unwrap();
+        assert_eq!(warning.code(), Some("land-ids-match"));
+        assert_eq!(
+            warning.message(),
+            "Land id 10 is already used by another land, so objects placed on it may end up on either land"
+        );
+        assert_eq!(warning.location().range(), 69.into()..71.into());
+        let label = warning.labels().next().unwrap();
+        assert_eq!(label.location().range(), 41.into()..43.into());
+
+        // Lands in different branches can use the same id.
+        assert_eq!(
+            lint_messages(
+                LandIdsMatchLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<LAND_GENERATION>\r\nif TINY_MAP create_land { land_id 10 } else create_land { land_id 10 } endif\r\n"
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            lint_messages(
+                LandIdsMatchLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<LAND_GENERATION>\r\nif TINY_MAP create_land { land_id 10 } endif\r\nif SMALL_MAP create_land { land_id 10 } endif\r\n"
+            ),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn zone_connections() {
+        assert_eq!(
+            lint_messages(
+                LandIdsMatchLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<LAND_GENERATION>\r\ncreate_land { land_percent 10 zone 1 }\r\ncreate_land { land_percent 10 zone 2 }\r\n<CONNECTION_GENERATION>\r\ncreate_connect_same_land_zones { }\r\n"
+            ),
+            vec!["No two lands are in the same zone, so `create_connect_same_land_zones` does not connect anything"]
+        );
+        assert_eq!(
+            lint_messages(
+                LandIdsMatchLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<LAND_GENERATION>\r\ncreate_land { land_percent 10 zone 1 }\r\ncreate_land { land_percent 10 zone 1 }\r\n<CONNECTION_GENERATION>\r\ncreate_connect_same_land_zones { }\r\n"
+            ),
+            Vec::<String>::new()
+        );
+        // Lands in different branches never exist together.
+        assert_eq!(
+            lint_messages(
+                LandIdsMatchLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<LAND_GENERATION>\r\nif TINY_MAP create_land { zone 1 } else create_land { zone 1 } endif\r\n<CONNECTION_GENERATION>\r\ncreate_connect_same_land_zones { }\r\n"
+            ),
+            vec!["No two lands are in the same zone, so `create_connect_same_land_zones` does not connect anything"]
+        );
+        // Player lands can share zones.
+        assert_eq!(
+            lint_messages(
+                LandIdsMatchLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<LAND_GENERATION>\r\ncreate_player_lands { land_percent 50 set_zone_by_team }\r\n<CONNECTION_GENERATION>\r\ncreate_connect_same_land_zones { }\r\n"
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            lint_messages(
+                LandIdsMatchLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<LAND_GENERATION>\r\ncreate_player_lands { land_percent 50 zone 3 }\r\n<CONNECTION_GENERATION>\r\ncreate_connect_same_land_zones { }\r\n"
+            ),
+            Vec::<String>::new()
+        );
+    }
+}
@@ -1,17 +1,77 @@
 mod actor_areas_match;
 mod arg_types;
 mod attribute_case;
+mod attribute_context;
+mod branches;
 mod comment_contents;
 mod compatibility;
+mod conflicting_attributes;
+mod dead_branch;
 mod include;
 mod incorrect_section;
+mod land_capacity;
+mod land_ids_match;
+mod percent_chance;
+mod required_attributes;
+mod token_category;
 mod unknown_attribute;
+mod unused_definition;
 
 pub use self::actor_areas_match::ActorAreasMatchLint;
 pub use self::arg_types::ArgTypesLint;
 pub use self::attribute_case::AttributeCaseLint;
+pub use self::attribute_context::AttributeContextLint;
 pub use self::comment_contents::CommentContentsLint;
 pub use self::compatibility::CompatibilityLint;
+pub use self::conflicting_attributes::ConflictingAttributesLint;
+pub use self::dead_branch::DeadBranchLint;
 pub use self::include::IncludeLint;
 pub use self::incorrect_section::IncorrectSectionLint;
+pub use self::land_capacity::LandCapacityLint;
+pub use self::land_ids_match::LandIdsMatchLint;
+pub use self::percent_chance::PercentChanceLint;
+pub use self::required_attributes::RequiredAttributesLint;
+pub use self::token_category::TokenCategoryLint;
 pub use self::unknown_attribute::UnknownAttributeLint;
+pub use self::unused_definition::UnusedDefinitionLint;
+
+/// Check a script with only the given lint, for tests.
+#[cfg(test)]
+pub(crate) fn check_with(
+    lint: impl crate::Lint + 'static,
+    compatibility: crate::Compatibility,
+    source: &str,
+) -> crate::RMSCheckResult {
+    let file = crate::RMSFile::from_string(format!("{}.rms", lint.name()), source);
+    crate::RMSCheck::new()
+        .compatibility(compatibility)
+        .with_lint(Box::new(lint))
+        .check(&file)
+}
+
+/// Check a script with only the given lint, and get the messages that it reports, for tests.
+#[cfg(test)]
+pub(crate) fn lint_messages(
+    lint: impl crate::Lint + 'static,
+    compatibility: crate::Compatibility,
+    source: &str,
+) -> Vec<String> {
+    check_with(lint, compatibility, source)
+        .iter()
+        .map(|diagnostic| diagnostic.message().to_string())
+        .collect()
+}
+
+/// Check a script with only the given lint, and get the severities and messages that it reports,
+/// for tests.
+#[cfg(test)]
+pub(crate) fn lint_diagnostics(
+    lint: impl crate::Lint + 'static,
+    compatibility: crate::Compatibility,
+    source: &str,
+) -> Vec<(crate::Severity, String)> {
+    check_with(lint, compatibility, source)
+        .iter()
+        .map(|diagnostic| (diagnostic.severity(), diagnostic.message().to_string()))
+        .collect()
+}
@@ -0,0 +1,345 @@
+use crate::diagnostic::{ByteIndex, Diagnostic, Label, Severity, SourceLocation};
+use crate::{Atom, AtomKind, Lint, ParseState, Word};
+
+/// A `percent_chance` branch inside a `start_random` block.
+#[derive(Clone)]
+struct Branch {
+    /// The `percent_chance` statement.
+    location: SourceLocation,
+    /// The chance, if it is a known number.
+    chance: Option<i32>,
+    /// The source code of the branch, with whitespace normalized.
+    body: String,
+}
+
+#[derive(Clone)]
+struct RandomBlock {
+    /// The `start_random` statement.
+    location: SourceLocation,
+    branches: Vec<Branch>,
+    /// Where the source code of the last branch starts.
+    body_start: ByteIndex,
+    /// Whether some of the chances could not be resolved to a single number.
+    has_unknown_chances: bool,
+    /// Whether a branch was already reported as never taken, because the chances before it add up
+    /// to 100% or more.
+    has_unreachable_branches: bool,
+}
+
+impl RandomBlock {
+    /// Store the source code of the last branch, which ends at `end`.
+    fn end_branch(&mut self, state: &ParseState<'_>, end: ByteIndex) {
+        let source = state.rms.source(self.location.file());
+        if let Some(branch) = self.branches.last_mut() {
+            branch.body = source[usize::from(self.body_start)..usize::from(end)]
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+        }
+    }
+
+    fn sum(&self) -> i32 {
+        self.branches
+            .iter()
+            .filter_map(|branch| branch.chance)
+            .sum()
+    }
+}
+
+/// Check that the `percent_chance` branches in a `start_random` block add up to 100.
+#[derive(Default, Clone)]
+pub struct PercentChanceLint {
+    /// The `start_random` blocks we are inside of.
+    blocks: Vec<RandomBlock>,
+}
+
+impl PercentChanceLint {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn check_chance(
+        &mut self,
+        state: &ParseState<'_>,
+        atom: &Atom<'_>,
+        chance: &Word<'_>,
+    ) -> Vec<Diagnostic> {
+        let block = match self.blocks.last_mut() {
+            Some(block) => block,
+            None => return vec![],
+        };
+        block.end_branch(state, atom.location.start());
+
+        let mut warnings = vec![];
+        let previous_sum = block.sum();
+        if !block.has_unknown_chances && previous_sum >= 100 {
+            block.has_unreachable_branches = true;
+            warnings.push(
+                Diagnostic::warning(
+                    atom.location,
+                    format_args!(
+                        "This branch is never taken, because the previous branches already add up to {}%",
+                        previous_sum
+                    ),
+                )
+                .add_label(Label::new(block.location, "In this `start_random` block")),
+            );
+        }
+
+        let value = state.evaluate(chance.value);
+        let number = match value {
+            Some(value) if value.is_number() => Some(value.min()),
+            Some(_) => {
+                block.has_unknown_chances = true;
+                None
+            }
+            None => {
+                block.has_unknown_chances = true;
+                warnings.push(Diagnostic::error(
+                    chance.location,
+                    format_args!(
+                        "Expected a number for `percent_chance`, got `{}`",
+                        chance.value
+                    ),
+                ));
+                None
+            }
+        };
+        if let Some(value) = value {
+            if value.min() < 0 {
+                warnings.push(Diagnostic::warning(
+                    chance.location,
+                    "The chance for a branch can not be negative",
+                ));
+            } else if value.max() == 0 {
+                warnings.push(Diagnostic::warning(
+                    atom.location,
+                    "This branch has a 0% chance, so it is never taken",
+                ));
+            }
+        }
+
+        block.branches.push(Branch {
+            location: atom.location,
+            chance: number,
+            body: String::new(),
+        });
+        block.body_start = atom.location.end();
+        warnings
+    }
+
+    fn check_block(&mut self, state: &ParseState<'_>, end_random: &Atom<'_>) -> Vec<Diagnostic> {
+        let mut block = match self.blocks.pop() {
+            Some(block) => block,
+            None => return vec![],
+        };
+        block.end_branch(state, end_random.location.start());
+
+        let mut warnings = vec![];
+        for (index, branch) in block.branches.iter().enumerate() {
+            if branch.body.is_empty() {
+                continue;
+            }
+            if let Some(original) = block.branches[..index]
+                .iter()
+                .find(|original| original.body == branch.body)
+            {
+                warnings.push(
+                    Diagnostic::warning(
+                        branch.location,
+                        "This branch is the same as an earlier branch; consider combining their chances",
+                    )
+                    .add_label(Label::new(original.location, "Same as this branch")),
+                );
+            }
+        }
+
+        if block.has_unknown_chances || block.branches.is_empty() {
+            return warnings;
+        }
+        let sum = block.sum();
+        if sum > 100 && !block.has_unreachable_branches {
+            warnings.push(Diagnostic::warning(
+                block.location,
+                format_args!(
+                    "The chances in this block add up to {}%, branches after 100% are never taken",
+                    sum
+                ),
+            ));
+        } else if sum < 100 && block.branches.len() > 1 {
+            // A single `percent_chance` is a common way to do something only some of the time,
+            // but with multiple branches it is easy to miscount.
+            warnings.push(
+                Diagnostic::warning(
+                    block.location,
+                    format_args!(
+                        "The chances in this block add up to {}%, so no branch is taken {}% of the time",
+                        sum,
+                        100 - sum
+                    ),
+                )
+                .with_severity(Severity::Hint),
+            );
+        }
+        warnings
+    }
+}
+
+impl Lint for PercentChanceLint {
+    fn name(&self) -> &'static str {
+        "percent-chance"
+    }
+    fn snapshot(&self) -> Option<Box<dyn Lint>> {
+        Some(Box::new(self.clone()))
+    }
+    fn lint_atom(&mut self, state: &mut ParseState<'_>, atom: &Atom<'_>) -> Vec<Diagnostic> {
+        match &atom.kind {
+            AtomKind::StartRandom { .. } => {
+                self.blocks.push(RandomBlock {
+                    location: atom.location,
+                    branches: vec![],
+                    body_start: atom.location.end(),
+                    has_unknown_chances: false,
+                    has_unreachable_branches: false,
+                });
+                vec![]
+            }
+            AtomKind::PercentChance { chance, .. } => self.check_chance(state, atom, chance),
+            AtomKind::EndRandom { .. } => self.check_block(state, atom),
+            _ => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lints::lint_diagnostics;
+    use crate::{Compatibility, RMSCheck, RMSFile};
+
+    #[test]
+    fn sums() {
+        assert_eq!(
+            lint_diagnostics(
+                PercentChanceLint::new(),
+                Compatibility::default(),
+                "start_random percent_chance 60 #define A percent_chance 40 #define B end_random"
+            ),
+            vec![]
+        );
+        assert_eq!(
+            lint_diagnostics(
+                PercentChanceLint::new(),
+                Compatibility::default(),
+                "start_random percent_chance 30 #define A end_random"
+            ),
+            vec![]
+        );
+        assert_eq!(
+            lint_diagnostics(
+                PercentChanceLint::new(),
+                Compatibility::default(),
+                "start_random percent_chance 60 #define A percent_chance 50 #define B end_random"
+            ),
+            vec![(
+                Severity::Warning,
+                "The chances in this block add up to 110%, branches after 100% are never taken"
+                    .to_string()
+            )]
+        );
+        // Only the branch that is never taken is reported, not the whole block.
+        assert_eq!(
+            lint_diagnostics(
+                PercentChanceLint::new(),
+                Compatibility::default(),
+                "start_random percent_chance 60 #define A percent_chance 40 #define B percent_chance 10 #define C end_random"
+            ),
+            vec![(
+                Severity::Warning,
+                "This branch is never taken, because the previous branches already add up to 100%"
+                    .to_string()
+            )]
+        );
+        assert_eq!(
+            lint_diagnostics(
+                PercentChanceLint::new(),
+                Compatibility::default(),
+                "start_random percent_chance 30 #define A percent_chance 30 #define B end_random"
+            ),
+            vec![(
+                Severity::Hint,
+                "The chances in this block add up to 60%, so no branch is taken 40% of the time"
+                    .to_string()
+            )]
+        );
+        // Constants are resolved.
+        assert_eq!(
+            lint_diagnostics(PercentChanceLint::new(), Compatibility::default(), "#const HALF 50\r\nstart_random percent_chance HALF #define A percent_chance HALF #define B end_random"),
+            vec![]
+        );
+        // Nested blocks are checked separately.
+        assert_eq!(
+            lint_diagnostics(PercentChanceLint::new(), Compatibility::default(), "start_random percent_chance 50 start_random percent_chance 50 #define A percent_chance 50 #define B end_random percent_chance 50 #define C end_random"),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn bad_chances() {
+        assert_eq!(
+            lint_diagnostics(PercentChanceLint::new(), Compatibility::default(), "start_random percent_chance SOME #define A percent_chance -5 #define B percent_chance 0 #define C end_random"),
+            vec![
+                (
+                    Severity::Error,
+                    "Expected a number for `percent_chance`, got `SOME`".to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "The chance for a branch can not be negative".to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "This branch has a 0% chance, so it is never taken".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn unreachable_and_duplicate_branches() {
+        let file = RMSFile::from_string(
+            "percent_chance.rms",
+            "start_random\r\npercent_chance 50 #define A\r\npercent_chance 50 #define  A\r\npercent_chance 10 #define B\r\nend_random\r\n",
+        );
+        let result = RMSCheck::new()
+            .with_lint(Box::new(PercentChanceLint::new()))
+            .check(&file);
+        let mut warnings = result.iter();
+
+        let first = warnings.next().unwrap();
+        assert_eq!(first.code(), Some("percent-chance"));
+        assert_eq!(
+            first.message(),
+            "This branch is never taken, because the previous branches already add up to 100%"
+        );
+        assert_eq!(
+            first.location(),
+            SourceLocation::new(file.file_id(), ByteIndex::from(73)..ByteIndex::from(90))
+        );
+        let second = warnings.next().unwrap();
+        assert_eq!(
+            second.message(),
+            "This branch is the same as an earlier branch; consider combining their chances"
+        );
+        assert_eq!(
+            second.location(),
+            SourceLocation::new(file.file_id(), ByteIndex::from(43)..ByteIndex::from(60))
+        );
+        let label = second.labels().next().unwrap();
+        assert_eq!(
+            label.location(),
+            SourceLocation::new(file.file_id(), ByteIndex::from(14)..ByteIndex::from(31))
+        );
+        assert!(warnings.next().is_none());
+    }
+}
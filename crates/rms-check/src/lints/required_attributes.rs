@@ -0,0 +1,310 @@
+use crate::diagnostic::{Diagnostic, Label};
+use crate::state::MAP_SIZE_DEFINES;
+use crate::{AtomKind, Block, BlockAttribute, Lint, Nesting, ParseState, TOKENS};
+use cow_utils::CowUtils;
+
+/// Are `a` and `b` the same branch?
+fn same_branch(a: &[Nesting<'_>], b: &[Nesting<'_>]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.location() == b.location())
+}
+
+/// Get the branches in `block` that are directly inside `branch`.
+fn branches_in<'b, 'a>(
+    block: &'b Block<'a>,
+    branch: &'b [Nesting<'a>],
+) -> impl Iterator<Item = &'b [Nesting<'a>]> + 'b {
+    let depth = branch.len();
+    block
+        .branches
+        .iter()
+        .filter(move |nesting| nesting.len() == depth + 1 && same_branch(&nesting[..depth], branch))
+        .map(Vec::as_slice)
+}
+
+/// An `if` statement or `start_random` block.
+struct Statement<'b, 'a> {
+    /// The nesting of each branch.
+    branches: Vec<&'b [Nesting<'a>]>,
+    /// Whether one of the branches is always taken: for `if` statements, whether there is an
+    /// `else` or the conditions cover every map size, and for `start_random` blocks, whether the
+    /// chances add up to 100.
+    exhaustive: bool,
+}
+
+/// Get the `if` statements and `start_random` blocks that are directly inside `branch`.
+fn statements_in<'b, 'a>(
+    state: &ParseState<'_>,
+    block: &'b Block<'a>,
+    branch: &'b [Nesting<'a>],
+) -> Vec<Statement<'b, 'a>> {
+    let mut statements: Vec<Statement<'_, '_>> = vec![];
+    // The conditions of the `if` statement that is being read.
+    let mut conditions = vec![];
+    for nesting in branches_in(block, branch) {
+        match (nesting.last(), statements.last_mut()) {
+            (Some(Nesting::If(atom)), _) => {
+                conditions.clear();
+                if let AtomKind::If { condition, .. } = &atom.kind {
+                    conditions.push(condition.value);
+                }
+                statements.push(Statement {
+                    branches: vec![nesting],
+                    exhaustive: false,
+                });
+            }
+            (Some(Nesting::ElseIf(atom)), Some(statement)) => {
+                if let AtomKind::ElseIf { condition, .. } = &atom.kind {
+                    conditions.push(condition.value);
+                }
+                statement.branches.push(nesting);
+                statement.exhaustive = MAP_SIZE_DEFINES
+                    .iter()
+                    .all(|size| conditions.contains(size));
+            }
+            (Some(Nesting::Else(_)), Some(statement)) => {
+                statement.branches.push(nesting);
+                statement.exhaustive = true;
+            }
+            (Some(Nesting::StartRandom(_)), _) => {
+                let branches: Vec<_> = branches_in(block, nesting).collect();
+                let chance: i32 = branches
+                    .iter()
+                    .filter_map(|branch| match &branch.last()?.atom().kind {
+                        AtomKind::PercentChance { chance, .. } => state.evaluate(chance.value),
+                        _ => None,
+                    })
+                    .filter(|value| value.is_number())
+                    .map(|value| value.min())
+                    .sum();
+                statements.push(Statement {
+                    branches,
+                    exhaustive: chance == 100,
+                });
+            }
+            _ => (),
+        }
+    }
+    statements
+}
+
+/// Is one of the `alternatives` used in every game where `branch` is used?
+fn is_met(
+    state: &ParseState<'_>,
+    block: &Block<'_>,
+    branch: &[Nesting<'_>],
+    alternatives: &[String],
+) -> bool {
+    let matches = |attribute: &BlockAttribute<'_>| {
+        alternatives
+            .iter()
+            .any(|name| attribute.name.value.eq_ignore_ascii_case(name))
+    };
+    block
+        .attributes
+        .iter()
+        .any(|attribute| same_branch(&attribute.nesting, branch) && matches(attribute))
+        || statements_in(state, block, branch).iter().any(|statement| {
+            statement.exhaustive
+                && statement
+                    .branches
+                    .iter()
+                    .all(|branch| is_met(state, block, branch, alternatives))
+        })
+}
+
+/// Check that blocks contain the attributes that they need, like `base_terrain` in
+/// `create_terrain`.
+///
+/// An attribute inside an `if` statement or `start_random` block only counts if it is used in
+/// every branch.
+#[derive(Default, Clone)]
+pub struct RequiredAttributesLint {}
+
+impl RequiredAttributesLint {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Lint for RequiredAttributesLint {
+    fn name(&self) -> &'static str {
+        "required-attributes"
+    }
+    fn snapshot(&self) -> Option<Box<dyn Lint>> {
+        Some(Box::new(self.clone()))
+    }
+    fn lint_block(&mut self, state: &mut ParseState<'_>, block: &Block<'_>) -> Vec<Diagnostic> {
+        let command = match TOKENS.get(block.name.value.cow_to_ascii_lowercase().as_ref()) {
+            Some(command) => command,
+            None => return vec![],
+        };
+
+        command
+            .required_attributes()
+            .iter()
+            .filter(|alternatives| !is_met(state, block, &[], alternatives))
+            .map(|alternatives| {
+                let names = alternatives
+                    .iter()
+                    .map(|name| format!("`{}`", name))
+                    .collect::<Vec<_>>()
+                    .join(" or ");
+                let conditional = block.attributes.iter().find(|attribute| {
+                    alternatives
+                        .iter()
+                        .any(|name| attribute.name.value.eq_ignore_ascii_case(name))
+                });
+                match conditional {
+                    Some(attribute) => Diagnostic::warning(
+                        block.name.location,
+                        format_args!(
+                            "`{}` needs {}, but it is not used in every branch",
+                            block.name.value, names
+                        ),
+                    )
+                    .add_label(Label::new(attribute.location, "Only used conditionally")),
+                    None => Diagnostic::warning(
+                        block.name.location,
+                        format_args!("`{}` needs {}", block.name.value, names),
+                    ),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lints::{check_with, lint_messages};
+    use crate::Compatibility;
+
+    #[test]
+    fn builtin_requirements() {
+        for token_type in TOKENS.values() {
+            for name in token_type.required_attributes().iter().flatten() {
+                assert!(
+                    TOKENS.contains_key(name),
+                    "`{}` requires unknown attribute `{}`",
+                    token_type.name,
+                    name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn missing() {
+        assert_eq!(
+            lint_messages(
+                RequiredAttributesLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<LAND_GENERATION>\r\ncreate_land { terrain_type DESERT }\r\ncreate_land { terrain_type DESERT number_of_tiles 100 }\r\n<TERRAIN_GENERATION>\r\ncreate_terrain FOREST { land_percent 5 }\r\n<ELEVATION_GENERATION>\r\ncreate_elevation 7 { base_terrain GRASS }\r\n"
+            ),
+            vec![
+                "`create_land` needs `land_percent` or `number_of_tiles`",
+                "`create_terrain` needs `base_terrain`",
+            ]
+        );
+        // Commands without a block are not checked.
+        assert!(lint_messages(
+            RequiredAttributesLint::new(),
+            Compatibility::DefinitiveEdition,
+            "<TERRAIN_GENERATION>\r\ncreate_terrain FOREST\r\n"
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn branches() {
+        assert!(lint_messages(
+            RequiredAttributesLint::new(),
+            Compatibility::DefinitiveEdition,
+            "<TERRAIN_GENERATION>\r\ncreate_terrain FOREST {\r\n  if TINY_MAP base_terrain GRASS\r\n  elseif SMALL_MAP base_terrain DIRT\r\n  else base_terrain DESERT\r\n  endif\r\n}\r\n"
+        ).is_empty());
+        assert!(lint_messages(
+            RequiredAttributesLint::new(),
+            Compatibility::DefinitiveEdition,
+            "<TERRAIN_GENERATION>\r\ncreate_terrain FOREST {\r\n  start_random\r\n    percent_chance 50 base_terrain GRASS\r\n    percent_chance 50 base_terrain DIRT\r\n  end_random\r\n}\r\n"
+        ).is_empty());
+        assert_eq!(
+            lint_messages(
+                RequiredAttributesLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<TERRAIN_GENERATION>\r\ncreate_terrain FOREST {\r\n  if TINY_MAP base_terrain GRASS\r\n  elseif SMALL_MAP base_terrain DIRT\r\n  endif\r\n}\r\n"
+            ),
+            vec!["`create_terrain` needs `base_terrain`, but it is not used in every branch"]
+        );
+        assert_eq!(
+            lint_messages(
+                RequiredAttributesLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<TERRAIN_GENERATION>\r\ncreate_terrain FOREST {\r\n  if TINY_MAP base_terrain GRASS\r\n  else land_percent 10\r\n  endif\r\n}\r\n"
+            ),
+            vec!["`create_terrain` needs `base_terrain`, but it is not used in every branch"]
+        );
+        assert_eq!(
+            lint_messages(
+                RequiredAttributesLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<TERRAIN_GENERATION>\r\ncreate_terrain FOREST {\r\n  start_random\r\n    percent_chance 50 base_terrain GRASS\r\n  end_random\r\n}\r\n"
+            ),
+            vec!["`create_terrain` needs `base_terrain`, but it is not used in every branch"]
+        );
+        // Alternatives can be used in different branches.
+        assert!(lint_messages(
+            RequiredAttributesLint::new(),
+            Compatibility::DefinitiveEdition,
+            "<LAND_GENERATION>\r\ncreate_land {\r\n  if TINY_MAP land_percent 10\r\n  else number_of_tiles 100\r\n  endif\r\n}\r\n"
+        ).is_empty());
+        // Empty branches do not use the attribute either.
+        assert_eq!(
+            lint_messages(
+                RequiredAttributesLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<TERRAIN_GENERATION>\r\ncreate_terrain FOREST {\r\n  if TINY_MAP base_terrain GRASS\r\n  elseif SMALL_MAP\r\n  else base_terrain DESERT\r\n  endif\r\n}\r\n"
+            ),
+            vec!["`create_terrain` needs `base_terrain`, but it is not used in every branch"]
+        );
+        // Nested statements.
+        assert!(lint_messages(
+            RequiredAttributesLint::new(),
+            Compatibility::DefinitiveEdition,
+            "<TERRAIN_GENERATION>\r\ncreate_terrain FOREST {\r\n  if TINY_MAP base_terrain GRASS\r\n  else\r\n    start_random\r\n      percent_chance 40 base_terrain DIRT\r\n      percent_chance 60 base_terrain DESERT\r\n    end_random\r\n  endif\r\n}\r\n"
+        ).is_empty());
+    }
+
+    #[test]
+    fn map_sizes() {
+        // A chain of conditions that covers every map size is always taken.
+        assert!(lint_messages(
+            RequiredAttributesLint::new(),
+            Compatibility::DefinitiveEdition,
+            "<TERRAIN_GENERATION>\r\ncreate_terrain FOREST {\r\n  if TINY_MAP base_terrain GRASS\r\n  elseif SMALL_MAP base_terrain GRASS\r\n  elseif MEDIUM_MAP base_terrain GRASS\r\n  elseif LARGE_MAP base_terrain DIRT\r\n  elseif HUGE_MAP base_terrain DIRT\r\n  elseif GIGANTIC_MAP base_terrain DIRT\r\n  endif\r\n}\r\n"
+        ).is_empty());
+        assert_eq!(
+            lint_messages(
+                RequiredAttributesLint::new(),
+                Compatibility::DefinitiveEdition,
+                "<TERRAIN_GENERATION>\r\ncreate_terrain FOREST {\r\n  if TINY_MAP base_terrain GRASS\r\n  elseif SMALL_MAP base_terrain GRASS\r\n  elseif MEDIUM_MAP base_terrain GRASS\r\n  elseif LARGE_MAP base_terrain DIRT\r\n  elseif HUGE_MAP base_terrain DIRT\r\n  endif\r\n}\r\n"
+            ),
+            vec!["`create_terrain` needs `base_terrain`, but it is not used in every branch"]
+        );
+    }
+
+    #[test]
+    fn location() {
+        let source = "<TERRAIN_GENERATION>\r\ncreate_terrain FOREST { if TINY_MAP base_terrain GRASS endif }\r\n";
+        let result = check_with(
+            RequiredAttributesLint::new(),
+            Compatibility::default(),
+            source,
+        );
+        let warning = result.iter().next().unwrap();
+        assert_eq!(warning.code(), Some("required-attributes"));
+        assert_eq!(warning.location().range(), 22.into()..36.into());
+        let label = warning.labels().next().unwrap();
+        assert_eq!(label.message(), "Only used conditionally");
+        assert_eq!(label.location().range(), 58.into()..76.into());
+    }
+}
@@ -0,0 +1,138 @@
+use super::unknown_attribute::meant;
+use crate::diagnostic::{Diagnostic, Fix};
+use crate::{Atom, AtomKind, Lint, ParseState, TokenCategory, Word, TOKENS};
+use cow_utils::CowUtils;
+
+/// Check that `#const` arguments refer to the right kind of thing, for example that
+/// `create_object` is not given a terrain.
+#[derive(Default, Clone)]
+pub struct TokenCategoryLint {}
+
+impl TokenCategoryLint {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn check_arg(
+        &self,
+        state: &ParseState<'_>,
+        command: &Word<'_>,
+        expected: TokenCategory,
+        arg: &Word<'_>,
+    ) -> Option<Diagnostic> {
+        let actual = state.const_category(arg.value)?;
+        if actual == expected {
+            return None;
+        }
+
+        let warning = Diagnostic::warning(
+            arg.location,
+            format_args!(
+                "`{}` is {}, but `{}` expects {}",
+                arg.value,
+                actual.describe(),
+                command.value,
+                expected.describe()
+            ),
+        );
+        let candidates = state
+            .consts()
+            .filter(|name| state.const_category(name) == Some(expected));
+        Some(match meant(arg.value, candidates) {
+            Some(similar) => warning.suggest(
+                Fix::new(arg.location, format_args!("Did you mean `{}`?", similar))
+                    .replace(similar),
+            ),
+            None => warning,
+        })
+    }
+}
+
+impl Lint for TokenCategoryLint {
+    fn name(&self) -> &'static str {
+        "token-category"
+    }
+    fn snapshot(&self) -> Option<Box<dyn Lint>> {
+        Some(Box::new(self.clone()))
+    }
+    fn lint_atom(&mut self, state: &mut ParseState<'_>, atom: &Atom<'_>) -> Vec<Diagnostic> {
+        let (name, arguments) = match &atom.kind {
+            AtomKind::Command { name, arguments } => (name, arguments),
+            _ => return vec![],
+        };
+        let token_type = match TOKENS.get(name.value.cow_to_ascii_lowercase().as_ref()) {
+            Some(token_type) => token_type,
+            None => return vec![],
+        };
+
+        arguments
+            .iter()
+            .enumerate()
+            .filter_map(|(i, arg)| {
+                let expected = token_type.arg_category(i as u8)?;
+                self.check_arg(state, name, expected, arg)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lints::{check_with, lint_messages};
+    use crate::Compatibility;
+
+    #[test]
+    fn categories() {
+        assert_eq!(
+            lint_messages(
+                TokenCategoryLint::new(),
+                Compatibility::Conquerors,
+                "<LAND_GENERATION>\r\nbase_terrain GRASS\r\ncreate_land { terrain_type SCOUT_CAVALRY }\r\n<OBJECTS_GENERATION>\r\ncreate_object GOLD\r\ncreate_object GRASS\r\ncreate_object SCOUT_CAVALRY { terrain_to_place_on DESERT }\r\n"
+            ),
+            vec![
+                "`SCOUT_CAVALRY` is an object, but `terrain_type` expects a terrain",
+                "`GRASS` is a terrain, but `create_object` expects an object"
+            ]
+        );
+        assert_eq!(
+            lint_messages(
+                TokenCategoryLint::new(),
+                Compatibility::UserPatch15,
+                "<PLAYER_SETUP>\r\nai_info_map_type ARABIA 0 0 0\r\nai_info_map_type DESERT 0 0 0\r\neffect_amount SET_ATTRIBUTE VILLAGER_CLASS ATTR_HITPOINTS 20\r\neffect_amount ATTR_HITPOINTS VILLAGER_CLASS SET_ATTRIBUTE 20\r\n"
+            ),
+            vec![
+                "`DESERT` is a terrain, but `ai_info_map_type` expects a map type",
+                "`ATTR_HITPOINTS` is an effect attribute, but `effect_amount` expects an effect"
+            ]
+        );
+    }
+
+    #[test]
+    fn user_consts() {
+        // Constants set to a builtin take on its category, numeric ones are unknown.
+        assert_eq!(
+            lint_messages(
+                TokenCategoryLint::new(),
+                Compatibility::Conquerors,
+                "#const MY_TERRAIN GRASS\r\n#const MY_OBJECT 66\r\n<OBJECTS_GENERATION>\r\ncreate_object MY_TERRAIN\r\ncreate_object MY_OBJECT\r\n"
+            ),
+            vec!["`MY_TERRAIN` is a terrain, but `create_object` expects an object"]
+        );
+    }
+
+    #[test]
+    fn suggestion() {
+        let source = "<OBJECTS_GENERATION>\r\ncreate_object FOREST\r\n";
+        let result = check_with(TokenCategoryLint::new(), Compatibility::Conquerors, source);
+        let warning = result.iter().next().unwrap();
+        assert_eq!(warning.code(), Some("token-category"));
+        assert_eq!(
+            warning.message(),
+            "`FOREST` is a terrain, but `create_object` expects an object"
+        );
+        let fix = warning.suggestions().next().unwrap();
+        assert_eq!(fix.message(), "Did you mean `FOREST_TREE`?");
+        assert_eq!(fix.replacement(), Some("FOREST_TREE"));
+    }
+}
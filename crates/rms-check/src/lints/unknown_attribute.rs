@@ -3,11 +3,15 @@ use crate::{Atom, AtomKind, Lint, ParseState, TOKENS};
 use strsim::jaro_winkler;
 
 #[allow(unused)]
+#[derive(Clone)]
 pub struct UnknownAttributeLint {}
 impl Lint for UnknownAttributeLint {
     fn name(&self) -> &'static str {
         "unknown-attribute"
     }
+    fn snapshot(&self) -> Option<Box<dyn Lint>> {
+        Some(Box::new(self.clone()))
+    }
     fn lint_atom(&mut self, _state: &mut ParseState<'_>, atom: &Atom<'_>) -> Vec<Diagnostic> {
         match atom.kind {
             // Treat unrecognised tokens as attributes, if they are not numbers
@@ -37,7 +41,7 @@ impl Lint for UnknownAttributeLint {
     }
 }
 
-fn meant<'a>(actual: &str, possible: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+pub(super) fn meant<'a>(actual: &str, possible: impl Iterator<Item = &'a str>) -> Option<&'a str> {
     possible
         .map(|expected| (expected, jaro_winkler(actual, expected)))
         .filter(|(_, similarity)| *similarity >= 0.8)
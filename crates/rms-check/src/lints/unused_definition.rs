@@ -0,0 +1,292 @@
+use super::branches::{excludes, BranchId, Branches};
+use crate::diagnostic::{Diagnostic, Fix, Label, SourceLocation};
+use crate::{Atom, AtomKind, Lint, ParseState, Word};
+use std::collections::{HashMap, HashSet};
+
+/// A `#const` or `#define` statement in the script.
+#[derive(Clone)]
+struct Definition {
+    /// The entire statement.
+    location: SourceLocation,
+    /// The value of a `#const`, or `None` for a `#define`.
+    value: Option<String>,
+    /// The conditional and random blocks that this statement is inside of.
+    branches: Vec<BranchId>,
+}
+
+impl Definition {
+    /// Can this definition and `other` never both be used in the same game, because they are in
+    /// different branches of an `if` statement or a `start_random` block?
+    fn excludes(&self, other: &Definition) -> bool {
+        excludes(&self.branches, &other.branches)
+    }
+}
+
+/// Find `#const` and `#define` names that are never used, and definitions that conflict with
+/// earlier ones.
+#[derive(Default, Clone)]
+pub struct UnusedDefinitionLint {
+    /// The definitions by name.
+    definitions: HashMap<String, Vec<Definition>>,
+    /// The names that are used as command arguments, `#const` values or conditions. Only names
+    /// that can refer to a definition in the script are stored: numbers are not, and neither are
+    /// builtin names, unless the script defined them already.
+    used: HashSet<String>,
+    /// The branches that we are currently inside of.
+    branches: Branches,
+}
+
+impl UnusedDefinitionLint {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn add_definition(
+        &mut self,
+        state: &ParseState<'_>,
+        atom: &Atom<'_>,
+        name: &Word<'_>,
+        value: Option<&Word<'_>>,
+    ) -> Vec<Diagnostic> {
+        let definition = Definition {
+            location: atom.location,
+            value: value.map(|value| value.value.to_string()),
+            branches: self.branches.current(),
+        };
+
+        let mut warnings = vec![];
+        let previous = self.definitions.entry(name.value.to_string()).or_default();
+        if previous.is_empty() {
+            if let Some(builtin) = state.get_builtin_definition(name.value) {
+                let kind = if builtin.value().is_some() {
+                    "#const"
+                } else {
+                    "#define"
+                };
+                warnings.push(
+                    Diagnostic::warning(
+                        name.location,
+                        format_args!("`{}` shadows a builtin `{}`", name.value, kind),
+                    )
+                    .add_label(Label::new(builtin.location(), "Defined in random_map.def")),
+                );
+            }
+        }
+
+        if let Some(value) = &definition.value {
+            let conflict = previous.iter().find(|other| {
+                other.value.as_ref().is_some_and(|other| other != value)
+                    && !other.excludes(&definition)
+            });
+            if let Some(other) = conflict {
+                warnings.push(
+                    Diagnostic::warning(
+                        atom.location,
+                        format_args!(
+                            "`{}` was already defined with a different value",
+                            name.value
+                        ),
+                    )
+                    .add_label(Label::new(
+                        other.location,
+                        format_args!(
+                            "Previously defined as `{}` here",
+                            other.value.as_deref().unwrap_or_default()
+                        ),
+                    )),
+                );
+            }
+        }
+
+        previous.push(definition);
+        warnings
+    }
+
+    /// Record a use of a name.
+    fn add_use(&mut self, state: &ParseState<'_>, name: &str) {
+        if self.used.contains(name) || name.parse::<i32>().is_ok() || name.starts_with("rnd(") {
+            return;
+        }
+        if self.definitions.contains_key(name) || state.get_builtin_definition(name).is_none() {
+            self.used.insert(name.to_string());
+        }
+    }
+}
+
+impl Lint for UnusedDefinitionLint {
+    fn name(&self) -> &'static str {
+        "unused-definition"
+    }
+    fn snapshot(&self) -> Option<Box<dyn Lint>> {
+        Some(Box::new(self.clone()))
+    }
+    fn lint_atom(&mut self, state: &mut ParseState<'_>, atom: &Atom<'_>) -> Vec<Diagnostic> {
+        self.branches.update(atom);
+        match &atom.kind {
+            AtomKind::Const { name, value, .. } => {
+                if let Some(value) = value {
+                    self.add_use(state, value.value);
+                }
+                return self.add_definition(state, atom, name, value.as_ref());
+            }
+            AtomKind::Define { name, .. } => return self.add_definition(state, atom, name, None),
+            AtomKind::If { condition, .. } | AtomKind::ElseIf { condition, .. } => {
+                self.add_use(state, condition.value);
+            }
+            AtomKind::PercentChance { chance, .. } => self.add_use(state, chance.value),
+            AtomKind::Command { arguments, .. } => {
+                for argument in arguments {
+                    self.add_use(state, argument.value);
+                }
+            }
+            _ => (),
+        }
+        vec![]
+    }
+    fn lint_end(&mut self, _state: &mut ParseState<'_>) -> Vec<Diagnostic> {
+        let mut unused = self
+            .definitions
+            .iter()
+            .filter(|(name, _)| !self.used.contains(*name))
+            .collect::<Vec<_>>();
+        unused.sort_by_key(|(_, definitions)| definitions[0].location.start());
+
+        unused
+            .into_iter()
+            .map(|(name, definitions)| {
+                let first = &definitions[0];
+                let kind = if first.value.is_some() {
+                    "#const"
+                } else {
+                    "#define"
+                };
+                let diagnostic = Diagnostic::warning(
+                    first.location,
+                    format_args!("`{} {}` is never used", kind, name),
+                );
+                if definitions.len() == 1 {
+                    diagnostic.suggest(
+                        Fix::new(first.location, "Remove the unused definition").replace(""),
+                    )
+                } else {
+                    diagnostic.add_labels(
+                        definitions[1..]
+                            .iter()
+                            .map(|other| Label::new(other.location, "Also defined here")),
+                    )
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lints::{check_with, lint_messages};
+    use crate::Compatibility;
+
+    #[test]
+    fn unused() {
+        assert_eq!(
+            lint_messages(
+                UnusedDefinitionLint::new(),
+                Compatibility::Conquerors,
+                "#const A 1\r\n#define B\r\n#const C 2\r\n#define D\r\n<LAND_GENERATION>\r\nbase_terrain C\r\nif D endif\r\n"
+            ),
+            vec!["`#const A` is never used", "`#define B` is never used"]
+        );
+        // Constants can be used in other constants and in `percent_chance`.
+        assert_eq!(
+            lint_messages(
+                UnusedDefinitionLint::new(),
+                Compatibility::Conquerors,
+                "#const A 1\r\n#const B A\r\n#const C 50\r\n<LAND_GENERATION>\r\nbase_terrain B\r\nstart_random percent_chance C end_random\r\n"
+            ),
+            Vec::<String>::new()
+        );
+        // Uses before the definition count too, for example in a different branch.
+        assert_eq!(
+            lint_messages(
+                UnusedDefinitionLint::new(),
+                Compatibility::Conquerors,
+                "<LAND_GENERATION>\r\nif TINY_MAP base_terrain A endif\r\n#const A 1\r\n"
+            ),
+            Vec::<String>::new()
+        );
+        // Shadowed builtins count as used only after they are defined.
+        assert_eq!(
+            lint_messages(
+                UnusedDefinitionLint::new(),
+                Compatibility::Conquerors,
+                "<LAND_GENERATION>\r\nbase_terrain GRASS\r\n#const GRASS 0\r\n"
+            ),
+            vec![
+                "`GRASS` shadows a builtin `#const`",
+                "`#const GRASS` is never used"
+            ]
+        );
+    }
+
+    #[test]
+    fn unused_fix() {
+        let source = "#const A 1\r\n#define B\r\nif B endif\r\n";
+        let result = check_with(
+            UnusedDefinitionLint::new(),
+            Compatibility::default(),
+            source,
+        );
+        let diagnostic = result.iter().next().unwrap();
+        assert_eq!(diagnostic.code(), Some("unused-definition"));
+        let fix = diagnostic.suggestions().next().unwrap();
+        assert_eq!(fix.location().range(), 0.into()..10.into());
+        assert_eq!(fix.replacement(), Some(""));
+    }
+
+    #[test]
+    fn redefinitions() {
+        assert_eq!(
+            lint_messages(
+                UnusedDefinitionLint::new(),
+                Compatibility::Conquerors,
+                "#const A 1\r\n#const A 2\r\n#const A 1\r\n<LAND_GENERATION>\r\nbase_terrain A\r\n"
+            ),
+            vec![
+                "`A` was already defined with a different value",
+                "`A` was already defined with a different value"
+            ]
+        );
+        // Different branches can not both be used.
+        assert_eq!(
+            lint_messages(
+                UnusedDefinitionLint::new(),
+                Compatibility::Conquerors,
+                "if TINY_MAP #const A 1 else #const A 2 endif\r\nstart_random percent_chance 50 #const B 1 percent_chance 50 #const B 2 end_random\r\n<LAND_GENERATION>\r\nbase_terrain A\r\nbase_terrain B\r\n"
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            lint_messages(
+                UnusedDefinitionLint::new(),
+                Compatibility::Conquerors,
+                "if TINY_MAP #const A 1 endif\r\n#const A 2\r\n<LAND_GENERATION>\r\nbase_terrain A\r\n"
+            ),
+            vec!["`A` was already defined with a different value"]
+        );
+    }
+
+    #[test]
+    fn shadowed_builtins() {
+        assert_eq!(
+            lint_messages(
+                UnusedDefinitionLint::new(),
+                Compatibility::Conquerors,
+                "#const GRASS 0\r\n#const DESERT 3\r\n<LAND_GENERATION>\r\nbase_terrain GRASS\r\nbase_terrain DESERT\r\n"
+            ),
+            vec![
+                "`GRASS` shadows a builtin `#const`",
+                "`DESERT` shadows a builtin `#const`"
+            ]
+        );
+    }
+}
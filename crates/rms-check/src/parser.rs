@@ -160,6 +160,16 @@ impl Display for Atom<'_> {
     }
 }
 
+/// Does a word look like an argument value: a number, an `rnd()` call, or an uppercase `#const`
+/// name?
+fn looks_like_value(word: &str) -> bool {
+    word.starts_with("rnd(")
+        || word.parse::<i32>().is_ok()
+        || word
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
 /// A forgiving random map script parser, turning a stream of words into a stream of atoms.
 #[derive(Debug)]
 pub struct Parser<'a> {
@@ -177,6 +187,16 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Create a new parser for the given source code that starts parsing at the given index.
+    ///
+    /// The index should be the start of an atom, or whitespace preceding it.
+    pub fn new_at(file_id: FileId, source: &'a str, offset: ByteIndex) -> Self {
+        Parser {
+            source,
+            iter: itertools::multipeek(Tokenizer::new_at(file_id, source, offset)),
+        }
+    }
+
     /// Take a slice of the source code.
     fn slice(&self, range: impl RangeBounds<ByteIndex>) -> String {
         use std::ops::Bound::*;
@@ -186,7 +206,7 @@ impl<'a> Parser<'a> {
             Excluded(n) => *n + 1,
         };
         let end = match range.end_bound() {
-            Unbounded => ByteIndex::from(self.source.as_bytes().len()),
+            Unbounded => ByteIndex::from(self.source.len()),
             Included(n) => *n,
             Excluded(n) => *n - 1,
         };
@@ -195,10 +215,7 @@ impl<'a> Parser<'a> {
 
     /// Check if the next word could be a command argument. If yes, return it; else return None.
     fn peek_arg(&mut self) -> Option<&Word<'a>> {
-        let token = match self.iter.peek() {
-            Some(token) => token,
-            None => return None,
-        };
+        let token = self.iter.peek()?;
 
         // Things that should never be args
         match token.value {
@@ -284,7 +301,19 @@ impl<'a> Parser<'a> {
         // token is guaranteed to exist at this point
         let token_type = &TOKENS[lower_name];
         let mut arguments = vec![];
-        for _ in 0..token_type.arg_len() {
+        for i in 0..token_type.arg_len() {
+            // Optional arguments are only read if they look like a value, so that a misspelled
+            // attribute on the next line is not taken as an argument.
+            let is_optional = i >= token_type.min_arg_len();
+            if is_optional {
+                let has_value = self
+                    .peek_arg()
+                    .is_some_and(|arg| looks_like_value(arg.value));
+                self.iter.reset_peek();
+                if !has_value {
+                    break;
+                }
+            }
             match self.read_arg() {
                 Some(arg) => arguments.push(arg),
                 _ => break,
@@ -295,7 +324,7 @@ impl<'a> Parser<'a> {
             Some(arg) => name.location.start()..arg.location.end(),
             _ => name.location.range(),
         };
-        if arguments.len() != token_type.arg_len() as usize {
+        if arguments.len() < token_type.min_arg_len() as usize {
             warnings.push(ParseError::new(
                 SourceLocation::new(name.location.file(), range.clone()),
                 ParseErrorKind::MissingCommandArgs,
@@ -314,10 +343,7 @@ impl<'a> Parser<'a> {
 impl<'a> Iterator for Parser<'a> {
     type Item = (Atom<'a>, Vec<ParseError>);
     fn next(&mut self) -> Option<Self::Item> {
-        let word = match self.iter.next() {
-            Some(word) => word,
-            None => return None,
-        };
+        let word = self.iter.next()?;
 
         let t = |atom| Some((atom, vec![]));
 
@@ -507,7 +533,7 @@ mod tests {
             assert_eq!(value.unwrap().value, "B");
             assert!(warnings.is_empty());
         } else {
-            assert!(false);
+            panic!("unexpected atom");
         }
     }
 
@@ -523,7 +549,7 @@ mod tests {
             assert_eq!(warnings.len(), 1);
             assert_eq!(warnings[0].kind, ParseErrorKind::MissingConstValue);
         } else {
-            assert!(false);
+            panic!("unexpected atom");
         }
     }
 
@@ -537,7 +563,7 @@ mod tests {
             assert_eq!(warnings.len(), 1);
             assert_eq!(warnings[0].kind, ParseErrorKind::MissingConstName);
         } else {
-            assert!(false);
+            panic!("unexpected atom");
         }
     }
 
@@ -551,7 +577,7 @@ mod tests {
             assert_eq!(name.value, "B");
             assert!(warnings.is_empty());
         } else {
-            assert!(false);
+            panic!("unexpected atom");
         }
     }
 
@@ -565,7 +591,7 @@ mod tests {
             assert_eq!(warnings.len(), 1);
             assert_eq!(warnings[0].kind, ParseErrorKind::MissingDefineName);
         } else {
-            assert!(false);
+            panic!("unexpected atom");
         }
     }
 
@@ -580,7 +606,7 @@ mod tests {
             assert!(arguments.is_empty());
             assert!(warnings.is_empty());
         } else {
-            assert!(false);
+            panic!("unexpected atom");
         }
     }
 
@@ -596,14 +622,14 @@ mod tests {
             assert_eq!(arguments[0].value, "10");
             assert!(warnings.is_empty());
         } else {
-            assert!(false);
+            panic!("unexpected atom");
         }
         if let (AtomKind::Command { name, arguments }, warnings) = &atoms[1] {
             assert_eq!(name.value, "grouped_by_team");
             assert!(arguments.is_empty());
             assert!(warnings.is_empty());
         } else {
-            assert!(false);
+            panic!("unexpected atom");
         }
     }
 
@@ -620,14 +646,14 @@ mod tests {
             assert_eq!(arguments[0].value, "10");
             assert!(warnings.is_empty());
         } else {
-            assert!(false);
+            panic!("unexpected atom");
         }
         if let (AtomKind::Command { name, arguments }, warnings) = &atoms[1] {
             assert_eq!(name.value, "grouped_BY_team");
             assert!(arguments.is_empty());
             assert!(warnings.is_empty());
         } else {
-            assert!(false);
+            panic!("unexpected atom");
         }
     }
 
@@ -642,24 +668,24 @@ mod tests {
             assert_eq!(arguments.len(), 1);
             assert_eq!(arguments[0].value, "SNOW");
         } else {
-            assert!(false)
+            panic!("unexpected atom")
         }
         if let (AtomKind::OpenBlock { head }, _) = atoms.remove(0) {
             assert_eq!(head.value, "{");
         } else {
-            assert!(false)
+            panic!("unexpected atom")
         }
         if let (AtomKind::Command { name, arguments }, _) = atoms.remove(0) {
             assert_eq!(name.value, "base_size");
             assert_eq!(arguments.len(), 1);
             assert_eq!(arguments[0].value, "15");
         } else {
-            assert!(false)
+            panic!("unexpected atom")
         }
         if let (AtomKind::CloseBlock { head }, _) = atoms.remove(0) {
             assert_eq!(head.value, "}");
         } else {
-            assert!(false)
+            panic!("unexpected atom")
         }
     }
 
@@ -675,7 +701,7 @@ mod tests {
         if let (AtomKind::Command { .. }, _) = atoms.remove(0) {
             // ok
         } else {
-            assert!(false)
+            panic!("unexpected atom")
         }
         if let (
             AtomKind::Comment {
@@ -690,17 +716,17 @@ mod tests {
             assert_eq!(content, " this is a comment ");
             assert_eq!(close.unwrap().value, "*/");
         } else {
-            assert!(false)
+            panic!("unexpected atom")
         }
         if let (AtomKind::OpenBlock { head }, _) = atoms.remove(0) {
             assert_eq!(head.value, "{");
         } else {
-            assert!(false)
+            panic!("unexpected atom")
         }
         if let (AtomKind::CloseBlock { head }, _) = atoms.remove(0) {
             assert_eq!(head.value, "}");
         } else {
-            assert!(false)
+            panic!("unexpected atom")
         }
     }
 
@@ -0,0 +1,107 @@
+//! A list that is cheap to copy, for state that grows over the whole script and is stored in
+//! every incremental checkpoint.
+
+use std::sync::Arc;
+
+/// The number of items that are copied along with a list before they are shared.
+const CHUNK_SIZE: usize = 32;
+
+/// Items that are shared between copies of a list.
+#[derive(Debug)]
+struct Chunk<T> {
+    items: Vec<T>,
+    /// The items that were added before these.
+    previous: Option<Arc<Chunk<T>>>,
+}
+
+impl<T> Drop for Chunk<T> {
+    fn drop(&mut self) {
+        // Drop the chain of chunks iteratively, so long lists do not overflow the stack.
+        let mut previous = self.previous.take();
+        while let Some(chunk) = previous {
+            previous = match Arc::try_unwrap(chunk) {
+                Ok(mut chunk) => chunk.previous.take(),
+                Err(_) => None,
+            };
+        }
+    }
+}
+
+/// An append-only list. Copies share all but the most recently added items, so copying a list
+/// takes the same time no matter how long it is.
+#[derive(Debug, Clone)]
+pub(crate) struct PersistentList<T> {
+    /// The items that are shared with other copies of this list.
+    chunks: Option<Arc<Chunk<T>>>,
+    /// The items that were added after the last shared chunk.
+    tail: Vec<T>,
+}
+
+impl<T> Default for PersistentList<T> {
+    fn default() -> Self {
+        Self {
+            chunks: None,
+            tail: vec![],
+        }
+    }
+}
+
+impl<T> PersistentList<T> {
+    /// Add an item to the end of the list.
+    pub fn push(&mut self, item: T) {
+        self.tail.push(item);
+        if self.tail.len() == CHUNK_SIZE {
+            self.chunks = Some(Arc::new(Chunk {
+                items: std::mem::take(&mut self.tail),
+                previous: self.chunks.take(),
+            }));
+        }
+    }
+
+    /// Iterate over the items in the order that they were added.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        let mut chunks = vec![];
+        let mut chunk = self.chunks.as_deref();
+        while let Some(current) = chunk {
+            chunks.push(&current.items);
+            chunk = current.previous.as_deref();
+        }
+        chunks.into_iter().rev().flatten().chain(&self.tail)
+    }
+}
+
+impl<T> Extend<T> for PersistentList<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, items: I) {
+        for item in items {
+            self.push(item);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copies_are_independent() {
+        let mut list = PersistentList::default();
+        list.extend(0..100);
+        let copy = list.clone();
+        list.extend(100..150);
+        assert_eq!(
+            copy.iter().copied().collect::<Vec<_>>(),
+            (0..100).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            list.iter().copied().collect::<Vec<_>>(),
+            (0..150).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn drop_long_list() {
+        let mut list = PersistentList::default();
+        list.extend(0..10_000_000);
+        drop(list);
+    }
+}
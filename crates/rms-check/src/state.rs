@@ -1,18 +1,108 @@
 //! State tracker while parsing AoE2 random map scripts.
 
 use crate::diagnostic::{Diagnostic, Label, SourceLocation};
+use crate::eval::Value;
 use crate::parser::{Atom, AtomKind, Parser};
 use crate::tokenizer::Word;
-use crate::tokens::TokenType;
+use crate::tokens::{TokenCategory, TokenType};
 use crate::RMSFile;
 use cow_utils::CowUtils;
+use lazy_static::lazy_static;
 use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 
+/// `#define` names that the game sets depending on the map size.
+pub(crate) const MAP_SIZE_DEFINES: [&str; 6] = [
+    "TINY_MAP",
+    "SMALL_MAP",
+    "MEDIUM_MAP",
+    "LARGE_MAP",
+    "HUGE_MAP",
+    "GIGANTIC_MAP",
+];
+
+/// Find the category of the #consts following a section heading comment in a random_map.def
+/// file. Returns `None` if the comment is not a section heading.
+fn heading_category(comment: &str) -> Option<Option<TokenCategory>> {
+    let heading = comment.trim();
+    if let Some(name) = heading
+        .strip_prefix("Define ")
+        .and_then(|heading| heading.strip_suffix(" Constants"))
+    {
+        return Some(match name {
+            "Effect" => Some(TokenCategory::Effect),
+            "Effect Type" | "ModifyTech" | "Attribute" | "ResourceAmount" | "PlayerData" => {
+                Some(TokenCategory::Attribute)
+            }
+            "Building" | "Villager" => Some(TokenCategory::Object),
+            _ => None,
+        });
+    }
+    if heading.to_ascii_lowercase().starts_with("random map types") {
+        return Some(Some(TokenCategory::MapType));
+    }
+    if heading.is_empty() || !heading.chars().all(|c| c.is_ascii_uppercase() || c == ' ') {
+        return None;
+    }
+    Some(match heading {
+        "TERRAIN TYPES" => Some(TokenCategory::Terrain),
+        "OBJECT TYPES" | "GAIA" | "UNITS" | "BUILDINGS" | "EXPORTED FROM THE DATABASE" => {
+            Some(TokenCategory::Object)
+        }
+        _ => None,
+    })
+}
+
+/// Is the given byte offset in `source` the first non-whitespace character on its line?
+fn starts_line(source: &str, offset: usize) -> bool {
+    let before = source[..offset].trim_end_matches([' ', '\t']);
+    before.is_empty() || before.ends_with('\n')
+}
+
+/// `#define` names that UserPatch sets depending on the game mode.
+pub(crate) const GAME_MODE_DEFINES: [&str; 8] = [
+    "CAPTURE_RELIC",
+    "DEATH_MATCH",
+    "DEFEND_WONDER",
+    "KING_OT_HILL",
+    "RANDOM_MAP",
+    "REGICIDE",
+    "TURBO_RANDOM_MAP",
+    "WONDER_RACE",
+];
+
+lazy_static! {
+    /// `#define` names that UserPatch sets depending on the game settings.
+    static ref UP_OPTION_DEFINES: Vec<String> = {
+        let mut list = vec!["FIXED_POSITIONS".to_string(), "AI_PLAYERS".to_string()];
+        list.extend(GAME_MODE_DEFINES.iter().map(|name| name.to_string()));
+
+        for i in 1..=8 {
+            list.push(format!("{}_PLAYER_GAME", i));
+        }
+        for i in 0..=4 {
+            list.push(format!("{}_TEAM_GAME", i));
+        }
+        for team in 0..=4 {
+            for player in 1..=8 {
+                list.push(format!("PLAYER{}_TEAM{}", player, team));
+            }
+        }
+        for team in 0..=4 {
+            for size in 0..=8 {
+                list.push(format!("TEAM{}_SIZE{}", team, size));
+            }
+        }
+
+        list
+    };
+}
+
 /// The target compatibility for a map script.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Hash)]
 pub enum Compatibility {
     /// The Conquerors.
+    #[default]
     Conquerors = 1,
     /// Target UserPatch 1.4, accept the features it added.
     UserPatch14 = 3,
@@ -29,9 +119,40 @@ pub enum Compatibility {
     All = 0,
 }
 
-impl Default for Compatibility {
-    fn default() -> Compatibility {
-        Compatibility::Conquerors
+impl FromStr for Compatibility {
+    type Err = ();
+
+    /// Parse a compatibility name, as used in `/* Compatibility: */` comments.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let lower_name = name.cow_to_ascii_lowercase();
+        match lower_name.trim() {
+            "hd edition" | "hd" => Ok(Compatibility::HDEdition),
+            "conquerors" | "aoc" => Ok(Compatibility::Conquerors),
+            "userpatch 1.5" | "up 1.5" => Ok(Compatibility::UserPatch15),
+            "userpatch 1.4" | "up 1.4" | "userpatch" | "up" => Ok(Compatibility::UserPatch14),
+            "wololokingdoms" | "wk" => Ok(Compatibility::WololoKingdoms),
+            "definitive edition" | "de" => Ok(Compatibility::DefinitiveEdition),
+            "all" => Ok(Compatibility::All),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Compatibility {
+    /// Can scripts targeting this compatibility level use features introduced by `feature`?
+    ///
+    /// HD Edition adopted the random map additions of UserPatch 1.4, and the Definitive Edition
+    /// supports the features of all other versions.
+    pub fn includes(self, feature: Compatibility) -> bool {
+        use Compatibility::*;
+        match feature {
+            All | Conquerors => true,
+            UserPatch14 => !matches!(self, All | Conquerors),
+            UserPatch15 => matches!(self, UserPatch15 | WololoKingdoms | DefinitiveEdition),
+            WololoKingdoms => self == WololoKingdoms,
+            HDEdition => matches!(self, HDEdition | DefinitiveEdition),
+            DefinitiveEdition => self == DefinitiveEdition,
+        }
     }
 }
 
@@ -52,6 +173,25 @@ pub enum Nesting<'a> {
     Brace(Atom<'a>),
 }
 
+impl<'a> Nesting<'a> {
+    /// Get the atom that started this nested context.
+    pub const fn atom(&self) -> &Atom<'a> {
+        match self {
+            Nesting::If(atom)
+            | Nesting::ElseIf(atom)
+            | Nesting::Else(atom)
+            | Nesting::StartRandom(atom)
+            | Nesting::PercentChance(atom)
+            | Nesting::Brace(atom) => atom,
+        }
+    }
+
+    /// Get the source location of the atom that started this nested context.
+    pub const fn location(&self) -> SourceLocation {
+        self.atom().location
+    }
+}
+
 #[derive(Debug, Hash, Clone, Copy, PartialEq, Eq)]
 enum HeaderName {
     Compatibility,
@@ -73,7 +213,6 @@ impl FromStr for HeaderName {
 pub struct ConstDefinition<'a> {
     atom: Atom<'a>,
     value: Option<Word<'a>>,
-    // depends_on: Vec<ConstDependency>,
 }
 
 impl<'a> ConstDefinition<'a> {
@@ -86,6 +225,11 @@ impl<'a> ConstDefinition<'a> {
         }
     }
 
+    /// Get the value this const was defined with, if any.
+    pub fn value(&self) -> Option<Word<'a>> {
+        self.value
+    }
+
     /// Get the location where this const is defined.
     pub fn location(&self) -> SourceLocation {
         self.atom.location
@@ -107,9 +251,19 @@ pub struct ParseState<'a> {
     /// The current <SECTION>, as well as its opening token.
     pub current_section: Option<Atom<'a>>,
     /// List of builtin #const definitions.
-    builtin_consts: HashSet<String>,
-    /// List of builtin #define definitions.
-    builtin_defines: HashSet<String>,
+    builtin_consts: HashMap<&'a str, ConstDefinition<'a>>,
+    /// List of builtin #define definitions. Definitions that are set by the game itself do not
+    /// have a location.
+    builtin_defines: HashMap<&'a str, Option<ConstDefinition<'a>>>,
+    /// List of builtin #define definitions that may or may not be set, depending on the game
+    /// settings.
+    builtin_option_defines: HashSet<String>,
+    /// List of builtin #const definitions that are only available when a #define is set, with
+    /// the name of that #define.
+    conditional_consts: HashMap<&'a str, &'static str>,
+    /// The categories of builtin #const definitions, based on the random_map.def section they
+    /// are defined in.
+    builtin_categories: HashMap<&'a str, TokenCategory>,
     /// List of user-mode #const definitions we've seen so far.
     consts: HashMap<&'a str, ConstDefinition<'a>>,
     /// List of user-mode #define definitions we've seen so far.
@@ -120,6 +274,61 @@ pub struct ParseState<'a> {
     end_of_headers: bool,
 }
 
+/// Is `nesting` inside an `if` or `elseif` branch that checks the given `#define` name?
+pub(crate) fn is_inside_condition(nesting: &[Nesting<'_>], name: &str) -> bool {
+    nesting.iter().any(|nesting| match nesting {
+        Nesting::If(atom) | Nesting::ElseIf(atom) => matches!(
+            atom.kind,
+            AtomKind::If { condition, .. } | AtomKind::ElseIf { condition, .. }
+                if condition.value == name
+        ),
+        _ => false,
+    })
+}
+
+/// Parse the atom at a location that was stored in a snapshot.
+pub(crate) fn atom_at<'a>(rms: &'a RMSFile<'a>, location: SourceLocation) -> Atom<'a> {
+    Parser::new_at(
+        location.file(),
+        rms.source(location.file()),
+        location.start(),
+    )
+    .next()
+    .map(|(atom, _)| atom)
+    .expect("snapshot locations must point to atoms")
+}
+
+/// Parse the nesting atom at a location that was stored in a snapshot.
+pub(crate) fn nesting_at<'a>(rms: &'a RMSFile<'a>, location: SourceLocation) -> Nesting<'a> {
+    let atom = atom_at(rms, location);
+    match atom.kind {
+        AtomKind::If { .. } => Nesting::If(atom),
+        AtomKind::ElseIf { .. } => Nesting::ElseIf(atom),
+        AtomKind::Else { .. } => Nesting::Else(atom),
+        AtomKind::StartRandom { .. } => Nesting::StartRandom(atom),
+        AtomKind::PercentChance { .. } => Nesting::PercentChance(atom),
+        AtomKind::OpenBlock { .. } => Nesting::Brace(atom),
+        _ => unreachable!("snapshot nesting must point to nesting atoms"),
+    }
+}
+
+/// An owned copy of a `ParseState`, which does not borrow from the source code.
+///
+/// Atoms are stored by their location, and parsed again when the snapshot is restored. Restoring
+/// is only valid if the source code up to the point where the snapshot was taken did not change.
+#[derive(Debug, Clone)]
+pub(crate) struct ParseStateSnapshot {
+    compatibility: Compatibility,
+    is_builtin_map: bool,
+    nesting: Vec<SourceLocation>,
+    current_token: Option<&'static TokenType>,
+    current_section: Option<SourceLocation>,
+    consts: Vec<SourceLocation>,
+    defines: Vec<SourceLocation>,
+    option_defines: HashSet<String>,
+    end_of_headers: bool,
+}
+
 impl<'a> ParseState<'a> {
     pub(crate) fn new(rms: &'a RMSFile<'a>, compatibility: Compatibility) -> Self {
         let mut state = Self {
@@ -129,8 +338,11 @@ impl<'a> ParseState<'a> {
             nesting: vec![],
             current_token: None,
             current_section: None,
-            builtin_consts: HashSet::new(),
-            builtin_defines: HashSet::new(),
+            builtin_consts: HashMap::new(),
+            builtin_defines: HashMap::new(),
+            builtin_option_defines: HashSet::new(),
+            conditional_consts: HashMap::new(),
+            builtin_categories: HashMap::new(),
             consts: HashMap::new(),
             defines: HashMap::new(),
             option_defines: HashSet::new(),
@@ -140,6 +352,50 @@ impl<'a> ParseState<'a> {
         state
     }
 
+    /// Take an owned snapshot of the current state.
+    pub(crate) fn snapshot(&self) -> ParseStateSnapshot {
+        ParseStateSnapshot {
+            compatibility: self.compatibility,
+            is_builtin_map: self.is_builtin_map,
+            nesting: self.nesting.iter().map(Nesting::location).collect(),
+            current_token: self.current_token,
+            current_section: self.current_section.as_ref().map(|atom| atom.location),
+            consts: self
+                .consts
+                .values()
+                .map(ConstDefinition::location)
+                .collect(),
+            defines: self
+                .defines
+                .values()
+                .map(ConstDefinition::location)
+                .collect(),
+            option_defines: self.option_defines.clone(),
+            end_of_headers: self.end_of_headers,
+        }
+    }
+
+    /// Restore a state from a snapshot, parsing the atoms it refers to from `rms`.
+    pub(crate) fn restore(rms: &'a RMSFile<'a>, snapshot: &ParseStateSnapshot) -> Self {
+        let atom_at = |location: SourceLocation| atom_at(rms, location);
+
+        let mut state = Self::new(rms, snapshot.compatibility);
+        state.is_builtin_map = snapshot.is_builtin_map;
+        state.current_token = snapshot.current_token;
+        state.current_section = snapshot.current_section.map(atom_at);
+        state.option_defines = snapshot.option_defines.clone();
+        state.end_of_headers = snapshot.end_of_headers;
+        state.nesting = snapshot
+            .nesting
+            .iter()
+            .map(|&location| nesting_at(rms, location))
+            .collect();
+        for &location in snapshot.consts.iter().chain(&snapshot.defines) {
+            state.update(&atom_at(location));
+        }
+        state
+    }
+
     /// Track that a `#define` name may or may not exist from this point.
     ///
     /// These defines are valid in `if` statements, but not in commands, for example.
@@ -156,37 +412,102 @@ impl<'a> ParseState<'a> {
     }
     /// Does a given `#define` name exist?
     pub fn has_define(&self, name: &str) -> bool {
-        self.defines.contains_key(name) || self.builtin_defines.contains(name)
+        self.defines.contains_key(name) || self.builtin_defines.contains_key(name)
+    }
+    /// Is a given `#define` name always set by the game, for the current compatibility level?
+    pub fn is_builtin_define(&self, name: &str) -> bool {
+        self.builtin_defines.contains_key(name)
     }
     /// May a given `#define` name exist at this point?
     pub fn may_have_define(&self, name: &str) -> bool {
-        self.has_define(name) || self.option_defines.contains(name)
+        self.has_define(name)
+            || self.option_defines.contains(name)
+            || self.builtin_option_defines.contains(name)
     }
     /// Does a given `#const` name exist?
     pub fn has_const(&self, name: &str) -> bool {
-        self.consts.contains_key(name) || self.builtin_consts.contains(name)
+        self.consts.contains_key(name)
+            || self.builtin_consts.contains_key(name)
+            || self
+                .const_condition(name)
+                .is_some_and(|condition| self.is_inside_condition(condition))
+    }
+    /// Get the `#define` name that must be set for a builtin `#const` name to exist, if that
+    /// `#const` is only available conditionally.
+    pub fn const_condition(&self, name: &str) -> Option<&'static str> {
+        self.conditional_consts.get(name).copied()
+    }
+    /// Are we inside an `if` or `elseif` branch that checks the given `#define` name?
+    pub fn is_inside_condition(&self, name: &str) -> bool {
+        is_inside_condition(&self.nesting, name)
     }
     /// List all the `#const` names that are currently available.
     pub fn consts(&self) -> impl Iterator<Item = &str> {
         self.consts
             .keys()
             .copied()
-            .chain(self.builtin_consts.iter().map(|string| string.as_ref()))
+            .chain(self.builtin_consts.keys().copied())
     }
     /// List all the `#define` names that are currently available.
     pub fn defines(&self) -> impl Iterator<Item = &str> {
         self.defines
             .keys()
             .copied()
-            .chain(self.builtin_defines.iter().map(|string| string.as_ref()))
+            .chain(self.builtin_defines.keys().copied())
     }
 
-    pub fn get_define(&self, name: &str) -> Option<&ConstDefinition<'_>> {
-        self.defines.get(name)
+    /// Get the definition of a `#define` name, if it was defined in a script or random_map.def
+    /// file.
+    pub fn get_define(&self, name: &str) -> Option<&ConstDefinition<'a>> {
+        self.defines
+            .get(name)
+            .or_else(|| self.builtin_defines.get(name)?.as_ref())
     }
 
-    pub fn get_const(&self, name: &str) -> Option<&ConstDefinition<'_>> {
-        self.consts.get(name)
+    /// Get the definition of a `#const` name, if it was defined in a script or random_map.def
+    /// file.
+    pub fn get_const(&self, name: &str) -> Option<&ConstDefinition<'a>> {
+        self.consts
+            .get(name)
+            .or_else(|| self.builtin_consts.get(name))
+    }
+
+    /// Get the definition of a `#const` or `#define` name from the random_map.def file, ignoring
+    /// definitions in the script.
+    pub fn get_builtin_definition(&self, name: &str) -> Option<&ConstDefinition<'a>> {
+        self.builtin_consts
+            .get(name)
+            .or_else(|| self.builtin_defines.get(name)?.as_ref())
+    }
+
+    /// Get the category of a `#const`, like whether it refers to a terrain or an object.
+    ///
+    /// User-defined `#const`s take on the category of the builtin `#const` they are set to.
+    pub fn const_category(&self, name: &str) -> Option<TokenCategory> {
+        let mut name = name;
+        for _ in 0..=self.consts.len() {
+            match self.consts.get(name) {
+                Some(definition) => name = definition.value?.value,
+                None => return self.builtin_categories.get(name).copied(),
+            }
+        }
+        None
+    }
+
+    /// Evaluate a numeric argument: a number, an `rnd(min,max)` call, or the name of a `#const`.
+    /// Constants defined in terms of other constants are resolved recursively.
+    ///
+    /// Returns `None` if the value is not numeric, or refers to an unknown or cyclic constant.
+    pub fn evaluate(&self, value: &str) -> Option<Value> {
+        let mut value = value;
+        // Each step follows one constant, so a longer chain must contain a cycle.
+        for _ in 0..=self.consts.len() + self.builtin_consts.len() {
+            if let Some(result) = Value::parse(value) {
+                return Some(result);
+            }
+            value = self.get_const(value)?.value()?.value;
+        }
+        None
     }
 
     /// Get the compatibility mode the parser runs in.
@@ -202,20 +523,77 @@ impl<'a> ParseState<'a> {
 
         self.builtin_consts.clear();
         self.builtin_defines.clear();
+        self.builtin_option_defines.clear();
+        self.conditional_consts.clear();
+        self.builtin_categories.clear();
 
-        let (file_id, content) = self.rms.definitions(compatibility);
+        let (defines, option_defines): (&[&str], &[&str]) = match compatibility {
+            Compatibility::All | Compatibility::Conquerors => {
+                (&[], &["UP_AVAILABLE", "UP_EXTENSION"])
+            }
+            Compatibility::UserPatch14 => (&["UP_AVAILABLE"], &["UP_EXTENSION"]),
+            Compatibility::UserPatch15 | Compatibility::WololoKingdoms => {
+                (&["UP_AVAILABLE", "UP_EXTENSION"], &[])
+            }
+            Compatibility::HDEdition | Compatibility::DefinitiveEdition => (&[], &[]),
+        };
+        self.builtin_defines
+            .extend(defines.iter().map(|&name| (name, None)));
+        self.builtin_option_defines.extend(
+            option_defines
+                .iter()
+                .chain(MAP_SIZE_DEFINES.iter())
+                .map(|name| name.to_string()),
+        );
+        if compatibility != Compatibility::HDEdition {
+            self.builtin_option_defines
+                .extend(UP_OPTION_DEFINES.iter().cloned());
+        }
 
-        for (atom, _) in Parser::new(file_id, content) {
-            match atom.kind {
-                AtomKind::Const { name, .. } => {
-                    self.builtin_consts.insert(name.value.to_string());
+        let rms = self.rms;
+        for definition in rms.definitions(compatibility) {
+            let content = rms.source(definition.file);
+            let mut category = None;
+            for (atom, _) in Parser::new(definition.file, content) {
+                match &atom.kind {
+                    // Section headings are on their own line, other comments describe the
+                    // #const before them.
+                    AtomKind::Comment {
+                        content: comment, ..
+                    } if starts_line(content, atom.location.start().into()) => {
+                        if let Some(heading) = heading_category(comment) {
+                            category = heading;
+                        }
+                    }
+                    AtomKind::Const { name, .. } => {
+                        if let Some(category) = category {
+                            self.builtin_categories.insert(name.value, category);
+                        }
+                    }
+                    _ => (),
                 }
-                AtomKind::Define { name, .. } => {
-                    self.builtin_defines.insert(name.value.to_string());
+                match (&atom.kind, definition.condition) {
+                    (&AtomKind::Const { name, value, .. }, None) => {
+                        self.builtin_consts
+                            .insert(name.value, ConstDefinition { atom, value });
+                    }
+                    (AtomKind::Const { name, .. }, Some(condition)) => {
+                        self.conditional_consts.insert(name.value, condition);
+                    }
+                    (&AtomKind::Define { name, .. }, None) => {
+                        let definition = ConstDefinition { atom, value: None };
+                        self.builtin_defines.insert(name.value, Some(definition));
+                    }
+                    (AtomKind::Define { name, .. }, Some(_)) => {
+                        self.builtin_option_defines.insert(name.value.to_string());
+                    }
+                    _ => (),
                 }
-                _ => (),
             }
         }
+        let builtin_consts = &self.builtin_consts;
+        self.conditional_consts
+            .retain(|name, _| !builtin_consts.contains_key(name));
     }
 
     /// Update the parse state upon reading a new Atom.
@@ -245,17 +623,9 @@ impl<'a> ParseState<'a> {
     fn set_header(&mut self, name: HeaderName, value: &str) {
         match name {
             HeaderName::Compatibility => {
-                let lower_value = value.cow_to_ascii_lowercase();
-                let compat = match lower_value.trim() {
-                    "hd edition" | "hd" => Compatibility::HDEdition,
-                    "conquerors" | "aoc" => Compatibility::Conquerors,
-                    "userpatch 1.5" | "up 1.5" => Compatibility::UserPatch15,
-                    "userpatch 1.4" | "up 1.4" | "userpatch" | "up" => Compatibility::UserPatch14,
-                    "wololokingdoms" | "wk" => Compatibility::WololoKingdoms,
-                    "definitive edition" | "de" => Compatibility::DefinitiveEdition,
-                    _ => return,
-                };
-                self.set_compatibility(compat);
+                if let Ok(compat) = value.parse() {
+                    self.set_compatibility(compat);
+                }
             }
         }
     }
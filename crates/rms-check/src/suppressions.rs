@@ -0,0 +1,305 @@
+//! Comments that suppress diagnostics.
+//!
+//! ```rms
+//! /* rms-check-disable-next-line arg-types */
+//! base_terrain MY_DEFINE
+//!
+//! /* rms-check-disable compatibility unknown-attribute */
+//! ...
+//! /* rms-check-enable */
+//! ```
+//!
+//! Suppressions are keyed by diagnostic code. Without codes, a suppression applies to all
+//! diagnostics. A reason can be added after ` -- `, like
+//! `/* rms-check-disable-next-line arg-types -- explanation */`.
+
+use crate::diagnostic::{ByteIndex, Diagnostic, SourceLocation};
+use crate::parser::{Atom, AtomKind};
+use crate::RMSFile;
+
+const DISABLE_NEXT_LINE: &str = "rms-check-disable-next-line";
+const DISABLE: &str = "rms-check-disable";
+const ENABLE: &str = "rms-check-enable";
+
+/// The code used for warnings about suppression comments that did not do anything.
+pub const UNUSED_SUPPRESSION: &str = "unused-suppression";
+
+/// Check if the contents of a comment are a suppression directive.
+pub fn is_directive(content: &str) -> bool {
+    matches!(
+        parse_directive(content),
+        Some((DISABLE_NEXT_LINE, _)) | Some((DISABLE, _)) | Some((ENABLE, _))
+    )
+}
+
+/// Split the contents of a comment into the directive name and a list of codes. Anything after
+/// ` -- ` is a description, and ignored.
+fn parse_directive(content: &str) -> Option<(&str, Vec<&str>)> {
+    let directive = content.split(" -- ").next().unwrap_or(content);
+    let mut words = directive
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|word| !word.is_empty());
+    let directive = words.next()?;
+    Some((directive, words.collect()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    /// Applies to diagnostics that start on this line.
+    Line(u32),
+    /// Applies to diagnostics that start in this range. The end is None while no
+    /// `rms-check-enable` comment was found.
+    Range(ByteIndex, Option<ByteIndex>),
+}
+
+#[derive(Debug, Clone)]
+struct Suppression {
+    /// The comment containing the suppression.
+    location: SourceLocation,
+    /// The diagnostic code to suppress, or None to suppress everything.
+    code: Option<String>,
+    scope: Scope,
+}
+
+impl Suppression {
+    fn matches(&self, rms: &RMSFile<'_>, diagnostic: &Diagnostic) -> bool {
+        let location = diagnostic.location();
+        if location.file() != self.location.file() {
+            return false;
+        }
+        if let Some(code) = &self.code {
+            if diagnostic.code() != Some(code.as_str()) {
+                return false;
+            }
+        }
+        match self.scope {
+            Scope::Line(line) => rms
+                .get_location(location.file(), location.start())
+                .is_some_and(|(start_line, _)| start_line == line),
+            Scope::Range(start, end) => {
+                location.start() >= start && end.is_none_or(|end| location.start() < end)
+            }
+        }
+    }
+}
+
+/// The suppression comments in a script.
+#[derive(Debug, Clone, Default)]
+pub struct Suppressions {
+    suppressions: Vec<Suppression>,
+    /// `rms-check-enable` comments that did not match a `rms-check-disable` comment.
+    unmatched_enables: Vec<SourceLocation>,
+}
+
+impl Suppressions {
+    /// Record the suppression directive in an atom, if it is a comment containing one.
+    pub fn add_atom(&mut self, rms: &RMSFile<'_>, atom: &Atom<'_>) {
+        let content = match &atom.kind {
+            AtomKind::Comment { content, .. } => content,
+            _ => return,
+        };
+        let (directive, codes) = match parse_directive(content) {
+            Some(parsed) => parsed,
+            None => return,
+        };
+        let codes = if codes.is_empty() {
+            vec![None]
+        } else {
+            codes.into_iter().map(Some).collect()
+        };
+
+        match directive {
+            DISABLE_NEXT_LINE => {
+                let line = match rms.get_location(atom.location.file(), atom.location.end()) {
+                    Some((line, _)) => line + 1,
+                    None => return,
+                };
+                for code in codes {
+                    self.suppressions.push(Suppression {
+                        location: atom.location,
+                        code: code.map(str::to_string),
+                        scope: Scope::Line(line),
+                    });
+                }
+            }
+            DISABLE => {
+                for code in codes {
+                    self.suppressions.push(Suppression {
+                        location: atom.location,
+                        code: code.map(str::to_string),
+                        scope: Scope::Range(atom.location.end(), None),
+                    });
+                }
+            }
+            ENABLE => {
+                let mut matched = false;
+                for code in codes {
+                    for suppression in self.suppressions.iter_mut() {
+                        match suppression.scope {
+                            // A bare `rms-check-enable` ends all open ranges.
+                            Scope::Range(start, None)
+                                if code.is_none() || suppression.code.as_deref() == code =>
+                            {
+                                suppression.scope =
+                                    Scope::Range(start, Some(atom.location.start()));
+                                matched = true;
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+                if !matched {
+                    self.unmatched_enables.push(atom.location);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Remove the suppressed diagnostics, and add warnings for suppression comments that did
+    /// not suppress anything. Comments that suppress one of the `disabled_lints` are not
+    /// reported, because those lints did not run.
+    pub fn apply(
+        &self,
+        rms: &RMSFile<'_>,
+        diagnostics: Vec<Diagnostic>,
+        disabled_lints: &[&str],
+    ) -> Vec<Diagnostic> {
+        if self.suppressions.is_empty() && self.unmatched_enables.is_empty() {
+            return diagnostics;
+        }
+
+        let mut used = vec![false; self.suppressions.len()];
+        let mut result: Vec<Diagnostic> = diagnostics
+            .into_iter()
+            .filter(|diagnostic| {
+                let mut suppressed = false;
+                for (index, suppression) in self.suppressions.iter().enumerate() {
+                    if suppression.matches(rms, diagnostic) {
+                        used[index] = true;
+                        suppressed = true;
+                    }
+                }
+                !suppressed
+            })
+            .collect();
+
+        let unused = self
+            .suppressions
+            .iter()
+            .zip(used)
+            .filter(|(_, used)| !used)
+            .map(|(suppression, _)| suppression)
+            .filter(|suppression| match &suppression.code {
+                Some(code) => !disabled_lints.contains(&code.as_str()),
+                None => true,
+            });
+        for suppression in unused {
+            let message = match &suppression.code {
+                Some(code) => format!("No `{}` diagnostics were suppressed by this comment", code),
+                None => "No diagnostics were suppressed by this comment".to_string(),
+            };
+            result.push(
+                Diagnostic::warning(suppression.location, message).with_code(UNUSED_SUPPRESSION),
+            );
+        }
+        for &location in &self.unmatched_enables {
+            result.push(
+                Diagnostic::warning(
+                    location,
+                    "This comment does not match a preceding `rms-check-disable` comment",
+                )
+                .with_code(UNUSED_SUPPRESSION),
+            );
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Compatibility, RMSCheck, RMSFile};
+
+    fn check(source: &str) -> Vec<(Option<String>, String)> {
+        let rms = RMSFile::from_string("test.rms", source);
+        RMSCheck::default()
+            .compatibility(Compatibility::Conquerors)
+            .check(&rms)
+            .iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.code().map(str::to_string),
+                    diagnostic.message().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    fn codes(source: &str) -> Vec<String> {
+        check(source)
+            .into_iter()
+            .map(|(code, _)| code.unwrap_or_default())
+            .collect()
+    }
+
+    #[test]
+    fn disable_next_line() {
+        let source = "#define A\r\n<LAND_GENERATION>\r\nbase_terrain A\r\nbase_terrain A\r\n";
+        assert_eq!(codes(source), vec!["arg-types", "arg-types"]);
+
+        let source = "#define A\r\n<LAND_GENERATION>\r\n/* rms-check-disable-next-line arg-types */\r\nbase_terrain A\r\nbase_terrain A\r\n";
+        assert_eq!(codes(source), vec!["arg-types"]);
+
+        let source = "#define A\r\n<LAND_GENERATION>\r\n/* rms-check-disable-next-line */\r\nbase_terrain A\r\nbase_terrain A\r\n";
+        assert_eq!(codes(source), vec!["arg-types"]);
+
+        let source = "#define A\r\n<LAND_GENERATION>\r\n/* rms-check-disable-next-line unknown-attribute */\r\nbase_terrain A\r\n";
+        assert_eq!(
+            check(source),
+            vec![
+                (
+                    Some("arg-types".to_string()),
+                    "Expected a valued token (defined using #const), got a valueless token `A` (defined using #define)".to_string()
+                ),
+                (
+                    Some("unused-suppression".to_string()),
+                    "No `unknown-attribute` diagnostics were suppressed by this comment"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn disable_range() {
+        let source = "#define A\r\n<LAND_GENERATION>\r\n/* rms-check-disable arg-types */\r\nbase_terrain A\r\nbase_terrain A\r\n/* rms-check-enable */\r\nbase_terrain A\r\n";
+        assert_eq!(codes(source), vec!["arg-types"]);
+
+        // Unclosed ranges last until the end of the file.
+        let source = "#define A\r\n<LAND_GENERATION>\r\n/* rms-check-disable arg-types, unknown-attribute */\r\nbase_terrain A\r\nbase_terrain A\r\n";
+        assert_eq!(codes(source), vec!["unused-suppression"]);
+
+        // Re-enable a single code.
+        let source = "#define A\r\n<LAND_GENERATION>\r\n/* rms-check-disable arg-types parse unknown-attribute */\r\nbase_terrain A\r\n/* rms-check-enable arg-types */\r\nbase_terrain A\r\nbase_terran 1\r\n";
+        assert_eq!(codes(source), vec!["arg-types"]);
+
+        let source = "<LAND_GENERATION>\r\n/* rms-check-enable */\r\n";
+        assert_eq!(
+            check(source),
+            vec![(
+                Some("unused-suppression".to_string()),
+                "This comment does not match a preceding `rms-check-disable` comment".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn inside_conditionals() {
+        // The game parses the reason, so mentioning a constant there is not safe.
+        let source = "#define A\r\n<LAND_GENERATION>\r\nif A\r\n/* rms-check-disable-next-line arg-types -- A is fine */\r\nbase_terrain A\r\nendif\r\nstart_random\r\npercent_chance 50\r\n/* rms-check-disable-next-line arg-types -- A is fine */\r\nbase_terrain A\r\nend_random\r\n";
+        assert_eq!(codes(source), vec!["comment-contents", "comment-contents"]);
+        let source = "#define A\r\n<LAND_GENERATION>\r\nif A\r\n/* rms-check-disable-next-line arg-types -- `A` is fine */\r\nbase_terrain A\r\nendif\r\n";
+        assert_eq!(codes(source), Vec::<String>::new());
+    }
+}
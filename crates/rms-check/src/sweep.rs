@@ -0,0 +1,554 @@
+//! Check a script for every combination of lobby settings.
+
+use crate::diagnostic::{Diagnostic, SourceLocation};
+use crate::expand::{mask, ExpandOptions};
+use crate::parser::{AtomKind, Parser};
+use crate::state::{Compatibility, GAME_MODE_DEFINES, MAP_SIZE_DEFINES};
+use crate::suppressions::UNUSED_SUPPRESSION;
+use crate::{RMSCheck, RMSFile};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+
+/// The number of configurations to list in a description, before summarising the rest.
+const MAX_LISTED_CONFIGURATIONS: usize = 3;
+
+/// The default maximum number of configurations to check a script with.
+pub const DEFAULT_MAX_CONFIGURATIONS: usize = 256;
+
+/// A combination of lobby settings, as the `#define` names that the game sets for them.
+///
+/// Only the settings that the script checks for are included.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Configuration {
+    /// A description of each setting, like "LARGE_MAP" or "other game mode".
+    settings: Vec<String>,
+    defines: Vec<String>,
+}
+
+impl Configuration {
+    /// Iterate over the `#define` names that are set in this configuration.
+    pub fn defines(&self) -> impl Iterator<Item = &str> {
+        self.defines.iter().map(String::as_str)
+    }
+
+    fn has_setting(&self, setting: &str) -> bool {
+        self.settings.iter().any(|s| s == setting)
+    }
+}
+
+impl Display for Configuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.settings.is_empty() {
+            write!(f, "default settings")
+        } else {
+            write!(f, "{}", self.settings.join(", "))
+        }
+    }
+}
+
+/// Lobby settings that are mutually exclusive: every configuration uses one of the options.
+struct Dimension {
+    /// Describes the options that set none of the `#define` names the script checks for.
+    other: &'static str,
+    /// Each option is a list of `#define` names.
+    options: Vec<Vec<String>>,
+}
+
+fn dimensions(compatibility: Compatibility) -> Vec<Dimension> {
+    let names = |names: &[&str]| names.iter().map(|name| vec![name.to_string()]).collect();
+    let mut dimensions = vec![Dimension {
+        other: "other map size",
+        options: names(&MAP_SIZE_DEFINES),
+    }];
+    if compatibility == Compatibility::HDEdition {
+        return dimensions;
+    }
+
+    let mut player_setups = vec![];
+    for players in 1..=8 {
+        // Everyone for themselves.
+        let mut setup = vec![
+            format!("{}_PLAYER_GAME", players),
+            "0_TEAM_GAME".to_string(),
+            format!("TEAM0_SIZE{}", players),
+        ];
+        setup.extend((1..=players).map(|player| format!("PLAYER{}_TEAM0", player)));
+        player_setups.push(setup);
+        // Two teams, as even as possible, with the first players on team 1.
+        if players >= 2 {
+            let team1_size = players - players / 2;
+            let mut setup = vec![
+                format!("{}_PLAYER_GAME", players),
+                "2_TEAM_GAME".to_string(),
+                format!("TEAM1_SIZE{}", team1_size),
+                format!("TEAM2_SIZE{}", players / 2),
+            ];
+            setup.extend((1..=players).map(|player| {
+                let team = if player <= team1_size { 1 } else { 2 };
+                format!("PLAYER{}_TEAM{}", player, team)
+            }));
+            player_setups.push(setup);
+        }
+    }
+    dimensions.push(Dimension {
+        other: "other player setup",
+        options: player_setups,
+    });
+    dimensions.push(Dimension {
+        other: "other game mode",
+        options: names(&GAME_MODE_DEFINES),
+    });
+    dimensions.push(Dimension {
+        other: "without FIXED_POSITIONS",
+        options: vec![vec![], vec!["FIXED_POSITIONS".to_string()]],
+    });
+    dimensions.push(Dimension {
+        other: "without AI_PLAYERS",
+        options: vec![vec![], vec!["AI_PLAYERS".to_string()]],
+    });
+    dimensions
+}
+
+/// List the configurations that can make a difference for a script. Settings the script does not
+/// check for are left out, so scripts that only check the map size are only checked a few times.
+fn configurations(rms: &RMSFile<'_>, compatibility: Compatibility) -> Vec<Configuration> {
+    let checked: HashSet<&str> = Parser::new(rms.file_id(), rms.main_source())
+        .filter_map(|(atom, _)| match atom.kind {
+            AtomKind::If { condition, .. } | AtomKind::ElseIf { condition, .. } => {
+                Some(condition.value)
+            }
+            _ => None,
+        })
+        .collect();
+
+    let mut configurations = vec![Configuration::default()];
+    for Dimension {
+        other,
+        options: all_options,
+    } in dimensions(compatibility)
+    {
+        let mut options: Vec<Vec<String>> = vec![];
+        for option in all_options {
+            let option: Vec<String> = option
+                .into_iter()
+                .filter(|name| checked.contains(name.as_str()))
+                .collect();
+            if !options.contains(&option) {
+                options.push(option);
+            }
+        }
+        if options.len() < 2 {
+            continue;
+        }
+
+        configurations = configurations
+            .into_iter()
+            .flat_map(|configuration| {
+                options.iter().map(move |option| {
+                    let setting = if option.is_empty() {
+                        other.to_string()
+                    } else {
+                        option.join(", ")
+                    };
+                    let mut configuration = configuration.clone();
+                    configuration.settings.push(setting);
+                    configuration.defines.extend(option.iter().cloned());
+                    configuration
+                })
+            })
+            .collect();
+    }
+    configurations
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Pick `max` configurations spread over the list, if there are more than that.
+///
+/// Configurations are picked at a fixed step. The step has no factor in common with the number of
+/// configurations, so that the picks do not always land on the same option of a setting.
+fn sample(configurations: Vec<Configuration>, max: usize) -> Vec<Configuration> {
+    let total = configurations.len();
+    if total <= max {
+        return configurations;
+    }
+    let mut step = total / max;
+    while gcd(step, total) != 1 {
+        step += 1;
+    }
+    let picked: HashSet<usize> = (0..max).map(|index| index * step % total).collect();
+    configurations
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| picked.contains(index))
+        .map(|(_, configuration)| configuration)
+        .collect()
+}
+
+/// A diagnostic that was reported for some of the configurations.
+#[derive(Debug, Clone)]
+pub struct SweepDiagnostic {
+    diagnostic: Diagnostic,
+    configurations: Vec<Configuration>,
+    condition: Option<String>,
+}
+
+impl SweepDiagnostic {
+    /// Get the diagnostic.
+    pub fn diagnostic(&self) -> &Diagnostic {
+        &self.diagnostic
+    }
+
+    /// Get the configurations this diagnostic was reported for.
+    pub fn configurations(&self) -> &[Configuration] {
+        &self.configurations
+    }
+
+    /// Describe the configurations this diagnostic was reported for, like "LARGE_MAP" or
+    /// "TINY_MAP, 2_PLAYER_GAME". Returns `None` if it was reported for all configurations.
+    pub fn condition(&self) -> Option<&str> {
+        self.condition.as_deref()
+    }
+}
+
+/// Describe a subset of the configurations.
+fn describe(all: &[Configuration], subset: &[Configuration]) -> Option<String> {
+    if subset.len() == all.len() {
+        return None;
+    }
+
+    // If the subset is exactly the configurations that have some settings in common, describe it
+    // using those settings.
+    let common: Vec<&str> = subset[0]
+        .settings
+        .iter()
+        .map(String::as_str)
+        .filter(|setting| {
+            subset
+                .iter()
+                .all(|configuration| configuration.has_setting(setting))
+        })
+        .collect();
+    let num_matching = all
+        .iter()
+        .filter(|configuration| {
+            common
+                .iter()
+                .all(|setting| configuration.has_setting(setting))
+        })
+        .count();
+    if !common.is_empty() && num_matching == subset.len() {
+        return Some(common.join(", "));
+    }
+
+    let mut description = subset
+        .iter()
+        .take(MAX_LISTED_CONFIGURATIONS)
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ");
+    if subset.len() > MAX_LISTED_CONFIGURATIONS {
+        description += &format!(" and {} more", subset.len() - MAX_LISTED_CONFIGURATIONS);
+    }
+    Some(description)
+}
+
+/// The result of a configuration sweep.
+#[derive(Debug, Clone)]
+pub struct SweepResult {
+    configurations: Vec<Configuration>,
+    skipped: usize,
+    diagnostics: Vec<SweepDiagnostic>,
+}
+
+impl SweepResult {
+    /// Get the configurations that the script was checked with.
+    pub fn configurations(&self) -> &[Configuration] {
+        &self.configurations
+    }
+
+    /// Get the number of configurations that were not checked, because there were more than the
+    /// maximum.
+    pub const fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// Iterate over the diagnostics, with the configurations they were reported for.
+    pub fn iter(&self) -> impl Iterator<Item = &SweepDiagnostic> {
+        self.diagnostics.iter()
+    }
+
+    /// Iterate over the diagnostics that were only reported for some configurations.
+    pub fn conditional(&self) -> impl Iterator<Item = &SweepDiagnostic> {
+        self.iter()
+            .filter(|diagnostic| diagnostic.condition.is_some())
+    }
+}
+
+/// Check a script once for every combination of lobby settings: map sizes, player counts and
+/// teams, and game modes.
+///
+/// For each combination, the `if` branches that the game would skip are removed before checking,
+/// so problems like using a `#const` that is only defined `if LARGE_MAP` are found. `start_random`
+/// blocks are checked as usual.
+///
+/// Only the settings that the script has `if` statements for are combined, and combinations that
+/// leave the same code are checked once. Still, every remaining combination is a full check, and a
+/// script that checks for all settings has thousands of them. At most `max_configurations()` of
+/// them are checked, picked evenly from all combinations.
+///
+/// ## Example
+/// ```rust
+/// use rms_check::{ConfigurationSweep, RMSCheck, RMSFile};
+/// let source = "if LARGE_MAP #const UNIT 4 endif
+/// <OBJECTS_GENERATION> create_object UNIT { number_of_objects 1 }";
+/// let rms = RMSFile::from_string("example.rms", source);
+/// assert!(!RMSCheck::default().check(&rms).has_warnings());
+///
+/// let result = ConfigurationSweep::new(RMSCheck::default).check(&rms);
+/// let diagnostic = result.conditional().next().unwrap();
+/// assert_eq!(diagnostic.diagnostic().message(), "Token `UNIT` is never defined");
+/// assert_eq!(diagnostic.configurations().len(), 1);
+/// ```
+pub struct ConfigurationSweep<F> {
+    make_check: F,
+    max_configurations: usize,
+}
+
+impl<F> ConfigurationSweep<F>
+where
+    F: Fn() -> RMSCheck,
+{
+    /// Create a configuration sweep. `make_check` is called to create a fresh checker for each
+    /// configuration.
+    pub const fn new(make_check: F) -> Self {
+        Self {
+            make_check,
+            max_configurations: DEFAULT_MAX_CONFIGURATIONS,
+        }
+    }
+
+    /// Set the maximum number of configurations to check (default
+    /// [`DEFAULT_MAX_CONFIGURATIONS`]).
+    pub fn max_configurations(self, max_configurations: usize) -> Self {
+        Self {
+            max_configurations,
+            ..self
+        }
+    }
+
+    /// Check a script for every configuration.
+    pub fn check(&self, rms: &RMSFile<'_>) -> SweepResult {
+        let compatibility = (self.make_check)().checker.compatibility_for(rms);
+        let all_configurations = configurations(rms, compatibility);
+        let total = all_configurations.len();
+        let configurations = sample(all_configurations, self.max_configurations);
+
+        let mut diagnostics: Vec<(Diagnostic, Vec<Configuration>)> = vec![];
+        let mut seen: HashMap<(SourceLocation, Option<String>, String), usize> = HashMap::new();
+        // The diagnostics for each version of the script, so that configurations that leave the
+        // same code are only checked once.
+        let mut checked: HashMap<String, Vec<usize>> = HashMap::new();
+        for configuration in &configurations {
+            let options = configuration.defines().fold(
+                ExpandOptions::default()
+                    .compatibility(compatibility)
+                    .keep_random_blocks(true),
+                ExpandOptions::define,
+            );
+            let source = mask(rms, &options);
+            if let Some(indices) = checked.get(&source) {
+                for &index in indices {
+                    diagnostics[index].1.push(configuration.clone());
+                }
+                continue;
+            }
+
+            let masked = rms.with_main_source(source.clone());
+            let mut indices = vec![];
+            for diagnostic in (self.make_check)().check(&masked) {
+                // Suppression comments and definitions may be used by code that was removed in this
+                // configuration.
+                if matches!(
+                    diagnostic.code(),
+                    Some(UNUSED_SUPPRESSION) | Some("unused-definition")
+                ) {
+                    continue;
+                }
+                let key = (
+                    diagnostic.location(),
+                    diagnostic.code().map(str::to_string),
+                    diagnostic.message().to_string(),
+                );
+                match seen.get(&key) {
+                    // The same diagnostic may be reported more than once in one configuration.
+                    Some(&index) if indices.contains(&index) => (),
+                    Some(&index) => {
+                        diagnostics[index].1.push(configuration.clone());
+                        indices.push(index);
+                    }
+                    None => {
+                        seen.insert(key, diagnostics.len());
+                        indices.push(diagnostics.len());
+                        diagnostics.push((diagnostic, vec![configuration.clone()]));
+                    }
+                }
+            }
+            checked.insert(source, indices);
+        }
+
+        diagnostics.sort_by_key(|(diagnostic, _)| {
+            let location = diagnostic.location();
+            (location.file().to_usize(), location.start())
+        });
+        let diagnostics = diagnostics
+            .into_iter()
+            .map(|(diagnostic, subset)| SweepDiagnostic {
+                condition: describe(&configurations, &subset),
+                diagnostic,
+                configurations: subset,
+            })
+            .collect();
+        SweepResult {
+            skipped: total - configurations.len(),
+            configurations,
+            diagnostics,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sweep(source: &str, compatibility: Compatibility) -> SweepResult {
+        let rms = RMSFile::from_string("test.rms", source);
+        ConfigurationSweep::new(|| RMSCheck::default().compatibility(compatibility)).check(&rms)
+    }
+
+    #[test]
+    fn only_checked_settings() {
+        let result = sweep("<LAND_GENERATION>\r\n", Compatibility::Conquerors);
+        assert_eq!(result.configurations(), &[Configuration::default()]);
+        assert_eq!(result.configurations()[0].to_string(), "default settings");
+
+        let result = sweep(
+            "if TINY_MAP #define A elseif LARGE_MAP #define B endif\r\n",
+            Compatibility::UserPatch15,
+        );
+        let configurations: Vec<_> = result
+            .configurations()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            configurations,
+            vec!["TINY_MAP", "other map size", "LARGE_MAP"]
+        );
+
+        let result = sweep(
+            "if LARGE_MAP #define A endif\r\nif 2_PLAYER_GAME #define B endif\r\nif TEAM1_SIZE4 #define C endif\r\n",
+            Compatibility::UserPatch15,
+        );
+        assert_eq!(result.configurations().len(), 2 * 3);
+
+        // HD Edition does not have player count defines.
+        let result = sweep(
+            "if LARGE_MAP #define A endif\r\nif 2_PLAYER_GAME #define B endif\r\n",
+            Compatibility::HDEdition,
+        );
+        assert_eq!(result.configurations().len(), 2);
+    }
+
+    #[test]
+    fn player_teams() {
+        let result = sweep(
+            "if PLAYER2_TEAM2 #define A endif\r\n",
+            Compatibility::UserPatch15,
+        );
+        let configurations: Vec<_> = result
+            .configurations()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(configurations, vec!["other player setup", "PLAYER2_TEAM2"]);
+    }
+
+    #[test]
+    fn same_code_checked_once() {
+        let checks = std::cell::Cell::new(0);
+        let rms = RMSFile::from_string(
+            "test.rms",
+            "if LARGE_MAP endif\r\nif REGICIDE endif\r\n<OBJECTS_GENERATION>\r\ncreate_object MISSING { number_of_objects 1 }\r\n",
+        );
+        let result = ConfigurationSweep::new(|| {
+            checks.set(checks.get() + 1);
+            RMSCheck::default().compatibility(Compatibility::UserPatch15)
+        })
+        .check(&rms);
+        assert_eq!(result.configurations().len(), 4);
+        // One checker is used to find the compatibility level.
+        assert_eq!(checks.get(), 2);
+        let diagnostic = result.iter().next().unwrap();
+        assert_eq!(diagnostic.configurations().len(), 4);
+        assert_eq!(diagnostic.condition(), None);
+    }
+
+    #[test]
+    fn max_configurations() {
+        let rms = RMSFile::from_string(
+            "test.rms",
+            "if TINY_MAP #define A elseif LARGE_MAP #define B endif\r\nif REGICIDE #define C endif\r\n",
+        );
+        let result = ConfigurationSweep::new(|| {
+            RMSCheck::default().compatibility(Compatibility::UserPatch15)
+        })
+        .max_configurations(3)
+        .check(&rms);
+        let configurations: Vec<_> = result
+            .configurations()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            configurations,
+            vec![
+                "TINY_MAP, other game mode",
+                "LARGE_MAP, other game mode",
+                "LARGE_MAP, REGICIDE"
+            ]
+        );
+        assert_eq!(result.skipped(), 3);
+    }
+
+    #[test]
+    fn conditional_diagnostics() {
+        let source = "if LARGE_MAP\r\n  #const UNIT 4\r\nelseif HUGE_MAP\r\n  #const UNIT 4\r\nendif\r\nif REGICIDE\r\n  #define ROYAL\r\nendif\r\n<OBJECTS_GENERATION>\r\ncreate_object UNIT { number_of_objects 1 }\r\ncreate_object ROYAL { number_of_objects 1 }\r\ncreate_object MISSING { number_of_objects 1 }\r\n";
+        let result = sweep(source, Compatibility::UserPatch15);
+        assert_eq!(result.configurations().len(), 3 * 2);
+
+        let diagnostics: Vec<_> = result
+            .iter()
+            .map(|diagnostic| (diagnostic.diagnostic().message(), diagnostic.condition()))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            vec![
+                ("Token `UNIT` is never defined", Some("other map size")),
+                ("Token `ROYAL` is never defined", Some("other game mode")),
+                (
+                    "Expected a valued token (defined using #const), got a valueless token `ROYAL` (defined using #define)",
+                    Some("REGICIDE")
+                ),
+                ("Token `MISSING` is never defined", None),
+            ]
+        );
+        assert_eq!(result.conditional().count(), 3);
+    }
+}
@@ -0,0 +1,643 @@
+//! A lossless, hierarchical syntax tree for AoE2 random map scripts.
+//!
+//! The [`Parser`] yields a flat stream of atoms. This module groups those atoms into nested nodes
+//! for sections, command blocks, `if` statements and `start_random` statements, and keeps the
+//! whitespace between atoms around as trivia. Printing a [`SyntaxTree`] always reproduces the exact
+//! source text it was parsed from.
+
+use crate::diagnostic::{ByteIndex, FileId, SourceLocation};
+use crate::parser::{Atom, AtomKind, Parser};
+use std::fmt::{self, Display};
+
+/// The kind of a syntax node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SyntaxKind {
+    /// The entire script.
+    Script,
+    /// A `<SECTION>` header, and everything up to the next section header.
+    Section,
+    /// A block of attributes, `{ … }`. If the block belongs to a command like `create_object`, the
+    /// command atom is the first child of the block.
+    Block,
+    /// A conditional statement. Contains `IfBranch` nodes followed by the `endif` atom.
+    If,
+    /// A single branch of a conditional statement, starting with an `if`, `elseif` or `else`
+    /// atom.
+    IfBranch,
+    /// A `start_random` statement. Contains the `start_random` atom, `RandomBranch` nodes and the
+    /// `end_random` atom.
+    Random,
+    /// A single `percent_chance` branch of a `start_random` statement.
+    RandomBranch,
+}
+
+/// Whitespace in between atoms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trivia<'a> {
+    /// The whitespace characters.
+    pub value: &'a str,
+    /// Source code location for this whitespace.
+    pub location: SourceLocation,
+}
+
+/// A child of a syntax node.
+#[derive(Debug, Clone)]
+pub enum SyntaxElement<'a> {
+    /// A nested node.
+    Node(SyntaxNode<'a>),
+    /// A single atom.
+    Atom(Atom<'a>),
+    /// Whitespace.
+    Trivia(Trivia<'a>),
+}
+
+impl<'a> SyntaxElement<'a> {
+    /// Get the source code location of this element.
+    pub fn location(&self) -> SourceLocation {
+        match self {
+            SyntaxElement::Node(node) => node.location(),
+            SyntaxElement::Atom(atom) => atom.location,
+            SyntaxElement::Trivia(trivia) => trivia.location,
+        }
+    }
+
+    /// Get the node if this element is a node.
+    pub fn as_node(&self) -> Option<&SyntaxNode<'a>> {
+        match self {
+            SyntaxElement::Node(node) => Some(node),
+            _ => None,
+        }
+    }
+
+    /// Get the atom if this element is an atom.
+    pub fn as_atom(&self) -> Option<&Atom<'a>> {
+        match self {
+            SyntaxElement::Atom(atom) => Some(atom),
+            _ => None,
+        }
+    }
+
+    /// Is this element whitespace?
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, SyntaxElement::Trivia(_))
+    }
+}
+
+/// A node in the syntax tree.
+#[derive(Debug, Clone)]
+pub struct SyntaxNode<'a> {
+    kind: SyntaxKind,
+    location: SourceLocation,
+    children: Vec<SyntaxElement<'a>>,
+}
+
+impl<'a> SyntaxNode<'a> {
+    fn new(kind: SyntaxKind, location: SourceLocation) -> Self {
+        Self {
+            kind,
+            location,
+            children: vec![],
+        }
+    }
+
+    /// Add a child, extending the location of this node to cover it.
+    fn push(&mut self, element: SyntaxElement<'a>) {
+        let location = element.location();
+        if self.children.is_empty() && self.kind != SyntaxKind::Script {
+            self.location = location;
+        } else {
+            self.location = SourceLocation::new(
+                self.location.file(),
+                self.location.start().min(location.start())
+                    ..self.location.end().max(location.end()),
+            );
+        }
+        self.children.push(element);
+    }
+
+    /// Get the kind of this node.
+    pub const fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+
+    /// Get the source code location covered by this node.
+    pub const fn location(&self) -> SourceLocation {
+        self.location
+    }
+
+    /// Get the byte range covered by this node.
+    pub const fn range(&self) -> std::ops::Range<ByteIndex> {
+        self.location.range()
+    }
+
+    /// Iterate over the direct children of this node, including trivia.
+    pub fn children(&self) -> impl Iterator<Item = &SyntaxElement<'a>> {
+        self.children.iter()
+    }
+
+    /// Iterate over the direct child nodes of this node.
+    pub fn child_nodes(&self) -> impl Iterator<Item = &SyntaxNode<'a>> {
+        self.children.iter().filter_map(SyntaxElement::as_node)
+    }
+
+    /// Iterate over the direct child atoms of this node.
+    pub fn child_atoms(&self) -> impl Iterator<Item = &Atom<'a>> {
+        self.children.iter().filter_map(SyntaxElement::as_atom)
+    }
+
+    /// Get the atom that introduces this node: the section header, the block command (or `{` if
+    /// the block has no command), the `if`/`elseif`/`else`, `start_random` or `percent_chance`
+    /// atom.
+    ///
+    /// For `If` nodes, this is the `if` atom of the first branch. The `Script` node has no head.
+    pub fn head(&self) -> Option<&Atom<'a>> {
+        match self.kind {
+            SyntaxKind::Script => None,
+            SyntaxKind::If => self.child_nodes().next().and_then(SyntaxNode::head),
+            _ => self.child_atoms().next(),
+        }
+    }
+
+    /// Get the atom that closes this node: `}`, `endif` or `end_random`, if it is present.
+    pub fn tail(&self) -> Option<&Atom<'a>> {
+        let last = self.child_atoms().last()?;
+        let is_tail = matches!(
+            (self.kind, &last.kind),
+            (SyntaxKind::Block, AtomKind::CloseBlock { .. })
+                | (SyntaxKind::If, AtomKind::EndIf { .. })
+                | (SyntaxKind::Random, AtomKind::EndRandom { .. })
+        );
+        if is_tail {
+            Some(last)
+        } else {
+            None
+        }
+    }
+
+    /// Get the command atom that this block belongs to, like `create_object X`.
+    pub fn command(&self) -> Option<&Atom<'a>> {
+        if self.kind != SyntaxKind::Block {
+            return None;
+        }
+        self.head()
+            .filter(|atom| matches!(atom.kind, AtomKind::Command { .. }))
+    }
+
+    /// Iterate over the branches of an `If` or `Random` node.
+    pub fn branches(&self) -> impl Iterator<Item = &SyntaxNode<'a>> {
+        self.child_nodes()
+            .filter(|node| matches!(node.kind, SyntaxKind::IfBranch | SyntaxKind::RandomBranch))
+    }
+
+    /// Collect all atoms inside this node, in source order.
+    pub fn atoms(&self) -> Vec<&Atom<'a>> {
+        fn collect<'n, 'a>(node: &'n SyntaxNode<'a>, atoms: &mut Vec<&'n Atom<'a>>) {
+            for child in &node.children {
+                match child {
+                    SyntaxElement::Node(node) => collect(node, atoms),
+                    SyntaxElement::Atom(atom) => atoms.push(atom),
+                    SyntaxElement::Trivia(_) => (),
+                }
+            }
+        }
+
+        let mut atoms = vec![];
+        collect(self, &mut atoms);
+        atoms
+    }
+
+    /// Find the innermost node that contains the given byte index.
+    pub fn node_at(&self, index: ByteIndex) -> Option<&SyntaxNode<'a>> {
+        if !self.range().contains(&index) {
+            return None;
+        }
+        Some(
+            self.child_nodes()
+                .find_map(|node| node.node_at(index))
+                .unwrap_or(self),
+        )
+    }
+
+    /// Write the source text of this node.
+    fn write_to(&self, source: &str, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.write_to(source, f)?,
+                SyntaxElement::Atom(atom) => {
+                    f.write_str(&source[atom.range().start.into()..atom.range().end.into()])?
+                }
+                SyntaxElement::Trivia(trivia) => f.write_str(trivia.value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A lossless syntax tree for a random map script.
+///
+/// ## Example
+/// ```rust
+/// use rms_check::{RMSFile, SyntaxKind};
+/// let source = "<OBJECTS_GENERATION>\r\ncreate_object SCOUT {\r\n  number_of_objects 1\r\n}\r\n";
+/// let rms = RMSFile::from_string("example.rms", source);
+/// let tree = rms.syntax_tree(rms.file_id());
+/// let section = tree.root().child_nodes().next().unwrap();
+/// assert_eq!(section.kind(), SyntaxKind::Section);
+/// let block = section.child_nodes().next().unwrap();
+/// assert_eq!(block.kind(), SyntaxKind::Block);
+/// assert_eq!(tree.text(block), "create_object SCOUT {\r\n  number_of_objects 1\r\n}");
+/// assert_eq!(tree.to_string(), source);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SyntaxTree<'a> {
+    source: &'a str,
+    root: SyntaxNode<'a>,
+}
+
+impl<'a> SyntaxTree<'a> {
+    /// Parse a source string into a syntax tree.
+    pub fn parse(file_id: FileId, source: &'a str) -> Self {
+        TreeBuilder::new(file_id, source).build(Parser::new(file_id, source))
+    }
+
+    /// Get the root node, of kind `SyntaxKind::Script`.
+    pub const fn root(&self) -> &SyntaxNode<'a> {
+        &self.root
+    }
+
+    /// Get the source text covered by a node.
+    pub fn text(&self, node: &SyntaxNode<'_>) -> &'a str {
+        &self.source[node.range().start.into()..node.range().end.into()]
+    }
+
+    /// Get the full source text this tree was parsed from.
+    pub const fn source(&self) -> &'a str {
+        self.source
+    }
+}
+
+impl Display for SyntaxTree<'_> {
+    /// Print the tree, reproducing the original source text.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.root.write_to(self.source, f)
+    }
+}
+
+/// Builds a syntax tree from a stream of atoms.
+struct TreeBuilder<'a> {
+    file_id: FileId,
+    source: &'a str,
+    /// The nodes that are currently open. The bottom of the stack is always the `Script` node.
+    stack: Vec<SyntaxNode<'a>>,
+    /// The end of the last atom, where the next trivia starts.
+    offset: ByteIndex,
+    /// Whitespace that has not been added to a node yet. It is added to whichever node receives
+    /// the next atom, so nodes never end in whitespace.
+    pending: Option<Trivia<'a>>,
+}
+
+impl<'a> TreeBuilder<'a> {
+    fn new(file_id: FileId, source: &'a str) -> Self {
+        let empty = SourceLocation::new(file_id, ByteIndex::from(0)..ByteIndex::from(0));
+        Self {
+            file_id,
+            source,
+            stack: vec![SyntaxNode::new(SyntaxKind::Script, empty)],
+            offset: ByteIndex::from(0),
+            pending: None,
+        }
+    }
+
+    fn top(&mut self) -> &mut SyntaxNode<'a> {
+        self.stack
+            .last_mut()
+            .expect("the script node is never popped")
+    }
+
+    /// Remember whitespace up to the given index.
+    fn trivia(&mut self, until: ByteIndex) {
+        if until > self.offset {
+            let location = SourceLocation::new(self.file_id, self.offset..until);
+            let value = &self.source[self.offset.into()..until.into()];
+            self.pending = Some(Trivia { value, location });
+        }
+    }
+
+    /// Add pending whitespace to the current node.
+    fn flush(&mut self) {
+        if let Some(trivia) = self.pending.take() {
+            self.top().push(SyntaxElement::Trivia(trivia));
+        }
+    }
+
+    fn push_atom(&mut self, atom: Atom<'a>) {
+        self.flush();
+        self.top().push(SyntaxElement::Atom(atom));
+    }
+
+    /// Close the current node, adding it to its parent.
+    fn close(&mut self) {
+        let node = self.stack.pop().expect("the script node is never popped");
+        self.top().push(SyntaxElement::Node(node));
+    }
+
+    /// Open a new node with the given atom as its first child.
+    fn open(&mut self, kind: SyntaxKind, atom: Atom<'a>) {
+        self.flush();
+        let mut node = SyntaxNode::new(kind, atom.location);
+        node.push(SyntaxElement::Atom(atom));
+        self.stack.push(node);
+    }
+
+    /// Find the innermost open node of the given kind. Only `Section` nodes may be open above it:
+    /// anything else means that the script is unbalanced.
+    fn find_open(&self, kind: SyntaxKind) -> Option<usize> {
+        for (index, node) in self.stack.iter().enumerate().skip(1).rev() {
+            if node.kind == kind {
+                return Some(index);
+            }
+            if node.kind != SyntaxKind::Section {
+                return None;
+            }
+        }
+        None
+    }
+
+    /// Close nodes until the node at `index` is on top of the stack.
+    fn close_until(&mut self, index: usize) {
+        while self.stack.len() > index + 1 {
+            self.close();
+        }
+    }
+
+    fn open_block(&mut self, atom: Atom<'a>) {
+        self.flush();
+        // If the `{` follows a command, possibly with comments in between, the command belongs to
+        // the block.
+        let file_id = self.file_id;
+        let top = self.top();
+        let command_index = top
+            .children
+            .iter()
+            .rposition(|child| {
+                !matches!(
+                    child,
+                    SyntaxElement::Trivia(_)
+                        | SyntaxElement::Atom(Atom {
+                            kind: AtomKind::Comment { .. },
+                            ..
+                        })
+                )
+            })
+            .filter(|&index| {
+                matches!(
+                    top.children[index],
+                    SyntaxElement::Atom(Atom {
+                        kind: AtomKind::Command { .. },
+                        ..
+                    })
+                )
+            });
+
+        let mut block = SyntaxNode::new(SyntaxKind::Block, atom.location);
+        if let Some(index) = command_index {
+            let elements = top.children.split_off(index);
+            // Recompute the parent's location without the moved elements.
+            let mut parent = SyntaxNode::new(top.kind, top.location);
+            if top.kind == SyntaxKind::Script {
+                parent.location =
+                    SourceLocation::new(file_id, ByteIndex::from(0)..ByteIndex::from(0));
+            }
+            for child in top.children.drain(..) {
+                parent.push(child);
+            }
+            *top = parent;
+            for element in elements {
+                block.push(element);
+            }
+        }
+        block.push(SyntaxElement::Atom(atom));
+        self.stack.push(block);
+    }
+
+    fn write_atom(&mut self, atom: Atom<'a>) {
+        self.trivia(atom.range().start);
+        self.offset = atom.range().end;
+
+        match atom.kind {
+            AtomKind::Section { .. } => {
+                if let Some(index) = self.find_open(SyntaxKind::Section) {
+                    self.close_until(index - 1);
+                }
+                self.open(SyntaxKind::Section, atom);
+            }
+            AtomKind::OpenBlock { .. } => self.open_block(atom),
+            AtomKind::CloseBlock { .. } => match self.find_open(SyntaxKind::Block) {
+                Some(index) => {
+                    self.close_until(index);
+                    self.push_atom(atom);
+                    self.close();
+                }
+                None => self.push_atom(atom),
+            },
+            AtomKind::If { .. } => {
+                self.flush();
+                let location = atom.location;
+                self.stack.push(SyntaxNode::new(SyntaxKind::If, location));
+                self.open(SyntaxKind::IfBranch, atom);
+            }
+            AtomKind::ElseIf { .. } | AtomKind::Else { .. } => {
+                match self.find_open(SyntaxKind::IfBranch) {
+                    Some(index) => {
+                        self.close_until(index - 1);
+                        self.open(SyntaxKind::IfBranch, atom);
+                    }
+                    None => self.push_atom(atom),
+                }
+            }
+            AtomKind::EndIf { .. } => match self.find_open(SyntaxKind::IfBranch) {
+                Some(index) => {
+                    self.close_until(index - 1);
+                    self.push_atom(atom);
+                    self.close();
+                }
+                None => self.push_atom(atom),
+            },
+            AtomKind::StartRandom { .. } => self.open(SyntaxKind::Random, atom),
+            AtomKind::PercentChance { .. } => {
+                let random = self
+                    .find_open(SyntaxKind::RandomBranch)
+                    .map(|index| index - 1)
+                    .or_else(|| self.find_open(SyntaxKind::Random));
+                match random {
+                    Some(index) => {
+                        self.close_until(index);
+                        self.open(SyntaxKind::RandomBranch, atom);
+                    }
+                    None => self.push_atom(atom),
+                }
+            }
+            AtomKind::EndRandom { .. } => {
+                let random = self
+                    .find_open(SyntaxKind::RandomBranch)
+                    .map(|index| index - 1)
+                    .or_else(|| self.find_open(SyntaxKind::Random));
+                match random {
+                    Some(index) => {
+                        self.close_until(index);
+                        self.push_atom(atom);
+                        self.close();
+                    }
+                    None => self.push_atom(atom),
+                }
+            }
+            _ => self.push_atom(atom),
+        }
+    }
+
+    fn build(mut self, parser: Parser<'a>) -> SyntaxTree<'a> {
+        for (atom, _errors) in parser {
+            self.write_atom(atom);
+        }
+        self.trivia(ByteIndex::from(self.source.len()));
+        self.close_until(0);
+        self.flush();
+
+        let mut root = self.stack.pop().expect("the script node is never popped");
+        root.location = SourceLocation::new(
+            self.file_id,
+            ByteIndex::from(0)..ByteIndex::from(self.source.len()),
+        );
+        SyntaxTree {
+            source: self.source,
+            root,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> SyntaxTree<'_> {
+        SyntaxTree::parse(FileId::new(0), source)
+    }
+
+    fn kinds(node: &SyntaxNode<'_>) -> Vec<SyntaxKind> {
+        node.child_nodes().map(SyntaxNode::kind).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        for name in &[
+            "tests/rms/Dry Arabia.rms",
+            "tests/rms/CM_Houseboat_v2.rms",
+            "tests/rms/comment-contents.rms",
+            "tests/rms/dead-branch-comment.rms",
+            "tests/rms/arg-types.rms",
+        ] {
+            let bytes = std::fs::read(name).unwrap();
+            let source = std::str::from_utf8(&bytes).unwrap();
+            assert_eq!(parse(source).to_string(), source, "{}", name);
+        }
+
+        for source in &[
+            "",
+            "   \r\n\t",
+            "endif } end_random percent_chance 10",
+            "if A create_object X { if B endif",
+            "create_object /* unclosed",
+            "  <PLAYER_SETUP>  random_placement  ",
+        ] {
+            assert_eq!(&parse(source).to_string(), source);
+        }
+    }
+
+    #[test]
+    fn sections() {
+        let tree = parse("#const X 1 <PLAYER_SETUP> random_placement <LAND_GENERATION>");
+        let root = tree.root();
+        assert_eq!(kinds(root), vec![SyntaxKind::Section, SyntaxKind::Section]);
+        let player_setup = root.child_nodes().next().unwrap();
+        assert_eq!(tree.text(player_setup), "<PLAYER_SETUP> random_placement");
+        assert_eq!(player_setup.child_atoms().count(), 2);
+    }
+
+    #[test]
+    fn blocks() {
+        let tree = parse("create_terrain SNOW /* comment */ { base_size 15 } { }");
+        let root = tree.root();
+        assert_eq!(kinds(root), vec![SyntaxKind::Block, SyntaxKind::Block]);
+        let mut blocks = root.child_nodes();
+        let block = blocks.next().unwrap();
+        assert_eq!(
+            tree.text(block),
+            "create_terrain SNOW /* comment */ { base_size 15 }"
+        );
+        assert!(block.command().is_some());
+        assert!(matches!(
+            block.tail().unwrap().kind,
+            AtomKind::CloseBlock { .. }
+        ));
+        let block = blocks.next().unwrap();
+        assert!(block.command().is_none());
+    }
+
+    #[test]
+    fn conditionals() {
+        let tree = parse("if A #define X elseif B if C endif else create_object Y { } endif");
+        let root = tree.root();
+        assert_eq!(kinds(root), vec![SyntaxKind::If]);
+        let cond = root.child_nodes().next().unwrap();
+        assert_eq!(cond.branches().count(), 3);
+        assert!(matches!(cond.head().unwrap().kind, AtomKind::If { .. }));
+        assert!(matches!(cond.tail().unwrap().kind, AtomKind::EndIf { .. }));
+        let branches: Vec<_> = cond.branches().collect();
+        assert_eq!(kinds(branches[1]), vec![SyntaxKind::If]);
+        assert_eq!(kinds(branches[2]), vec![SyntaxKind::Block]);
+    }
+
+    #[test]
+    fn random() {
+        let tree = parse(
+            "start_random percent_chance 30 #define A percent_chance 70 start_random end_random end_random",
+        );
+        let root = tree.root();
+        assert_eq!(kinds(root), vec![SyntaxKind::Random]);
+        let random = root.child_nodes().next().unwrap();
+        assert!(random.tail().is_some());
+        let branches: Vec<_> = random.branches().collect();
+        assert_eq!(branches.len(), 2);
+        assert_eq!(tree.text(branches[0]), "percent_chance 30 #define A");
+        assert_eq!(kinds(branches[1]), vec![SyntaxKind::Random]);
+    }
+
+    #[test]
+    fn unbalanced() {
+        // The `endif` can not close the `if` while the block is still open.
+        let tree = parse("if A create_object X { endif }");
+        let cond = tree.root().child_nodes().next().unwrap();
+        assert_eq!(cond.kind(), SyntaxKind::If);
+        assert!(cond.tail().is_none());
+        let block = cond
+            .branches()
+            .next()
+            .unwrap()
+            .child_nodes()
+            .next()
+            .unwrap();
+        assert_eq!(block.kind(), SyntaxKind::Block);
+        assert_eq!(block.atoms().len(), 4);
+    }
+
+    #[test]
+    fn node_at() {
+        let source = "<OBJECTS_GENERATION> create_object X { number_of_objects 1 }";
+        let tree = parse(source);
+        let index = ByteIndex::from(source.find("number_of_objects").unwrap());
+        let node = tree.root().node_at(index).unwrap();
+        assert_eq!(node.kind(), SyntaxKind::Block);
+        let node = tree.root().node_at(ByteIndex::from(0)).unwrap();
+        assert_eq!(node.kind(), SyntaxKind::Section);
+    }
+}
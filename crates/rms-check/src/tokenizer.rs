@@ -29,16 +29,25 @@ impl<'a> Word<'a> {
 pub struct Tokenizer<'a> {
     file: FileId,
     source: &'a str,
+    /// The index in `source` where `chars` starts.
+    offset: usize,
     chars: CharIndices<'a>,
 }
 
 impl<'a> Tokenizer<'a> {
     /// Create an iterator over the `source` string's words.
     pub fn new(file_id: FileId, source: &'a str) -> Self {
+        Self::new_at(file_id, source, ByteIndex::from(0))
+    }
+
+    /// Create an iterator over the `source` string's words, starting at the given index.
+    pub fn new_at(file_id: FileId, source: &'a str, offset: ByteIndex) -> Self {
+        let offset = usize::from(offset);
         Tokenizer {
             file: file_id,
             source,
-            chars: source.char_indices(),
+            offset,
+            chars: source[offset..].char_indices(),
         }
     }
 }
@@ -51,8 +60,8 @@ impl<'a> Iterator for Tokenizer<'a> {
         let mut start = ByteIndex::from(0);
         let mut end = ByteIndex::from(self.source.len());
         let mut saw_word = false;
-        while let Some((index, c)) = self.chars.next() {
-            let index = ByteIndex::from(index);
+        for (index, c) in self.chars.by_ref() {
+            let index = ByteIndex::from(self.offset + index);
             if !saw_word {
                 if !c.is_ascii_whitespace() {
                     saw_word = true;
@@ -117,4 +126,18 @@ mod tests {
         assert_eq!(word.start(), ByteIndex::from(11));
         assert_eq!(word.end(), ByteIndex::from(21));
     }
+
+    #[test]
+    fn split_words_from_offset() {
+        let (file_id, source) = file("simple test words");
+        let mut tokenizer = Tokenizer::new_at(file_id, source, ByteIndex::from(6));
+        let word = tokenizer.next().unwrap();
+        assert_eq!(word.value, "test");
+        assert_eq!(word.start(), ByteIndex::from(7));
+        assert_eq!(word.end(), ByteIndex::from(11));
+        let word = tokenizer.next().unwrap();
+        assert_eq!(word.value, "words");
+        assert_eq!(word.end(), ByteIndex::from(17));
+        assert!(tokenizer.next().is_none());
+    }
 }
@@ -1,8 +1,20 @@
+//! The token database, describing the commands and attributes that can appear in scripts.
+//!
+//! The builtin database is read from `tokens.toml`. More tokens can be loaded at runtime using
+//! `extend_tokens()`.
+
+use crate::{Compatibility, Value};
 use lazy_static::lazy_static;
+use serde::Deserialize;
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+use std::ops::RangeInclusive;
+use std::sync::Mutex;
 
 /// Argument type.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum ArgType {
     /// A literal string (no spaces)
     Word = 1,
@@ -16,6 +28,37 @@ pub enum ArgType {
     Filename = 5,
 }
 
+/// The kind of thing that a #const refers to.
+///
+/// Builtin #consts are categorised by the section of random_map.def that they are defined in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TokenCategory {
+    /// A terrain type, like `GRASS`.
+    Terrain,
+    /// An object or unit type, like `GOLD` or `SCOUT`.
+    Object,
+    /// A random map type for the AI, like `ARABIA`.
+    MapType,
+    /// A UserPatch effect, like `SET_ATTRIBUTE`.
+    Effect,
+    /// A UserPatch effect attribute or modifier, like `ATTR_HITPOINTS` or `AMOUNT_GOLD`.
+    Attribute,
+}
+
+impl TokenCategory {
+    /// Get a human readable description of this category, with an article.
+    pub const fn describe(self) -> &'static str {
+        match self {
+            TokenCategory::Terrain => "a terrain",
+            TokenCategory::Object => "an object",
+            TokenCategory::MapType => "a map type",
+            TokenCategory::Effect => "an effect",
+            TokenCategory::Attribute => "an effect attribute",
+        }
+    }
+}
+
 /// Defines where a token can appear.
 #[derive(Debug, Clone, Copy)]
 pub enum TokenContext {
@@ -33,8 +76,126 @@ pub enum TokenContext {
     AnyOf(&'static [TokenContext]),
 }
 
-/// A list of token argument types (up to 4).
-pub type TokenArgTypes = [Option<ArgType>; 4];
+/// The valid values of an argument.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArgRange {
+    /// Any number in a range.
+    Between(RangeInclusive<i32>),
+    /// One of a list of numbers.
+    OneOf(Vec<i32>),
+    /// A combination of the bit flags in a mask.
+    Flags(i32),
+}
+
+impl ArgRange {
+    /// Are all possible values of `value` valid?
+    pub fn contains(&self, value: Value) -> bool {
+        let values = value.min()..=value.max();
+        match self {
+            ArgRange::Between(range) => value.is_within(range.clone()),
+            ArgRange::OneOf(list) => {
+                // Avoid iterating over huge `rnd()` ranges that can not fit in the list anyway.
+                (value.max() as i64 - value.min() as i64) < list.len() as i64
+                    && values.into_iter().all(|n| list.contains(&n))
+            }
+            ArgRange::Flags(mask) => {
+                value.min() >= 0
+                    && value.max() <= *mask
+                    && values.into_iter().all(|n| n & !mask == 0)
+            }
+        }
+    }
+}
+
+/// Joins a list of numbers like "1, 2 or 3".
+fn join_numbers(f: &mut fmt::Formatter<'_>, numbers: &[i32], last_separator: &str) -> fmt::Result {
+    for (i, number) in numbers.iter().enumerate() {
+        if i > 0 {
+            let separator = if i == numbers.len() - 1 {
+                last_separator
+            } else {
+                ", "
+            };
+            f.write_str(separator)?;
+        }
+        write!(f, "{}", number)?;
+    }
+    Ok(())
+}
+
+impl Display for ArgRange {
+    /// Describe the valid values, so that it can follow "must be".
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgRange::Between(range) if *range.start() == i32::MIN => {
+                write!(f, "at most {}", range.end())
+            }
+            ArgRange::Between(range) if *range.end() == i32::MAX => {
+                write!(f, "at least {}", range.start())
+            }
+            ArgRange::Between(range) => write!(f, "{}-{}", range.start(), range.end()),
+            ArgRange::OneOf(list) => join_numbers(f, list, " or "),
+            ArgRange::Flags(mask) => {
+                let flags: Vec<_> = (0..31)
+                    .map(|bit| 1 << bit)
+                    .filter(|flag| mask & flag != 0)
+                    .collect();
+                f.write_str("a combination of the flags ")?;
+                join_numbers(f, &flags, " and ")
+            }
+        }
+    }
+}
+
+/// Describes an argument of a token.
+#[derive(Debug, Clone)]
+pub struct TokenArg {
+    /// The type of the argument.
+    arg_type: ArgType,
+    /// The category of #const that a `Token` argument expects, if it is restricted.
+    category: Option<TokenCategory>,
+    /// The valid values for this argument, if it is restricted.
+    range: Option<ArgRange>,
+    /// The warning for values outside of `range`, if it is not the default one.
+    invalid_message: Option<String>,
+    /// The name of the argument, for documentation.
+    name: Option<String>,
+    /// Documentation for the argument.
+    documentation: Option<String>,
+}
+
+impl TokenArg {
+    /// Get the type of this argument.
+    pub const fn arg_type(&self) -> ArgType {
+        self.arg_type
+    }
+
+    /// Get the category of #const expected by this argument, if it is restricted.
+    pub const fn category(&self) -> Option<TokenCategory> {
+        self.category
+    }
+
+    /// Get the valid values for this argument, if it is restricted.
+    pub const fn range(&self) -> Option<&ArgRange> {
+        self.range.as_ref()
+    }
+
+    /// Get the warning for values outside of the valid range, if the argument has a custom one.
+    pub fn invalid_message(&self) -> Option<&str> {
+        self.invalid_message.as_deref()
+    }
+
+    /// Get the name of this argument, if it is documented.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Get the documentation for this argument.
+    pub fn documentation(&self) -> Option<&str> {
+        self.documentation.as_deref()
+    }
+}
+
 /// Describes some characteristic of a token.
 #[derive(Debug, Clone)]
 pub struct TokenType {
@@ -42,18 +203,82 @@ pub struct TokenType {
     pub name: &'static str,
     /// The context where the token may appear.
     context: TokenContext,
-    /// The argument types for this token.
-    arg_types: TokenArgTypes,
+    /// The arguments for this token (up to 4).
+    args: Vec<TokenArg>,
+    /// The number of arguments accepted before the Definitive Edition, if it added more. The
+    /// arguments added by the Definitive Edition are optional.
+    legacy_arg_len: Option<u8>,
+    /// The compatibility level that introduced this token.
+    since: Compatibility,
+    /// The warning for scripts that use this token without the compatibility level that introduced
+    /// it, if it differs from the default.
+    since_message: Option<String>,
+    /// The compatibility level that deprecated this token, and an explanation.
+    deprecated: Option<(Compatibility, String)>,
+    /// Documentation for the token.
+    description: Option<String>,
+    /// The attributes that a block started by this token must contain. At least one attribute in
+    /// each list must be used.
+    required_attributes: Vec<Vec<String>>,
 }
 impl TokenType {
     /// Get the type of the `n`th argument.
-    pub const fn arg_type(&self, n: u8) -> &Option<ArgType> {
-        &self.arg_types[n as usize]
+    pub fn arg_type(&self, n: u8) -> Option<ArgType> {
+        self.args.get(n as usize).map(TokenArg::arg_type)
     }
 
-    /// Get the number of arguments required by this token type.
+    /// Get the category of #const expected by the `n`th argument, if it is restricted.
+    pub fn arg_category(&self, n: u8) -> Option<TokenCategory> {
+        self.args.get(n as usize)?.category()
+    }
+
+    /// Get the arguments of this token type.
+    pub fn args(&self) -> &[TokenArg] {
+        &self.args
+    }
+
+    /// Get the number of arguments accepted by this token type.
     pub fn arg_len(&self) -> u8 {
-        self.arg_types.iter().position(Option::is_none).unwrap_or(4) as u8
+        self.args.len() as u8
+    }
+
+    /// Get the number of arguments required by this token type.
+    pub fn min_arg_len(&self) -> u8 {
+        self.legacy_arg_len.unwrap_or_else(|| self.arg_len())
+    }
+
+    /// Get the number of arguments accepted by this token type before the Definitive Edition.
+    pub const fn legacy_arg_len(&self) -> Option<u8> {
+        self.legacy_arg_len
+    }
+
+    /// Get the compatibility level that introduced this token.
+    pub const fn since(&self) -> Compatibility {
+        self.since
+    }
+
+    /// Get the warning for scripts that use this token without the compatibility level that
+    /// introduced it, if it differs from the default.
+    pub fn since_message(&self) -> Option<&str> {
+        self.since_message.as_deref()
+    }
+
+    /// Get the compatibility level that deprecated this token, and an explanation.
+    pub fn deprecated(&self) -> Option<(Compatibility, &str)> {
+        self.deprecated
+            .as_ref()
+            .map(|(compatibility, reason)| (*compatibility, reason.as_str()))
+    }
+
+    /// Get the documentation for this token.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// Get the attributes that a block started by this token must contain. Each entry lists
+    /// alternatives, at least one of which must be used.
+    pub fn required_attributes(&self) -> &[Vec<String>] {
+        &self.required_attributes
     }
 
     /// Get the context for this type, describing where it can appear.
@@ -65,249 +290,410 @@ impl TokenType {
 /// A map holding token types, indexed by their name.
 type TokenMap = HashMap<String, TokenType>;
 
-/// Utility for initialising a TokenMap, without having to repeat the token names all the time.
-struct TokenMapBuilder(TokenMap);
-impl TokenMapBuilder {
-    /// Initialise a TokenMap builder.
-    fn new() -> Self {
-        TokenMapBuilder(TokenMap::new())
+/// The builtin token database.
+const BUILTIN_TOKENS: &str = include_str!("tokens.toml");
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TokenFile {
+    #[serde(default)]
+    token: Vec<TokenDefinition>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TokenDefinition {
+    name: String,
+    context: Vec<String>,
+    since: Option<String>,
+    since_message: Option<String>,
+    deprecated: Option<DeprecationDefinition>,
+    legacy_args: Option<u8>,
+    description: Option<String>,
+    #[serde(default)]
+    requires: Vec<Vec<String>>,
+    #[serde(default)]
+    args: Vec<ArgDefinition>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DeprecationDefinition {
+    since: String,
+    reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ArgDefinition {
+    #[serde(rename = "type")]
+    arg_type: ArgType,
+    category: Option<TokenCategory>,
+    min: Option<i32>,
+    max: Option<i32>,
+    #[serde(default)]
+    percent: bool,
+    values: Option<Vec<i32>>,
+    flags: Option<i32>,
+    message: Option<String>,
+    name: Option<String>,
+    doc: Option<String>,
+}
+
+/// An error that occurred while loading a token database.
+#[derive(Debug)]
+pub enum TokenDatabaseError {
+    /// The file is not valid TOML, or has unexpected keys.
+    Toml(toml::de::Error),
+    /// A token has a context that is not recognised.
+    InvalidContext(String, String),
+    /// A token refers to an unknown compatibility level.
+    UnknownCompatibility(String, String),
+    /// A token has more than 4 arguments.
+    TooManyArguments(String),
+    /// A token has an argument with more than one kind of range.
+    ConflictingRanges(String),
+    /// A token has an empty list of required attributes, which can never be satisfied.
+    EmptyRequirement(String),
+    /// The token database was already initialised, so it can no longer be extended.
+    AlreadyLoaded,
+}
+
+impl Display for TokenDatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Toml(err) => write!(f, "{}", err),
+            Self::InvalidContext(token, context) => {
+                write!(f, "`{}`: invalid context `{}`", token, context)
+            }
+            Self::UnknownCompatibility(token, name) => {
+                write!(f, "`{}`: unknown compatibility `{}`", token, name)
+            }
+            Self::TooManyArguments(token) => {
+                write!(f, "`{}`: tokens can have at most 4 arguments", token)
+            }
+            Self::ConflictingRanges(token) => write!(
+                f,
+                "`{}`: arguments can only have one of `min`/`max`, `percent`, `values` and `flags`",
+                token
+            ),
+            Self::EmptyRequirement(token) => {
+                write!(f, "`{}`: required attribute lists can not be empty", token)
+            }
+            Self::AlreadyLoaded => write!(f, "the token database is already in use"),
+        }
     }
+}
+
+impl std::error::Error for TokenDatabaseError {}
+
+/// Leak a string, for data that lives as long as the token database.
+fn leak(string: &str) -> &'static str {
+    Box::leak(string.to_string().into_boxed_str())
+}
 
-    /// Add a new token type to the map.
-    fn insert(&mut self, t: TokenType) {
-        self.0.insert(t.name.into(), t);
+/// Parse a single context, like `command <PLAYER_SETUP>` or `attribute create_object`.
+fn parse_context(context: &str) -> Option<TokenContext> {
+    let mut parts = context.split_whitespace();
+    let kind = parts.next()?;
+    let restriction = parts.next();
+    if parts.next().is_some() {
+        return None;
     }
+    Some(match (kind, restriction) {
+        ("flow", None) => TokenContext::Flow,
+        ("section", None) => TokenContext::Section,
+        ("command", restriction) => TokenContext::Command(restriction.map(leak)),
+        ("top-level", restriction) => TokenContext::TopLevelAttribute(restriction.map(leak)),
+        ("attribute", restriction) => TokenContext::Attribute(restriction.map(leak)),
+        _ => return None,
+    })
+}
+
+/// Parse a compatibility level for the token `token`.
+fn parse_compatibility(token: &str, name: &str) -> Result<Compatibility, TokenDatabaseError> {
+    name.parse()
+        .map_err(|_| TokenDatabaseError::UnknownCompatibility(token.to_string(), name.to_string()))
+}
 
-    /// Finish the TokenMap.
-    #[allow(clippy::missing_const_for_fn)] // false positive
-    fn build(self) -> TokenMap {
-        self.0
+/// Parse the range of valid values for an argument of the token `token`.
+fn parse_range(token: &str, arg: &ArgDefinition) -> Result<Option<ArgRange>, TokenDatabaseError> {
+    let mut ranges = vec![];
+    if arg.min.is_some() || arg.max.is_some() {
+        ranges.push(ArgRange::Between(
+            arg.min.unwrap_or(i32::MIN)..=arg.max.unwrap_or(i32::MAX),
+        ));
+    }
+    if arg.percent {
+        ranges.push(ArgRange::Between(0..=100));
+    }
+    if let Some(values) = &arg.values {
+        ranges.push(ArgRange::OneOf(values.clone()));
+    }
+    if let Some(mask) = arg.flags {
+        ranges.push(ArgRange::Flags(mask));
+    }
+    if ranges.len() > 1 {
+        return Err(TokenDatabaseError::ConflictingRanges(token.to_string()));
     }
+    Ok(ranges.pop())
 }
 
-/// Terser syntax for creating token types.
-macro_rules! token {
-    ( $name:expr, $context:expr ) => {
-        TokenType {
-            name: $name,
-            context: $context,
-            arg_types: [None, None, None, None],
-        }
-    };
-    ( $name:expr, $context:expr, [ $arg1:ident ] ) => {
-        TokenType {
-            name: $name,
-            context: $context,
-            arg_types: [Some(ArgType::$arg1), None, None, None],
-        }
-    };
-    ( $name:expr, $context:expr, [ $arg1:ident, $arg2:ident ] ) => {
-        TokenType {
-            name: $name,
-            context: $context,
-            arg_types: [Some(ArgType::$arg1), Some(ArgType::$arg2), None, None],
-        }
-    };
-    ( $name:expr, $context:expr, [ $arg1:ident, $arg2:ident, $arg3:ident ] ) => {
-        TokenType {
-            name: $name,
-            context: $context,
-            arg_types: [
-                Some(ArgType::$arg1),
-                Some(ArgType::$arg2),
-                Some(ArgType::$arg3),
-                None,
-            ],
+impl TryFrom<TokenDefinition> for TokenType {
+    type Error = TokenDatabaseError;
+
+    fn try_from(definition: TokenDefinition) -> Result<Self, Self::Error> {
+        let TokenDefinition {
+            name,
+            context,
+            since,
+            since_message,
+            deprecated,
+            legacy_args,
+            description,
+            requires,
+            args,
+        } = definition;
+
+        if args.len() > 4 {
+            return Err(TokenDatabaseError::TooManyArguments(name));
         }
-    };
-    ( $name:expr, $context:expr, [ $arg1:ident, $arg2:ident, $arg3:ident, $arg4:ident ] ) => {
-        TokenType {
-            name: $name,
-            context: $context,
-            arg_types: [
-                Some(ArgType::$arg1),
-                Some(ArgType::$arg2),
-                Some(ArgType::$arg3),
-                Some(ArgType::$arg4),
-            ],
+        if requires.iter().any(Vec::is_empty) {
+            return Err(TokenDatabaseError::EmptyRequirement(name));
         }
-    };
+        let contexts = context
+            .iter()
+            .map(|context| {
+                parse_context(context).ok_or_else(|| {
+                    TokenDatabaseError::InvalidContext(name.clone(), context.clone())
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let context = match contexts.as_slice() {
+            [context] => *context,
+            _ => TokenContext::AnyOf(Box::leak(contexts.into_boxed_slice())),
+        };
+        let since = match since {
+            Some(since) => parse_compatibility(&name, &since)?,
+            None => Compatibility::Conquerors,
+        };
+        let deprecated = match deprecated {
+            Some(DeprecationDefinition { since, reason }) => {
+                Some((parse_compatibility(&name, &since)?, reason))
+            }
+            None => None,
+        };
+        let args = args
+            .into_iter()
+            .map(|arg| {
+                Ok(TokenArg {
+                    arg_type: arg.arg_type,
+                    category: arg.category,
+                    range: parse_range(&name, &arg)?,
+                    invalid_message: arg.message,
+                    name: arg.name,
+                    documentation: arg.doc,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            name: leak(&name),
+            context,
+            args,
+            legacy_arg_len: legacy_args,
+            since,
+            since_message,
+            deprecated,
+            description,
+            required_attributes: requires,
+        })
+    }
+}
+
+/// Parse a token database.
+fn parse_tokens(source: &str) -> Result<TokenMap, TokenDatabaseError> {
+    let file: TokenFile = toml::from_str(source).map_err(TokenDatabaseError::Toml)?;
+    file.token
+        .into_iter()
+        .map(|definition| {
+            let token_type = TokenType::try_from(definition)?;
+            Ok((token_type.name.to_string(), token_type))
+        })
+        .collect()
 }
 
 lazy_static! {
+    /// Token databases added using `extend_tokens()`. This is `None` once `TOKENS` is initialised.
+    static ref EXTRA_TOKENS: Mutex<Option<Vec<TokenMap>>> = Mutex::new(Some(vec![]));
+
     /// All known tokens.
     pub static ref TOKENS: HashMap<String, TokenType> = {
-        let mut m = TokenMapBuilder::new();
-        m.insert(token!("#define", TokenContext::Flow, [Word]));
-        m.insert(token!("#undefine", TokenContext::Flow, [Word]));
-        m.insert(token!("#const", TokenContext::Flow, [Word, Number]));
-
-        m.insert(token!("if", TokenContext::Flow, [OptionalToken]));
-        m.insert(token!("elseif", TokenContext::Flow, [OptionalToken]));
-        m.insert(token!("else", TokenContext::Flow));
-        m.insert(token!("endif", TokenContext::Flow));
-
-        m.insert(token!("start_random", TokenContext::Flow));
-        m.insert(token!("percent_chance", TokenContext::Flow, [Number]));
-        m.insert(token!("end_random", TokenContext::Flow));
-
-        m.insert(token!("#include", TokenContext::Flow, [Filename]));
-        m.insert(token!("#include_drs", TokenContext::Flow, [Filename, Number]));
-
-        m.insert(token!("<PLAYER_SETUP>", TokenContext::Section));
-        m.insert(token!("<LAND_GENERATION>", TokenContext::Section));
-        m.insert(token!("<ELEVATION_GENERATION>", TokenContext::Section));
-        m.insert(token!("<TERRAIN_GENERATION>", TokenContext::Section));
-        m.insert(token!("<CLIFF_GENERATION>", TokenContext::Section));
-        m.insert(token!("<OBJECTS_GENERATION>", TokenContext::Section));
-        m.insert(token!("<CONNECTION_GENERATION>", TokenContext::Section));
-
-        m.insert(token!("color_correction", TokenContext::TopLevelAttribute(None), [Token]));
-        m.insert(token!("ai_info_map_type", TokenContext::TopLevelAttribute(Some("<PLAYER_SETUP>")), [Token, Number, Number, Number]));
-        m.insert(token!("random_placement", TokenContext::TopLevelAttribute(Some("<PLAYER_SETUP>"))));
-        m.insert(token!("direct_placement", TokenContext::TopLevelAttribute(Some("<PLAYER_SETUP>"))));
-        m.insert(token!("circle_placement", TokenContext::TopLevelAttribute(Some("<PLAYER_SETUP>"))));
-        m.insert(token!("circle_radius", TokenContext::TopLevelAttribute(Some("<PLAYER_SETUP>")), [Number]));
-        m.insert(token!("nomad_resources", TokenContext::TopLevelAttribute(Some("<PLAYER_SETUP>"))));
-        m.insert(token!("grouped_by_team", TokenContext::TopLevelAttribute(Some("<PLAYER_SETUP>"))));
-        m.insert(token!("effect_amount", TokenContext::TopLevelAttribute(Some("<PLAYER_SETUP>")), [Token, Token, Token, Number]));
-        m.insert(token!("effect_percent", TokenContext::TopLevelAttribute(Some("<PLAYER_SETUP>")), [Token, Token, Token, Number]));
-        m.insert(token!("terrain_state", TokenContext::TopLevelAttribute(Some("<PLAYER_SETUP>")), [Number, Number, Number, Number]));
-        m.insert(token!("weather_type", TokenContext::TopLevelAttribute(Some("<PLAYER_SETUP>")), [Number, Number, Number, Number]));
-        m.insert(token!("guard_state", TokenContext::TopLevelAttribute(Some("<PLAYER_SETUP>")), [Token, Token, Number, Number]));
-        m.insert(token!("enable_waves", TokenContext::TopLevelAttribute(Some("<PLAYER_SETUP>")), [Number]));
-        m.insert(token!("terrain_mask", TokenContext::TopLevelAttribute(Some("<PLAYER_SETUP>")), [Number]));
-        m.insert(token!("set_gaia_civilization", TokenContext::TopLevelAttribute(None), [Number])); // does this need <PLAYER_SETUP>?
-
-        let land_attribute_context = TokenContext::AnyOf(&[
-           TokenContext::Attribute(Some("create_land")),
-           TokenContext::Attribute(Some("create_player_lands")),
-        ]);
-
-        m.insert(token!("create_land", TokenContext::Command(Some("<LAND_GENERATION>"))));
-        m.insert(token!("create_player_lands", TokenContext::Command(Some("<LAND_GENERATION>"))));
-        m.insert(token!("land_percent", land_attribute_context, [Number]));
-        m.insert(token!("land_position", land_attribute_context, [Number, Number]));
-        m.insert(token!("land_id", land_attribute_context, [Number]));
-        m.insert(token!("terrain_type", TokenContext::AnyOf(&[
-           TokenContext::Attribute(Some("create_land")),
-           TokenContext::Attribute(Some("create_player_lands")),
-           TokenContext::Attribute(Some("create_terrain")),
-        ]), [Token]));
-        m.insert(token!("base_size", land_attribute_context, [Number]));
-        m.insert(token!("base_elevation", land_attribute_context, [Number]));
-        m.insert(token!("left_border", land_attribute_context, [Number]));
-        m.insert(token!("right_border", land_attribute_context, [Number]));
-        m.insert(token!("top_border", land_attribute_context, [Number]));
-        m.insert(token!("bottom_border", land_attribute_context, [Number]));
-        m.insert(token!("border_fuzziness", land_attribute_context, [Number]));
-        m.insert(token!("zone", land_attribute_context, [Number]));
-        m.insert(token!("set_zone_by_team", land_attribute_context));
-        m.insert(token!("set_zone_randomly", land_attribute_context));
-        m.insert(token!("other_zone_avoidance_distance", land_attribute_context, [Number]));
-        m.insert(token!("assign_to_player", TokenContext::Attribute(Some("create_land")), [Number]));
-        m.insert(token!("assign_to", TokenContext::Attribute(Some("create_land")), [Token, Number, Number, Number]));
-
-        m.insert(token!("base_terrain", TokenContext::AnyOf(&[
-            TokenContext::TopLevelAttribute(Some("<LAND_GENERATION>")),
-            TokenContext::Attribute(Some("create_land")),
-            TokenContext::Attribute(Some("create_player_lands")),
-            TokenContext::Attribute(Some("create_elevation")),
-            TokenContext::Attribute(Some("create_terrain")),
-            TokenContext::Attribute(Some("create_object")),
-        ]), [Token]));
-
-        m.insert(token!("min_number_of_cliffs", TokenContext::TopLevelAttribute(Some("<CLIFF_GENERATION>")), [Number]));
-        m.insert(token!("max_number_of_cliffs", TokenContext::TopLevelAttribute(Some("<CLIFF_GENERATION>")), [Number]));
-        m.insert(token!("min_length_of_cliff", TokenContext::TopLevelAttribute(Some("<CLIFF_GENERATION>")), [Number]));
-        m.insert(token!("max_length_of_cliff", TokenContext::TopLevelAttribute(Some("<CLIFF_GENERATION>")), [Number]));
-        m.insert(token!("cliff_curliness", TokenContext::TopLevelAttribute(Some("<CLIFF_GENERATION>")), [Number]));
-        m.insert(token!("min_distance_cliffs", TokenContext::TopLevelAttribute(Some("<CLIFF_GENERATION>")), [Number]));
-        m.insert(token!("min_terrain_distance", TokenContext::TopLevelAttribute(Some("<CLIFF_GENERATION>")), [Number]));
-
-        m.insert(token!("create_terrain", TokenContext::Command(Some("<TERRAIN_GENERATION>")), [Token]));
-        m.insert(token!("percent_of_land", TokenContext::Attribute(Some("create_terrain")), [Number]));
-        m.insert(token!("number_of_tiles", TokenContext::AnyOf(&[
-             TokenContext::Attribute(Some("create_terrain")),
-             TokenContext::Attribute(Some("create_elevation")),
-        ]), [Number]));
-        m.insert(token!("number_of_clumps", TokenContext::AnyOf(&[
-             TokenContext::Attribute(Some("create_terrain")),
-             TokenContext::Attribute(Some("create_elevation")),
-        ]), [Number]));
-        m.insert(token!("set_scale_by_groups", TokenContext::AnyOf(&[
-             TokenContext::Attribute(Some("create_terrain")),
-             TokenContext::Attribute(Some("create_elevation")),
-        ])));
-        m.insert(token!("set_scale_by_size", TokenContext::AnyOf(&[
-             TokenContext::Attribute(Some("create_terrain")),
-             TokenContext::Attribute(Some("create_elevation")),
-        ])));
-        m.insert(token!("spacing_to_other_terrain_types", TokenContext::Attribute(Some("create_terrain")), [Number]));
-        m.insert(token!("height_limits", TokenContext::Attribute(Some("create_terrain")), [Number, Number]));
-        m.insert(token!("set_flat_terrain_only", TokenContext::Attribute(Some("create_terrain"))));
-        m.insert(token!("set_avoid_player_start_areas", TokenContext::Attribute(Some("create_terrain"))));
-        m.insert(token!("clumping_factor", TokenContext::Attribute(Some("create_terrain")), [Number]));
-        m.insert(token!("base_layer", TokenContext::Attribute(Some("create_terrain")), [Token]));
-
-        m.insert(token!("create_object", TokenContext::Command(Some("<OBJECTS_GENERATION>")), [Token]));
-        let create_object = TokenContext::Attribute(Some("create_object"));
-        m.insert(token!("set_scaling_to_map_size", create_object));
-        m.insert(token!("set_scaling_to_player_number", create_object));
-        m.insert(token!("number_of_groups", create_object, [Number]));
-        m.insert(token!("number_of_objects", create_object, [Number]));
-        m.insert(token!("group_variance", create_object, [Number]));
-        m.insert(token!("group_placement_radius", create_object, [Number]));
-        m.insert(token!("set_loose_grouping", create_object));
-        m.insert(token!("set_tight_grouping", create_object));
-        m.insert(token!("terrain_to_place_on", create_object, [Token]));
-        m.insert(token!("layer_to_place_on", create_object, [Token]));
-        m.insert(token!("set_gaia_object_only", create_object));
-        m.insert(token!("set_gaia_unconvertible", create_object));
-        m.insert(token!("set_place_for_every_player", create_object));
-        m.insert(token!("place_on_specific_land_id", create_object, [Number]));
-        m.insert(token!("min_distance_to_players", create_object, [Number]));
-        m.insert(token!("max_distance_to_players", create_object, [Number]));
-        m.insert(token!("max_distance_to_other_zones", create_object, [Number]));
-        m.insert(token!("min_distance_group_placement", create_object, [Number]));
-        m.insert(token!("temp_min_distance_group_placement", create_object, [Number]));
-        m.insert(token!("resource_delta", create_object, [Number]));
-        m.insert(token!("avoid_forest_zone", create_object, [Number]));
-        m.insert(token!("place_on_forest_zone", create_object));
-        m.insert(token!("avoid_cliff_zone", create_object, [Number]));
-        m.insert(token!("actor_area", create_object, [Number]));
-        m.insert(token!("actor_area_radius", create_object, [Number]));
-        m.insert(token!("actor_area_to_place_in", create_object, [Number]));
-        m.insert(token!("avoid_actor_area", create_object, [Number]));
-        m.insert(token!("avoid_all_actor_areas", create_object));
-        m.insert(token!("force_placement", create_object));
-        m.insert(token!("find_closest", create_object));
-        m.insert(token!("second_object", create_object, [Token]));
-
-        let connect_attribute_context = TokenContext::AnyOf(&[
-            TokenContext::Attribute(Some("create_connect_all_players_land")),
-            TokenContext::Attribute(Some("create_connect_teams_land")),
-            TokenContext::Attribute(Some("create_connect_same_land_zones")),
-            TokenContext::Attribute(Some("create_connect_all_lands")),
-        ]);
-
-        m.insert(token!("create_connect_all_players_land", TokenContext::Command(Some("<CONNECTION_GENERATION>"))));
-        m.insert(token!("create_connect_teams_lands", TokenContext::Command(Some("<CONNECTION_GENERATION>"))));
-        m.insert(token!("create_connect_same_land_zones", TokenContext::Command(Some("<CONNECTION_GENERATION>"))));
-        m.insert(token!("create_connect_all_lands", TokenContext::Command(Some("<CONNECTION_GENERATION>"))));
-        m.insert(token!("create_connect_to_nonplayer_land", TokenContext::Command(Some("<CONNECTION_GENERATION>"))));
-        m.insert(token!("replace_terrain", connect_attribute_context, [Token, Token]));
-        m.insert(token!("terrain_cost", connect_attribute_context, [Token, Number]));
-        m.insert(token!("terrain_size", connect_attribute_context, [Token, Number, Number]));
-        m.insert(token!("default_terrain_replacement", connect_attribute_context, [Token]));
-
-        m.insert(token!("create_elevation", TokenContext::Command(Some("<ELEVATION_GENERATION>")), [Number]));
-        m.insert(token!("spacing", TokenContext::Attribute(Some("create_elevation")), [Number]));
-        m.insert(token!("enable_balanced_elevation", TokenContext::Attribute(Some("enable_balanced_elevation"))));
-
-        m.insert(token!("effect_amount", TokenContext::Command(Some("<PLAYER_SETUP>")), [Token, Token, Token, Number]));
-        m.insert(token!("effect_percent", TokenContext::Command(Some("<PLAYER_SETUP>")), [Token, Token, Token, Number]));
-
-        m.build()
+        let mut tokens = parse_tokens(BUILTIN_TOKENS).expect("builtin token database is invalid");
+        let extra = EXTRA_TOKENS.lock().unwrap().take().unwrap_or_default();
+        for map in extra {
+            tokens.extend(map);
+        }
+        tokens
     };
 }
+
+/// Add the tokens from a token database file, in the same format as the builtin `tokens.toml`.
+/// Tokens replace builtin tokens with the same name.
+///
+/// This must be called before any scripts are checked: it returns an error once the token
+/// database is in use.
+pub fn extend_tokens(source: &str) -> Result<(), TokenDatabaseError> {
+    let map = parse_tokens(source)?;
+    match EXTRA_TOKENS.lock().unwrap().as_mut() {
+        Some(extra) => {
+            extra.push(map);
+            Ok(())
+        }
+        None => Err(TokenDatabaseError::AlreadyLoaded),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_tokens() {
+        let base_elevation = &TOKENS["base_elevation"];
+        assert_eq!(base_elevation.arg_type(0), Some(ArgType::Number));
+        assert_eq!(
+            base_elevation.args()[0].range(),
+            Some(&ArgRange::Between(0..=7))
+        );
+        assert_eq!(
+            base_elevation.args()[0].invalid_message(),
+            Some("Elevation value out of range (0 or 1-7)")
+        );
+        assert!(base_elevation.description().is_some());
+
+        let random_placement = &TOKENS["random_placement"];
+        assert!(matches!(
+            random_placement.deprecated(),
+            Some((Compatibility::DefinitiveEdition, _))
+        ));
+        assert_eq!(
+            TOKENS["effect_amount"].since_message(),
+            Some("RMS Effects require UserPatch 1.5 or Definitive Edition")
+        );
+        assert_eq!(TOKENS["guard_state"].since_message(), None);
+        assert!(matches!(
+            TOKENS["create_land"].context(),
+            TokenContext::Command(Some("<LAND_GENERATION>"))
+        ));
+        assert_eq!(
+            TOKENS["create_land"].required_attributes(),
+            &[vec![
+                "land_percent".to_string(),
+                "number_of_tiles".to_string()
+            ]]
+        );
+    }
+
+    #[test]
+    fn parse() {
+        let tokens = parse_tokens(
+            r#"
+            [[token]]
+            name = "new_attribute"
+            context = ["attribute create_object", "attribute create_actor_area"]
+            since = "de"
+            [[token.args]]
+            type = "token"
+            category = "terrain"
+            [[token.args]]
+            type = "number"
+            min = 1
+            "#,
+        )
+        .unwrap();
+        let token = &tokens["new_attribute"];
+        assert_eq!(token.since(), Compatibility::DefinitiveEdition);
+        assert!(matches!(
+            token.context(),
+            TokenContext::AnyOf([
+                TokenContext::Attribute(Some("create_object")),
+                TokenContext::Attribute(Some("create_actor_area"))
+            ])
+        ));
+        assert_eq!(token.arg_category(0), Some(TokenCategory::Terrain));
+        assert_eq!(
+            token.args()[1].range(),
+            Some(&ArgRange::Between(1..=i32::MAX))
+        );
+        assert_eq!(token.arg_type(2), None);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = parse_tokens("[[token]]\nname = \"x\"\ncontext = [\"block\"]\n").unwrap_err();
+        assert_eq!(error.to_string(), "`x`: invalid context `block`");
+        let error =
+            parse_tokens("[[token]]\nname = \"x\"\ncontext = [\"flow\"]\nsince = \"aok\"\n")
+                .unwrap_err();
+        assert_eq!(error.to_string(), "`x`: unknown compatibility `aok`");
+        assert!(matches!(
+            parse_tokens("[[token]]\nname = \"x\"\n"),
+            Err(TokenDatabaseError::Toml(_))
+        ));
+        let error =
+            parse_tokens("[[token]]\nname = \"x\"\ncontext = [\"command\"]\nrequires = [[]]\n")
+                .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "`x`: required attribute lists can not be empty"
+        );
+    }
+
+    #[test]
+    fn ranges() {
+        let between = ArgRange::Between(0..=7);
+        assert!(between.contains(Value::number(0)));
+        assert!(between.contains(Value::range(1, 7)));
+        assert!(!between.contains(Value::range(1, 8)));
+        assert_eq!(between.to_string(), "0-7");
+        assert_eq!(ArgRange::Between(1..=i32::MAX).to_string(), "at least 1");
+
+        let one_of = ArgRange::OneOf(vec![-1, 0]);
+        assert!(one_of.contains(Value::range(-1, 0)));
+        assert!(!one_of.contains(Value::number(1)));
+        assert!(!one_of.contains(Value::range(i32::MIN, i32::MAX)));
+        assert_eq!(one_of.to_string(), "-1 or 0");
+        assert_eq!(ArgRange::OneOf(vec![0, 1, 2]).to_string(), "0, 1 or 2");
+
+        let flags = ArgRange::Flags(1 | 4);
+        assert!(flags.contains(Value::number(5)));
+        assert!(flags.contains(Value::range(0, 1)));
+        assert!(!flags.contains(Value::number(2)));
+        assert!(!flags.contains(Value::number(-1)));
+        assert_eq!(flags.to_string(), "a combination of the flags 1 and 4");
+
+        let tokens = parse_tokens(
+            "[[token]]\nname = \"x\"\ncontext = [\"flow\"]\n[[token.args]]\ntype = \"number\"\npercent = true\n",
+        )
+        .unwrap();
+        assert_eq!(
+            tokens["x"].args()[0].range(),
+            Some(&ArgRange::Between(0..=100))
+        );
+        let error = parse_tokens(
+            "[[token]]\nname = \"x\"\ncontext = [\"flow\"]\n[[token.args]]\ntype = \"number\"\nmin = 0\nflags = 3\n",
+        )
+        .unwrap_err();
+        assert!(matches!(error, TokenDatabaseError::ConflictingRanges(_)));
+    }
+
+    #[test]
+    fn extend_after_load() {
+        assert!(!TOKENS.is_empty());
+        assert!(matches!(
+            extend_tokens("[[token]]\nname = \"x\"\ncontext = [\"flow\"]\n"),
+            Err(TokenDatabaseError::AlreadyLoaded)
+        ));
+    }
+}
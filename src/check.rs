@@ -1,38 +1,160 @@
 use crate::cli_reporter::report as cli_report;
+use crate::inputs::{expand_inputs, Input};
+use crate::output_format::{to_checkstyle, to_json, to_sarif, FileReport, OutputFormat};
 use anyhow::{bail, Result};
 use multisplice::Multisplice;
-use rms_check::{Compatibility, RMSCheck, RMSFile};
+use rayon::prelude::*;
+use rms_check::{
+    Compatibility, Config, ConfigurationSweep, DefinitionsMode, Diagnostic, Label, RMSCheck,
+    RMSFile,
+};
 use std::fs::{remove_file, write};
 use std::path::PathBuf;
 
 #[derive(Debug, Default)]
 pub struct CheckArgs {
-    /// Path to the RMS file.
-    pub file: PathBuf,
-    /// Compatibility level to use when checking the script.
-    pub compatibility: Compatibility,
+    /// Compatibility level to use when checking the script. Defaults to the setting from the
+    /// configuration file, or to `Compatibility::All`.
+    pub compatibility: Option<Compatibility>,
+    /// Paths to custom random_map.def files.
+    pub definitions: Vec<PathBuf>,
+    /// How to combine the custom random_map.def files with the builtin ones.
+    pub definitions_mode: DefinitionsMode,
     /// Do not a actually apply fixes.
     pub dry_run: bool,
+    /// How to print the diagnostics.
+    pub format: OutputFormat,
+    /// Also check every combination of lobby settings.
+    pub sweep: bool,
 }
 
-pub fn cli_check(args: CheckArgs) -> Result<()> {
-    let file = RMSFile::from_path(args.file)?;
-    let checker = RMSCheck::default().compatibility(args.compatibility);
-    let result = checker.check(&file);
-    let has_warnings = result.has_warnings();
+/// Load a script, with the custom random_map.def files from the arguments and the configuration
+/// file.
+pub fn load_file(input: &Input, args: &CheckArgs, config: &Config) -> Result<RMSFile<'static>> {
+    let mut file = input.load()?;
+    for (index, path) in args.definitions.iter().enumerate() {
+        // Only the first file overrides the builtins, the others are layered on top of it.
+        let mode = if index == 0 {
+            args.definitions_mode
+        } else {
+            DefinitionsMode::Layer
+        };
+        file.load_definitions_for_all(path, mode)?;
+    }
+    config.add_definitions(&mut file);
+    Ok(file)
+}
+
+/// Find the configuration file for the script.
+pub fn load_config(input: &Input) -> Result<Config> {
+    Ok(Config::find(input.path())?.unwrap_or_default())
+}
+
+/// Get the compatibility level to use: command line flags take precedence over the configuration
+/// file.
+pub fn compatibility(args: &CheckArgs, config: &Config) -> Compatibility {
+    args.compatibility
+        .or_else(|| config.compatibility())
+        .unwrap_or(Compatibility::All)
+}
+
+fn create_checker(args: &CheckArgs, config: &Config) -> RMSCheck {
+    RMSCheck::default()
+        .with_config(config)
+        .compatibility(compatibility(args, config))
+}
+
+/// Check a single script.
+fn check_input(input: &Input, args: &CheckArgs) -> Result<FileReport> {
+    let config = load_config(input)?;
+    let file = load_file(input, args, &config)?;
+    let mut diagnostics: Vec<Diagnostic> = create_checker(args, &config)
+        .check(&file)
+        .into_iter()
+        .collect();
+    if args.sweep {
+        let is_reported = |diagnostics: &[Diagnostic], diagnostic: &Diagnostic| {
+            diagnostics.iter().any(|reported| {
+                reported.location() == diagnostic.location()
+                    && reported.code() == diagnostic.code()
+                    && reported.message() == diagnostic.message()
+            })
+        };
+        let result = ConfigurationSweep::new(|| create_checker(args, &config)).check(&file);
+        if result.skipped() > 0 {
+            eprintln!(
+                "{}: checked {} of {} combinations of lobby settings",
+                input.path().display(),
+                result.configurations().len(),
+                result.configurations().len() + result.skipped(),
+            );
+        }
+        for sweep_diagnostic in result.iter() {
+            let diagnostic = sweep_diagnostic.diagnostic();
+            if is_reported(&diagnostics, diagnostic) {
+                continue;
+            }
+            let diagnostic = match sweep_diagnostic.condition() {
+                Some(condition) => diagnostic.clone().add_label(Label::new(
+                    diagnostic.location(),
+                    format!("Only reported with {}", condition),
+                )),
+                None => diagnostic.clone(),
+            };
+            diagnostics.push(diagnostic);
+        }
+    }
+    Ok(FileReport { file, diagnostics })
+}
+
+/// Check scripts, directories containing scripts, or glob patterns matching scripts.
+pub fn cli_check(paths: &[PathBuf], args: CheckArgs) -> Result<()> {
+    let inputs = expand_inputs(paths)?;
 
-    cli_report(&file, result);
+    let results: Vec<_> = inputs
+        .par_iter()
+        .map(|input| check_input(input, &args))
+        .collect();
 
+    let mut reports = vec![];
+    let mut num_failed = 0;
+    for (input, result) in inputs.iter().zip(results) {
+        match result {
+            Ok(report) => reports.push(report),
+            Err(err) => {
+                eprintln!("{}: {:#}", input.path().display(), err);
+                num_failed += 1;
+            }
+        }
+    }
+
+    let has_warnings = reports.iter().any(|report| !report.diagnostics.is_empty());
+    match args.format {
+        OutputFormat::Text => cli_report(&reports),
+        OutputFormat::Json => println!("{}", to_json(&reports)),
+        OutputFormat::Sarif => println!("{}", to_sarif(&reports)),
+        OutputFormat::Checkstyle => print!("{}", to_checkstyle(&reports)),
+    }
+
+    if num_failed > 0 {
+        bail!(
+            "{} of {} files could not be checked",
+            num_failed,
+            inputs.len()
+        );
+    }
     if has_warnings {
         bail!("There were warnings");
     }
     Ok(())
 }
 
-pub fn cli_fix(args: CheckArgs) -> Result<()> {
-    let file = RMSFile::from_path(&args.file)?;
+pub fn cli_fix(path: PathBuf, args: CheckArgs) -> Result<()> {
+    let input = Input::Script(path.clone());
+    let config = load_config(&input)?;
+    let file = load_file(&input, &args, &config)?;
 
-    let checker = RMSCheck::default().compatibility(args.compatibility);
+    let checker = create_checker(&args, &config);
     let result = checker.check(&file);
 
     let mut splicer = Multisplice::new(file.main_source());
@@ -71,19 +193,16 @@ pub fn cli_fix(args: CheckArgs) -> Result<()> {
     }
 
     if args.dry_run {
-        let temp = format!("{}.tmp", args.file.to_string_lossy());
-        write(&temp, &splicer.to_string())?;
-        let check_result = cli_check(CheckArgs {
-            file: temp.clone().into(),
-            ..args
-        });
+        let temp = PathBuf::from(format!("{}.tmp", path.to_string_lossy()));
+        write(&temp, splicer.to_string())?;
+        let check_result = cli_check(std::slice::from_ref(&temp), args);
         remove_file(&temp)?;
         check_result
     } else {
-        let backup = format!("{}.bak", args.file.to_string_lossy());
+        let backup = format!("{}.bak", path.to_string_lossy());
         write(&backup, file.main_source())?;
-        write(&args.file, &splicer.to_string())?;
+        write(&path, splicer.to_string())?;
         remove_file(&backup)?;
-        cli_check(args)
+        cli_check(&[path], args)
     }
 }
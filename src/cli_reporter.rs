@@ -1,9 +1,10 @@
+use crate::output_format::FileReport;
 use ansi_term::Colour::Cyan;
 use ansi_term::Style;
 use codespan_reporting::diagnostic::{Diagnostic, Label, LabelStyle, Severity};
 use codespan_reporting::files::{Error, Files};
 use codespan_reporting::term::{emit, Config};
-use rms_check::{ByteIndex, FileId, RMSCheckResult, RMSFile};
+use rms_check::{ByteIndex, FileId, RMSFile};
 use std::ops::Range;
 use termcolor::{ColorChoice, StandardStream};
 
@@ -44,7 +45,7 @@ impl<'a> Files<'a> for Adapter<'a> {
 }
 
 /// Print rms-check results to standard output.
-pub fn report(file: &RMSFile<'_>, result: RMSCheckResult) {
+pub fn report(reports: &[FileReport]) {
     let mut num_warnings = 0;
     let mut num_errors = 0;
     let mut fixable_warnings = 0;
@@ -82,12 +83,18 @@ pub fn report(file: &RMSFile<'_>, result: RMSCheckResult) {
 
     let config = Config::default();
     let mut stream = StandardStream::stdout(ColorChoice::Auto);
-    for diagnostic in result {
+    let all_diagnostics = reports.iter().flat_map(|report| {
+        report
+            .diagnostics
+            .iter()
+            .map(move |diagnostic| (&report.file, diagnostic))
+    });
+    for (file, diagnostic) in all_diagnostics {
         emit(
             &mut stream,
             &config,
             &Adapter(file),
-            &to_codespan_diagnostic(&diagnostic),
+            &to_codespan_diagnostic(diagnostic),
         )
         .unwrap();
 
@@ -120,6 +127,17 @@ pub fn report(file: &RMSFile<'_>, result: RMSCheckResult) {
     }
 
     println!();
+    if reports.len() > 1 {
+        let files_with_problems = reports
+            .iter()
+            .filter(|report| !report.diagnostics.is_empty())
+            .count();
+        println!(
+            "Checked {} files, {} with problems.",
+            reports.len(),
+            files_with_problems
+        );
+    }
     println!("{} errors, {} warnings found.", num_errors, num_warnings);
     if fixable_errors > 0 || fixable_warnings > 0 {
         println!(
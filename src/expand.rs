@@ -0,0 +1,50 @@
+use crate::check::{compatibility, load_config, load_file, CheckArgs};
+use crate::inputs::Input;
+use anyhow::{anyhow, Result};
+use rms_check::{expand, ExpandOptions};
+use std::path::PathBuf;
+
+#[derive(Debug, Default)]
+pub struct ExpandArgs {
+    /// Compatibility level and custom random_map.def files.
+    pub check: CheckArgs,
+    /// `#define` names to treat as set.
+    pub defines: Vec<String>,
+    /// The 1-based branch to take in each `start_random` block, in source order.
+    pub random: Vec<usize>,
+    /// The 1-based branch to take in `start_random` blocks that are not listed in `random`.
+    pub default_random: usize,
+}
+
+/// Convert a 1-based branch number from the command line to a 0-based index.
+fn branch_index(branch: usize) -> Result<usize> {
+    branch
+        .checked_sub(1)
+        .ok_or_else(|| anyhow!("Random branches are numbered starting at 1"))
+}
+
+/// Print the script the game would interpret for a particular set of game settings.
+pub fn cli_expand(path: PathBuf, args: ExpandArgs) -> Result<()> {
+    let input = Input::Script(path);
+    let config = load_config(&input)?;
+    let file = load_file(&input, &args.check, &config)?;
+
+    let mut options = ExpandOptions::default()
+        .compatibility(compatibility(&args.check, &config))
+        .random_choices(
+            args.random
+                .into_iter()
+                .map(branch_index)
+                .collect::<Result<_>>()?,
+        )
+        .default_random_choice(branch_index(args.default_random)?);
+    if let Some(format_options) = config.format_options() {
+        options = options.format_options(format_options);
+    }
+    for name in args.defines {
+        options = options.define(name);
+    }
+
+    print!("{}", expand(&file, options));
+    Ok(())
+}
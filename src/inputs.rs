@@ -0,0 +1,221 @@
+//! Find the scripts to check from command line arguments.
+
+use anyhow::{bail, Result};
+use rms_check::RMSFile;
+use std::collections::HashSet;
+use std::fs::read_dir;
+use std::path::{Path, PathBuf};
+
+/// A random map script to check.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Input {
+    /// An .rms file, or a ZR@ map packed into a zip file.
+    Script(PathBuf),
+    /// A folder containing the files for a ZR@ map.
+    UnpackedZipRms(PathBuf),
+}
+
+impl Input {
+    /// Get the path to the script or folder.
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Script(path) | Self::UnpackedZipRms(path) => path,
+        }
+    }
+
+    /// Read the script.
+    pub fn load(&self) -> std::io::Result<RMSFile<'static>> {
+        match self {
+            Self::Script(path) => RMSFile::from_path(path),
+            Self::UnpackedZipRms(path) => RMSFile::from_zip_rms_path_unpacked(path),
+        }
+    }
+}
+
+fn is_zip_rms_name(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with("ZR@"))
+}
+
+fn is_script_name(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("rms"))
+}
+
+/// Find all scripts in a directory and its subdirectories.
+///
+/// Symbolic links are followed, but every directory is only searched once, so links that point to
+/// a parent directory do not recurse forever.
+fn walk_directory(
+    dir: &Path,
+    visited: &mut HashSet<PathBuf>,
+    inputs: &mut Vec<Input>,
+) -> Result<()> {
+    if !visited.insert(dir.canonicalize()?) {
+        return Ok(());
+    }
+    let mut entries = read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            if is_zip_rms_name(&path) {
+                inputs.push(Input::UnpackedZipRms(path));
+            } else {
+                walk_directory(&path, visited, inputs)?;
+            }
+        } else if is_script_name(&path) {
+            inputs.push(Input::Script(path));
+        }
+    }
+    Ok(())
+}
+
+/// Add the scripts for a single path that exists on disk.
+fn add_path(path: PathBuf, visited: &mut HashSet<PathBuf>, inputs: &mut Vec<Input>) -> Result<()> {
+    if !path.is_dir() {
+        inputs.push(Input::Script(path));
+    } else if is_zip_rms_name(&path) {
+        inputs.push(Input::UnpackedZipRms(path));
+    } else {
+        walk_directory(&path, visited, inputs)?;
+    }
+    Ok(())
+}
+
+/// Expand paths, directories and glob patterns to a list of scripts.
+///
+/// Directories are searched recursively for .rms files. Directories whose name starts with `ZR@`
+/// are checked as a single unpacked ZR@ map.
+pub fn expand_inputs(paths: &[PathBuf]) -> Result<Vec<Input>> {
+    let mut inputs = vec![];
+    let mut visited = HashSet::new();
+    for path in paths {
+        let pattern = path.to_string_lossy();
+        let is_pattern = pattern.contains(['*', '?', '[']);
+        if path.exists() || !is_pattern {
+            add_path(path.clone(), &mut visited, &mut inputs)?;
+            continue;
+        }
+
+        let mut matches = glob::glob(&pattern)?.collect::<Result<Vec<_>, _>>()?;
+        if matches.is_empty() {
+            bail!("No files match `{}`", pattern);
+        }
+        matches.sort();
+        for path in matches {
+            add_path(path, &mut visited, &mut inputs)?;
+        }
+    }
+
+    // The same file may be listed multiple times, for example by overlapping globs.
+    let mut seen = HashSet::new();
+    inputs.retain(|input| seen.insert(input.clone()));
+    Ok(inputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    /// A directory of test files that is removed afterwards.
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str, files: &[&str]) -> Self {
+            let root =
+                std::env::temp_dir().join(format!("rms-check-{}-{}", name, std::process::id()));
+            let _ = remove_dir_all(&root);
+            for file in files {
+                let path = root.join(file);
+                create_dir_all(path.parent().unwrap()).unwrap();
+                write(path, "").unwrap();
+            }
+            Self(root)
+        }
+
+        fn path(&self, path: &str) -> PathBuf {
+            self.0.join(path)
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn directories() -> Result<()> {
+        let dir = TestDir::new(
+            "directories",
+            &[
+                "b.rms",
+                "a.RMS",
+                "notes.txt",
+                "sub/c.rms",
+                "ZR@map/map.rms",
+                "ZR@map/map.scx",
+            ],
+        );
+        assert_eq!(
+            expand_inputs(&[dir.path("")])?,
+            vec![
+                Input::UnpackedZipRms(dir.path("ZR@map")),
+                Input::Script(dir.path("a.RMS")),
+                Input::Script(dir.path("b.rms")),
+                Input::Script(dir.path("sub/c.rms")),
+            ]
+        );
+        // A ZR@ folder can be checked directly.
+        assert_eq!(
+            expand_inputs(&[dir.path("ZR@map")])?,
+            vec![Input::UnpackedZipRms(dir.path("ZR@map"))]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn globs() -> Result<()> {
+        let dir = TestDir::new("globs", &["a.rms", "b.rms", "c.txt", "sub/d.rms"]);
+        assert_eq!(
+            expand_inputs(&[dir.path("*.rms")])?,
+            vec![
+                Input::Script(dir.path("a.rms")),
+                Input::Script(dir.path("b.rms"))
+            ]
+        );
+        assert!(expand_inputs(&[dir.path("*.zip")]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn duplicates() -> Result<()> {
+        let dir = TestDir::new("duplicates", &["a.rms", "b.rms"]);
+        assert_eq!(
+            expand_inputs(&[dir.path("a.rms"), dir.path("*.rms"), dir.path("")])?,
+            vec![
+                Input::Script(dir.path("a.rms")),
+                Input::Script(dir.path("b.rms"))
+            ]
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_loop() -> Result<()> {
+        let dir = TestDir::new("symlink-loop", &["a.rms", "sub/b.rms"]);
+        std::os::unix::fs::symlink(dir.path(""), dir.path("sub/parent"))?;
+        assert_eq!(
+            expand_inputs(&[dir.path("")])?,
+            vec![
+                Input::Script(dir.path("a.rms")),
+                Input::Script(dir.path("sub/b.rms"))
+            ]
+        );
+        Ok(())
+    }
+}
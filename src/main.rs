@@ -6,15 +6,20 @@
 
 mod check;
 mod cli_reporter;
+mod expand;
+mod inputs;
 mod language_server;
+mod output_format;
 mod zip_rms;
 
 use crate::check::{cli_check, cli_fix, CheckArgs};
+use crate::expand::{cli_expand, ExpandArgs};
 use crate::language_server::cli_server;
+use crate::output_format::OutputFormat;
 use crate::zip_rms::{cli_pack, cli_unpack};
-use anyhow::Result;
+use anyhow::{Context, Result};
 use notify::{RecommendedWatcher, RecursiveMode, Watcher};
-use rms_check::{Compatibility, FormatOptions};
+use rms_check::{Compatibility, Config, DefinitionsMode};
 use std::io::{self, Read};
 use std::path::{Path, PathBuf};
 use std::sync::mpsc;
@@ -51,31 +56,83 @@ struct CliCompat {
 }
 
 impl CliCompat {
-    fn to_compatibility(&self) -> Compatibility {
+    /// Get the selected compatibility level, or None if no flag was given.
+    fn to_compatibility(&self) -> Option<Compatibility> {
         if self.definitive_edition {
-            Compatibility::DefinitiveEdition
+            Some(Compatibility::DefinitiveEdition)
         } else if self.wololo_kingdoms {
-            Compatibility::WololoKingdoms
+            Some(Compatibility::WololoKingdoms)
         } else if self.hd_edition {
-            Compatibility::HDEdition
+            Some(Compatibility::HDEdition)
         } else if self.userpatch14 {
-            Compatibility::UserPatch14
+            Some(Compatibility::UserPatch14)
         } else if self.userpatch15 {
-            Compatibility::UserPatch15
+            Some(Compatibility::UserPatch15)
         } else if self.aoc {
-            Compatibility::Conquerors
+            Some(Compatibility::Conquerors)
         } else {
-            Compatibility::All
+            None
+        }
+    }
+}
+
+// CLI flags for loading custom random_map.def files.
+#[derive(Debug, StructOpt)]
+struct CliDefinitions {
+    /// Load additional `#const` and `#define` names from a random_map.def file, for every
+    /// compatibility level. Can be used multiple times.
+    #[structopt(long = "definitions", parse(from_os_str), number_of_values = 1)]
+    definitions: Vec<PathBuf>,
+    /// Use the `--definitions` files in place of the builtin definitions, rather than in addition
+    /// to them.
+    #[structopt(long = "replace-definitions")]
+    replace_definitions: bool,
+}
+
+impl CliDefinitions {
+    fn to_definitions_mode(&self) -> DefinitionsMode {
+        if self.replace_definitions {
+            DefinitionsMode::Override
+        } else {
+            DefinitionsMode::Layer
         }
     }
 }
 
 #[derive(Debug, StructOpt)]
 struct CliCheck {
-    /// The file to check.
-    file: PathBuf,
+    /// The files to check. These can be .rms files, ZR@ maps, directories containing them, or
+    /// glob patterns.
+    #[structopt(required = true, parse(from_os_str))]
+    files: Vec<PathBuf>,
     #[structopt(flatten)]
     compat_flags: CliCompat,
+    #[structopt(flatten)]
+    definitions: CliDefinitions,
+    /// The output format: text, json, sarif or checkstyle.
+    #[structopt(long = "format", default_value = "text")]
+    format: OutputFormat,
+    /// Also check the scripts for every combination of the map sizes, player counts and game
+    /// modes they check for, and report problems that only occur for some of them. This checks
+    /// each script up to 256 times.
+    #[structopt(long = "sweep")]
+    sweep: bool,
+}
+
+impl CliCheck {
+    fn run(self) -> Result<()> {
+        cli_check(
+            &self.files,
+            CheckArgs {
+                compatibility: self.compat_flags.to_compatibility(),
+                definitions_mode: self.definitions.to_definitions_mode(),
+                definitions: self.definitions.definitions,
+                format: self.format,
+                sweep: self.sweep,
+                ..Default::default()
+            },
+        )
+    }
 }
 
 #[derive(Debug, StructOpt)]
@@ -106,15 +163,17 @@ enum CliCommand {
         file: PathBuf,
         #[structopt(flatten)]
         compat_flags: CliCompat,
+        #[structopt(flatten)]
+        definitions: CliDefinitions,
     },
     /// Format the given file.
     #[structopt(name = "format")]
     Format {
         /// The file to format. Use "-" to read from standard input.
         file: PathBuf,
-        /// Set the size in spaces of a single tab indentation.
-        #[structopt(long = "tab-size", default_value = "2")]
-        tab_size: u32,
+        /// Set the size in spaces of a single tab indentation [default: 2].
+        #[structopt(long = "tab-size")]
+        tab_size: Option<u32>,
         /// Whether to use spaces instead of tabs for indentation.
         #[structopt(long = "no-use-spaces")]
         no_use_spaces: bool,
@@ -122,6 +181,29 @@ enum CliCommand {
         #[structopt(long = "no-align-arguments")]
         no_align_arguments: bool,
     },
+    /// Print the script the game would interpret for a particular set of game settings, with
+    /// conditionals and random blocks expanded.
+    #[structopt(name = "expand")]
+    Expand {
+        /// The file to expand.
+        file: PathBuf,
+        #[structopt(flatten)]
+        compat_flags: CliCompat,
+        #[structopt(flatten)]
+        definitions: CliDefinitions,
+        /// Treat a `#define` as set, like `LARGE_MAP`, `4_PLAYER_GAME` or `UP_EXTENSION`. Can be
+        /// used multiple times.
+        #[structopt(long = "define", short = "D", number_of_values = 1)]
+        defines: Vec<String>,
+        /// The `percent_chance` branch to take in each `start_random` block, in order, like
+        /// `--random 2,1,3`. Branches are numbered starting at 1.
+        #[structopt(long = "random", use_delimiter = true)]
+        random: Vec<usize>,
+        /// The `percent_chance` branch to take in `start_random` blocks that are not listed in
+        /// `--random`.
+        #[structopt(long = "default-random", default_value = "1")]
+        default_random: usize,
+    },
     /// Syntax check and lint a random map script.
     #[structopt(name = "check")]
     Check(CliCheck),
@@ -136,11 +218,20 @@ enum CliCommand {
 pub struct Cli {
     #[structopt(subcommand)]
     command: Option<CliCommand>,
-    // Compatibility flags for implicit `check`, when not using any subcommand.
+    // Flags for implicit `check`, when not using any subcommand.
     #[structopt(flatten)]
     compat_flags: CliCompat,
-    /// The file to check, when not using any subcommand.
-    file: Option<String>,
+    #[structopt(flatten)]
+    definitions: CliDefinitions,
+    /// The output format when not using any subcommand: text, json, sarif or checkstyle.
+    #[structopt(long = "format", default_value = "text")]
+    format: OutputFormat,
+    /// Check every combination of lobby settings, when not using any subcommand.
+    #[structopt(long = "sweep")]
+    sweep: bool,
+    /// The files to check, when not using any subcommand.
+    #[structopt(parse(from_os_str))]
+    files: Vec<PathBuf>,
 }
 
 fn read_input(path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
@@ -173,8 +264,22 @@ fn cli_watch(indir: impl AsRef<Path>, callback: impl Fn() -> Result<()>) -> Resu
     Ok(())
 }
 
+/// Load extra token database files listed in the `RMS_CHECK_TOKENS` environment variable.
+fn load_tokens() -> Result<()> {
+    if let Some(paths) = std::env::var_os("RMS_CHECK_TOKENS") {
+        for path in std::env::split_paths(&paths) {
+            let source = std::fs::read_to_string(&path)
+                .with_context(|| format!("could not read {}", path.display()))?;
+            rms_check::extend_tokens(&source)
+                .with_context(|| format!("invalid token database {}", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
 fn main() -> Result<()> {
     let args = Cli::from_args();
+    load_tokens()?;
 
     match args.command {
         Some(CliCommand::Unpack { outdir, input }) => cli_unpack(input, outdir),
@@ -197,43 +302,83 @@ fn main() -> Result<()> {
             dry_run,
             file,
             compat_flags,
-        }) => cli_fix(CheckArgs {
-            compatibility: compat_flags.to_compatibility(),
+            definitions,
+        }) => cli_fix(
             file,
-            dry_run,
-        }),
+            CheckArgs {
+                compatibility: compat_flags.to_compatibility(),
+                definitions_mode: definitions.to_definitions_mode(),
+                definitions: definitions.definitions,
+                dry_run,
+                ..Default::default()
+            },
+        ),
         Some(CliCommand::Format {
             file,
             tab_size,
             no_use_spaces,
             no_align_arguments,
         }) => {
-            let options = FormatOptions::default()
-                .tab_size(tab_size)
-                .use_spaces(!no_use_spaces)
-                .align_arguments(!no_align_arguments);
+            // Look for a configuration file next to the input, or in the working directory when
+            // reading from standard input.
+            let config = if file == Path::new("-") {
+                Config::find(std::env::current_dir()?)?
+            } else {
+                Config::find(&file)?
+            };
+            let mut options = config
+                .and_then(|config| config.format_options())
+                .unwrap_or_default();
+            if let Some(tab_size) = tab_size {
+                options = options.tab_size(tab_size);
+            }
+            if no_use_spaces {
+                options = options.use_spaces(false);
+            }
+            if no_align_arguments {
+                options = options.align_arguments(false);
+            }
 
             let bytes = read_input(file)?;
             let string = std::str::from_utf8(&bytes)?;
             println!("{}", rms_check::format(string, options));
             Ok(())
         }
+        Some(CliCommand::Expand {
+            file,
+            compat_flags,
+            definitions,
+            defines,
+            random,
+            default_random,
+        }) => cli_expand(
+            file,
+            ExpandArgs {
+                check: CheckArgs {
+                    compatibility: compat_flags.to_compatibility(),
+                    definitions_mode: definitions.to_definitions_mode(),
+                    definitions: definitions.definitions,
+                    ..Default::default()
+                },
+                defines,
+                random,
+                default_random,
+            },
+        ),
         Some(CliCommand::Server) => {
             cli_server();
             unreachable!();
         }
-        Some(CliCommand::Check(args)) => cli_check(CheckArgs {
-            compatibility: args.compat_flags.to_compatibility(),
-            file: args.file,
-            ..Default::default()
-        }),
-        None => {
-            let args = CliCheck::from_args();
-            cli_check(CheckArgs {
-                compatibility: args.compat_flags.to_compatibility(),
-                file: args.file,
-                ..Default::default()
-            })
+        Some(CliCommand::Check(args)) => args.run(),
+        // Let `check` report the missing files argument.
+        None if args.files.is_empty() => CliCheck::from_args().run(),
+        None => CliCheck {
+            files: args.files,
+            compat_flags: args.compat_flags,
+            definitions: args.definitions,
+            format: args.format,
+            sweep: args.sweep,
         }
+        .run(),
     }
 }
@@ -0,0 +1,440 @@
+//! Machine-readable output formats for diagnostics.
+
+use rms_check::{ByteIndex, Diagnostic, Fix, RMSFile, Severity, SourceLocation};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+/// How to print diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Human-readable output for the terminal.
+    #[default]
+    Text,
+    /// A JSON array of diagnostics.
+    Json,
+    /// A SARIF 2.1.0 log, for code scanning tools.
+    Sarif,
+    /// A Checkstyle XML report.
+    Checkstyle,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "sarif" => Ok(Self::Sarif),
+            "checkstyle" => Ok(Self::Checkstyle),
+            _ => Err(format!(
+                "unknown format `{}`, expected one of: text, json, sarif, checkstyle",
+                name
+            )),
+        }
+    }
+}
+
+/// The diagnostics for a checked script.
+pub struct FileReport {
+    pub file: RMSFile<'static>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// A 1-based line/column position. Columns count characters, not bytes.
+#[derive(Debug, Clone, Copy)]
+struct Position {
+    line: u32,
+    column: u32,
+}
+
+fn position(file: &RMSFile<'_>, location: SourceLocation, index: ByteIndex) -> Position {
+    let before = &file.source(location.file())[..usize::from(index)];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    Position {
+        line: before.matches('\n').count() as u32 + 1,
+        column: before[line_start..].chars().count() as u32 + 1,
+    }
+}
+
+/// The name of a file, using forward slashes so it can be used as a relative URI.
+fn file_name(file: &RMSFile<'_>, location: SourceLocation) -> String {
+    file.name(location.file()).replace('\\', "/")
+}
+
+const fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::ParseError => "parse-error",
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Hint => "hint",
+    }
+}
+
+fn json_range(file: &RMSFile<'_>, location: SourceLocation) -> Value {
+    let start = position(file, location, location.start());
+    let end = position(file, location, location.end());
+    json!({
+        "start": { "line": start.line, "column": start.column },
+        "end": { "line": end.line, "column": end.column },
+    })
+}
+
+fn json_fix(file: &RMSFile<'_>, fix: &Fix) -> Value {
+    json!({
+        "message": fix.message(),
+        "file": file_name(file, fix.location()),
+        "range": json_range(file, fix.location()),
+        "replacement": fix.replacement(),
+    })
+}
+
+/// Format diagnostics as a JSON array.
+pub fn to_json(reports: &[FileReport]) -> String {
+    let diagnostics: Vec<Value> = reports
+        .iter()
+        .flat_map(|report| {
+            let file = &report.file;
+            report.diagnostics.iter().map(move |diagnostic| {
+                json!({
+                    "severity": severity_name(diagnostic.severity()),
+                    "code": diagnostic.code(),
+                    "message": diagnostic.message(),
+                    "file": file_name(file, diagnostic.location()),
+                    "range": json_range(file, diagnostic.location()),
+                    "labels": diagnostic.labels().map(|label| json!({
+                        "message": label.message(),
+                        "file": file_name(file, label.location()),
+                        "range": json_range(file, label.location()),
+                    })).collect::<Vec<_>>(),
+                    "fixes": diagnostic.fixes().map(|fix| json_fix(file, fix)).collect::<Vec<_>>(),
+                    "suggestions": diagnostic.suggestions().map(|fix| json_fix(file, fix)).collect::<Vec<_>>(),
+                })
+            })
+        })
+        .collect();
+    serde_json::to_string_pretty(&diagnostics).unwrap()
+}
+
+/// Get the URI for a file: relative paths are kept relative, absolute paths become file:// URIs.
+fn sarif_uri(file: &RMSFile<'_>, location: SourceLocation) -> String {
+    let name = file_name(file, location);
+    let mut uri = if !Path::new(file.name(location.file())).is_absolute() {
+        String::new()
+    } else if name.starts_with('/') {
+        "file://".to_string()
+    } else {
+        // Windows paths, like C:/maps/map.rms.
+        "file:///".to_string()
+    };
+    for byte in name.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                uri.push(byte as char)
+            }
+            _ => write!(uri, "%{:02X}", byte).unwrap(),
+        }
+    }
+    uri
+}
+
+fn sarif_location(file: &RMSFile<'_>, location: SourceLocation) -> Value {
+    let start = position(file, location, location.start());
+    let end = position(file, location, location.end());
+    json!({
+        "artifactLocation": { "uri": sarif_uri(file, location) },
+        "region": {
+            "startLine": start.line,
+            "startColumn": start.column,
+            "endLine": end.line,
+            "endColumn": end.column,
+        },
+    })
+}
+
+fn sarif_fix(file: &RMSFile<'_>, fix: &Fix) -> Option<Value> {
+    let replacement = fix.replacement()?;
+    let location = sarif_location(file, fix.location());
+    Some(json!({
+        "description": { "text": fix.message() },
+        "artifactChanges": [{
+            "artifactLocation": location["artifactLocation"],
+            "replacements": [{
+                "deletedRegion": location["region"],
+                "insertedContent": { "text": replacement },
+            }],
+        }],
+    }))
+}
+
+/// Format diagnostics as a SARIF 2.1.0 log.
+pub fn to_sarif(reports: &[FileReport]) -> String {
+    let mut rules = BTreeMap::new();
+    let mut results = vec![];
+    for report in reports {
+        let file = &report.file;
+        for diagnostic in &report.diagnostics {
+            let code = diagnostic.code().unwrap_or("rms-check");
+            rules.insert(code, json!({ "id": code }));
+
+            let level = match diagnostic.severity() {
+                Severity::ParseError | Severity::Error => "error",
+                Severity::Warning => "warning",
+                Severity::Hint => "note",
+            };
+            let related_locations: Vec<Value> = diagnostic
+                .labels()
+                .enumerate()
+                .map(|(index, label)| {
+                    json!({
+                        "id": index,
+                        "physicalLocation": sarif_location(file, label.location()),
+                        "message": { "text": label.message() },
+                    })
+                })
+                .collect();
+            let fixes: Vec<Value> = diagnostic
+                .fixes()
+                .chain(diagnostic.suggestions())
+                .filter_map(|fix| sarif_fix(file, fix))
+                .collect();
+
+            results.push(json!({
+                "ruleId": code,
+                "level": level,
+                "message": { "text": diagnostic.message() },
+                "locations": [{
+                    "physicalLocation": sarif_location(file, diagnostic.location()),
+                }],
+                "relatedLocations": related_locations,
+                "fixes": fixes,
+            }));
+        }
+    }
+
+    let log = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "rms-check",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                    "rules": rules.into_values().collect::<Vec<_>>(),
+                },
+            },
+            "columnKind": "unicodeCodePoints",
+            "results": results,
+        }],
+    });
+    serde_json::to_string_pretty(&log).unwrap()
+}
+
+/// Escape text for use in an XML attribute value.
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Whitespace in attribute values is turned into spaces unless it is escaped.
+            '\t' | '\n' | '\r' => write!(escaped, "&#{};", c as u32).unwrap(),
+            // Other control characters are not allowed in XML 1.0 documents at all.
+            '\u{0}'..='\u{1f}' => escaped.push(char::REPLACEMENT_CHARACTER),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Format diagnostics as a Checkstyle XML report.
+pub fn to_checkstyle(reports: &[FileReport]) -> String {
+    let mut xml = String::new();
+    writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(xml, r#"<checkstyle version="4.3">"#).unwrap();
+    for report in reports {
+        let file = &report.file;
+        // Diagnostics are grouped by the file they occur in, which may not be the main script for
+        // ZR@ maps.
+        let mut by_file: BTreeMap<String, Vec<&Diagnostic>> = BTreeMap::new();
+        by_file.insert(file.name(file.file_id()).replace('\\', "/"), vec![]);
+        for diagnostic in &report.diagnostics {
+            by_file
+                .entry(file_name(file, diagnostic.location()))
+                .or_default()
+                .push(diagnostic);
+        }
+        for (name, diagnostics) in by_file {
+            writeln!(xml, r#"  <file name="{}">"#, escape_xml(&name)).unwrap();
+            for diagnostic in diagnostics {
+                let start = position(file, diagnostic.location(), diagnostic.location().start());
+                let severity = match diagnostic.severity() {
+                    Severity::ParseError | Severity::Error => "error",
+                    Severity::Warning => "warning",
+                    Severity::Hint => "info",
+                };
+                writeln!(
+                    xml,
+                    r#"    <error line="{}" column="{}" severity="{}" message="{}" source="rms-check.{}"/>"#,
+                    start.line,
+                    start.column,
+                    severity,
+                    escape_xml(diagnostic.message()),
+                    escape_xml(diagnostic.code().unwrap_or("rms-check")),
+                )
+                .unwrap();
+            }
+            writeln!(xml, "  </file>").unwrap();
+        }
+    }
+    writeln!(xml, "</checkstyle>").unwrap();
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rms_check::Label;
+
+    /// A report with one diagnostic that uses every field.
+    fn report() -> Vec<FileReport> {
+        let file = RMSFile::from_string(
+            "maps\\Test Map.rms",
+            "<PLAYER_SETUP>\r\nrandom_placement\r\ncreate_lnd {}\r\n",
+        );
+        let location = |start: usize, end: usize| {
+            SourceLocation::new(file.file_id(), start.into()..end.into())
+        };
+        let diagnostic =
+            Diagnostic::warning(location(34, 44), "Unknown command `create_lnd` <here>")
+                .with_code("unknown-command")
+                .add_label(Label::new(location(0, 14), "In this section"))
+                .suggest(
+                    Fix::new(location(34, 44), "Did you mean `create_land`?")
+                        .replace("create_land"),
+                );
+        vec![FileReport {
+            diagnostics: vec![diagnostic],
+            file,
+        }]
+    }
+
+    #[test]
+    fn json() {
+        let output: Value = serde_json::from_str(&to_json(&report())).unwrap();
+        assert_eq!(
+            output,
+            json!([{
+                "severity": "warning",
+                "code": "unknown-command",
+                "message": "Unknown command `create_lnd` <here>",
+                "file": "maps/Test Map.rms",
+                "range": {
+                    "start": { "line": 3, "column": 1 },
+                    "end": { "line": 3, "column": 11 },
+                },
+                "labels": [{
+                    "message": "In this section",
+                    "file": "maps/Test Map.rms",
+                    "range": {
+                        "start": { "line": 1, "column": 1 },
+                        "end": { "line": 1, "column": 15 },
+                    },
+                }],
+                "fixes": [],
+                "suggestions": [{
+                    "message": "Did you mean `create_land`?",
+                    "file": "maps/Test Map.rms",
+                    "range": {
+                        "start": { "line": 3, "column": 1 },
+                        "end": { "line": 3, "column": 11 },
+                    },
+                    "replacement": "create_land",
+                }],
+            }])
+        );
+    }
+
+    #[test]
+    fn sarif() {
+        let output: Value = serde_json::from_str(&to_sarif(&report())).unwrap();
+        let region = json!({ "startLine": 3, "startColumn": 1, "endLine": 3, "endColumn": 11 });
+        assert_eq!(
+            output,
+            json!({
+                "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+                "version": "2.1.0",
+                "runs": [{
+                    "tool": {
+                        "driver": {
+                            "name": "rms-check",
+                            "version": env!("CARGO_PKG_VERSION"),
+                            "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                            "rules": [{ "id": "unknown-command" }],
+                        },
+                    },
+                    "columnKind": "unicodeCodePoints",
+                    "results": [{
+                        "ruleId": "unknown-command",
+                        "level": "warning",
+                        "message": { "text": "Unknown command `create_lnd` <here>" },
+                        "locations": [{
+                            "physicalLocation": {
+                                "artifactLocation": { "uri": "maps/Test%20Map.rms" },
+                                "region": region,
+                            },
+                        }],
+                        "relatedLocations": [{
+                            "id": 0,
+                            "physicalLocation": {
+                                "artifactLocation": { "uri": "maps/Test%20Map.rms" },
+                                "region": { "startLine": 1, "startColumn": 1, "endLine": 1, "endColumn": 15 },
+                            },
+                            "message": { "text": "In this section" },
+                        }],
+                        "fixes": [{
+                            "description": { "text": "Did you mean `create_land`?" },
+                            "artifactChanges": [{
+                                "artifactLocation": { "uri": "maps/Test%20Map.rms" },
+                                "replacements": [{
+                                    "deletedRegion": region,
+                                    "insertedContent": { "text": "create_land" },
+                                }],
+                            }],
+                        }],
+                    }],
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn checkstyle() {
+        assert_eq!(
+            to_checkstyle(&report()),
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                "<checkstyle version=\"4.3\">\n",
+                "  <file name=\"maps/Test Map.rms\">\n",
+                "    <error line=\"3\" column=\"1\" severity=\"warning\" message=\"Unknown command `create_lnd` &lt;here&gt;\" source=\"rms-check.unknown-command\"/>\n",
+                "  </file>\n",
+                "</checkstyle>\n",
+            )
+        );
+    }
+
+    #[test]
+    fn xml_control_characters() {
+        assert_eq!(
+            escape_xml("a\tb\r\nc\u{0}d\u{1b}e"),
+            "a&#9;b&#13;&#10;c\u{fffd}d\u{fffd}e"
+        );
+    }
+}